@@ -4,6 +4,7 @@ use crate::{
     vec3::Point3,
 };
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone)]
 pub struct AABB {
     pub minimum: Point3,
@@ -11,17 +12,11 @@ pub struct AABB {
 }
 
 impl AABB {
-    pub fn empty() -> Self {
-        Self {
-            minimum: Point3::from(0.0),
-            maximum: Point3::from(0.0),
-        }
-    }
     pub fn new(minimum: Point3, maximum: Point3) -> Self {
         Self { minimum, maximum }
     }
 
-    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+    pub fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
         for (minimum, maximum, origin, direction) in [
             (
                 self.minimum.x,
@@ -47,13 +42,11 @@ impl AABB {
             let mut t1 = (maximum - origin) * inv_d;
 
             if inv_d < 0.0 {
-                let temp = t0;
-                t0 = t1;
-                t1 = temp;
+                std::mem::swap(&mut t0, &mut t1);
             }
 
-            let t_min = if t0 > t_min { t0 } else { t_min };
-            let t_max = if t1 < t_max { t1 } else { t_max };
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
 
             if t_max <= t_min {
                 return false;
@@ -62,6 +55,15 @@ impl AABB {
         true
     }
 
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
     pub fn surrounding_box(box0: &AABB, box1: &AABB) -> Self {
         let minimum = Point3::new(
             min(box0.minimum.x, box1.minimum.x),
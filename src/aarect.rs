@@ -1,9 +1,12 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
     aabb::AABB,
-    hittable::{HitRecord, Hittable},
+    hittable::{solid_angle_pdf, HitRecord, Hittable},
+    hittable_list::HittableList,
     materials::Material,
+    ray::Ray,
+    utils::{random_2d, INFINITY},
     vec3::{Point3, Vec3},
 };
 
@@ -30,12 +33,7 @@ impl<M: Material> XYRect<M> {
 }
 
 impl<M: Material> Hittable for XYRect<M> {
-    fn hit(
-        &self,
-        ray: &crate::ray::Ray,
-        t_min: f32,
-        t_max: f32,
-    ) -> Option<crate::hittable::HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = (self.k - ray.origin.z) / ray.direction.z;
 
         if t < t_min || t > t_max {
@@ -56,6 +54,7 @@ impl<M: Material> Hittable for XYRect<M> {
                 (y - self.y0) / (self.y1 - self.y0),
             ),
             mat: &self.material,
+            object_id: 0,
 
             // These two are set with set_face_normal
             normal: Vec3::from(0.0),
@@ -67,12 +66,29 @@ impl<M: Material> Hittable for XYRect<M> {
         Some(rec)
     }
 
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<crate::aabb::AABB> {
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         Some(AABB::new(
             Point3::new(self.x0, self.y0, self.k - 0.0001),
             Point3::new(self.x1, self.y1, self.k + 0.0001),
         ))
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, INFINITY) {
+            Some(rec) => {
+                let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+                solid_angle_pdf(direction, rec.t, &Vec3::new(0.0, 0.0, 1.0), area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let (r1, r2) = random_2d();
+        let x = self.x0 + r1 * (self.x1 - self.x0);
+        let y = self.y0 + r2 * (self.y1 - self.y0);
+        Point3::new(x, y, self.k) - *origin
+    }
 }
 
 pub struct XZRect<M: Material> {
@@ -98,12 +114,7 @@ impl<M: Material> XZRect<M> {
 }
 
 impl<M: Material> Hittable for XZRect<M> {
-    fn hit(
-        &self,
-        ray: &crate::ray::Ray,
-        t_min: f32,
-        t_max: f32,
-    ) -> Option<crate::hittable::HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = (self.k - ray.origin.y) / ray.direction.y;
 
         if t < t_min || t > t_max {
@@ -124,6 +135,7 @@ impl<M: Material> Hittable for XZRect<M> {
                 (z - self.z0) / (self.z1 - self.z0),
             ),
             mat: &self.material,
+            object_id: 0,
 
             // These two are set with set_face_normal
             normal: Vec3::from(0.0),
@@ -135,12 +147,29 @@ impl<M: Material> Hittable for XZRect<M> {
         Some(rec)
     }
 
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<crate::aabb::AABB> {
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         Some(AABB::new(
             Point3::new(self.x0, self.k - 0.0001, self.z0),
             Point3::new(self.x1, self.k + 0.0001, self.z1),
         ))
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, INFINITY) {
+            Some(rec) => {
+                let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+                solid_angle_pdf(direction, rec.t, &Vec3::new(0.0, 1.0, 0.0), area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let (r1, r2) = random_2d();
+        let x = self.x0 + r1 * (self.x1 - self.x0);
+        let z = self.z0 + r2 * (self.z1 - self.z0);
+        Point3::new(x, self.k, z) - *origin
+    }
 }
 
 pub struct YZRect<M: Material> {
@@ -166,12 +195,7 @@ impl<M: Material> YZRect<M> {
 }
 
 impl<M: Material> Hittable for YZRect<M> {
-    fn hit(
-        &self,
-        ray: &crate::ray::Ray,
-        t_min: f32,
-        t_max: f32,
-    ) -> Option<crate::hittable::HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = (self.k - ray.origin.x) / ray.direction.x;
 
         if t < t_min || t > t_max {
@@ -192,6 +216,7 @@ impl<M: Material> Hittable for YZRect<M> {
                 (z - self.z0) / (self.z1 - self.z0),
             ),
             mat: &self.material,
+            object_id: 0,
 
             // These two are set with set_face_normal
             normal: Vec3::from(0.0),
@@ -203,10 +228,120 @@ impl<M: Material> Hittable for YZRect<M> {
         Some(rec)
     }
 
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<crate::aabb::AABB> {
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         Some(AABB::new(
             Point3::new(self.k - 0.0001, self.y0, self.z0),
             Point3::new(self.k + 0.0001, self.y1, self.z1),
         ))
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        match self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, INFINITY) {
+            Some(rec) => {
+                let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+                solid_angle_pdf(direction, rec.t, &Vec3::new(1.0, 0.0, 0.0), area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let (r1, r2) = random_2d();
+        let y = self.y0 + r1 * (self.y1 - self.y0);
+        let z = self.z0 + r2 * (self.z1 - self.z0);
+        Point3::new(self.k, y, z) - *origin
+    }
+}
+
+/// An axis-aligned box between two opposite corners, made up of six rectangles.
+pub struct Cuboid {
+    minimum: Point3,
+    maximum: Point3,
+    sides: HittableList,
+}
+
+impl Cuboid {
+    pub fn new<M: Material + 'static>(p0: Point3, p1: Point3, material: M) -> Self {
+        let minimum = Point3::new(
+            f32::min(p0.x, p1.x),
+            f32::min(p0.y, p1.y),
+            f32::min(p0.z, p1.z),
+        );
+        let maximum = Point3::new(
+            f32::max(p0.x, p1.x),
+            f32::max(p0.y, p1.y),
+            f32::max(p0.z, p1.z),
+        );
+        let (p0, p1) = (minimum, maximum);
+
+        // All sides share the one material.
+        let material = Arc::new(material);
+        let mut sides = HittableList::new();
+
+        sides.add(Arc::new(XYRect::new(
+            p0.x,
+            p1.x,
+            p0.y,
+            p1.y,
+            p1.z,
+            material.clone(),
+        )));
+        sides.add(Arc::new(XYRect::new(
+            p0.x,
+            p1.x,
+            p0.y,
+            p1.y,
+            p0.z,
+            material.clone(),
+        )));
+
+        sides.add(Arc::new(XZRect::new(
+            p0.x,
+            p1.x,
+            p0.z,
+            p1.z,
+            p1.y,
+            material.clone(),
+        )));
+        sides.add(Arc::new(XZRect::new(
+            p0.x,
+            p1.x,
+            p0.z,
+            p1.z,
+            p0.y,
+            material.clone(),
+        )));
+
+        sides.add(Arc::new(YZRect::new(
+            p0.y,
+            p1.y,
+            p0.z,
+            p1.z,
+            p1.x,
+            material.clone(),
+        )));
+        sides.add(Arc::new(YZRect::new(
+            p0.y, p1.y, p0.z, p1.z, p0.x, material,
+        )));
+
+        Self {
+            minimum,
+            maximum,
+            sides,
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.sides.hit(ray, t_min, t_max)
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.sides.hit_any(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(AABB::new(self.minimum, self.maximum))
+    }
 }
@@ -0,0 +1,159 @@
+/// Pixels darker than this count as this bright when estimating their error, otherwise the
+/// slightest noise in the shadows would keep them from ever converging.
+const MIN_LUMINANCE: f32 = 0.01;
+
+/// Colors of the sample count heatmap, from few to many samples (roughly matplotlib's inferno).
+const HEATMAP: [[f32; 3]; 5] = [
+    [0.0, 0.0, 4.0],
+    [87.0, 16.0, 110.0],
+    [188.0, 55.0, 84.0],
+    [249.0, 142.0, 9.0],
+    [252.0, 255.0, 164.0],
+];
+
+/// Spends samples where the image is still noisy. Every pixel gets at least `min_samples`, after
+/// that passes only add samples to pixels whose estimated error (see `error`) is above `threshold`,
+/// until they reach `max_samples`.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveSampling {
+    pub threshold: f32,
+    pub min_samples: u32,
+    pub max_samples: u32,
+}
+
+impl AdaptiveSampling {
+    /// Whether a pixel with `count` samples still needs more, `error` being the error of its
+    /// neighbourhood.
+    pub fn needs_samples(&self, count: u32, error: f32) -> bool {
+        count < self.min_samples || (count < self.max_samples && error > self.threshold)
+    }
+}
+
+/// The standard error of the mean luminance of a pixel, divided by the square root of that mean.
+/// That roughly follows how visible the noise is once the image is gamma encoded: the same amount
+/// of noise stands out more in dark areas than in bright ones, but less than relative to their
+/// brightness. `luminance` and `luminance_squares` are the sums of the luminances and squared
+/// luminances of the pixel's `count` samples.
+pub fn error(luminance: f32, luminance_squares: f32, count: u32) -> f32 {
+    if count < 2 {
+        return f32::INFINITY;
+    }
+
+    let n = count as f32;
+    let mean = luminance / n;
+    // Unbiased sample variance, which can come out slightly negative due to rounding.
+    let variance = f32::max(luminance_squares / n - mean * mean, 0.0) * n / (n - 1.0);
+
+    f32::sqrt(variance / n) / f32::sqrt(f32::max(mean, MIN_LUMINANCE))
+}
+
+/// The color of the heatmap for `t` between 0 and 1, in 8-bit sRGB.
+pub fn heatmap_color(t: f32) -> [u8; 3] {
+    let position = t.clamp(0.0, 1.0) * (HEATMAP.len() - 1) as f32;
+    let i = usize::min(position as usize, HEATMAP.len() - 2);
+    let f = position - i as f32;
+
+    let mut color = [0; 3];
+    for (c, (a, b)) in color
+        .iter_mut()
+        .zip(HEATMAP[i].iter().zip(HEATMAP[i + 1].iter()))
+    {
+        *c = (a + f * (b - a) + 0.5) as u8;
+    }
+
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output_buffer::{OutputBuffer, PixelSums},
+        utils::random,
+    };
+
+    /// Sums up `count` samples of a pixel whose samples are either black or `2 * mean`.
+    fn noisy_samples(mean: f32, count: u32) -> PixelSums {
+        let mut samples = PixelSums {
+            weight: count as f32,
+            count,
+            ..Default::default()
+        };
+        for _ in 0..count {
+            let luminance = if random() < 0.5 { 2.0 * mean } else { 0.0 };
+            samples.luminance += luminance;
+            samples.luminance_squares += luminance * luminance;
+        }
+        samples
+    }
+
+    fn sample_error(samples: &PixelSums) -> f32 {
+        error(samples.luminance, samples.luminance_squares, samples.count)
+    }
+
+    #[test]
+    fn error_shrinks_with_the_square_root_of_the_samples() {
+        let few = sample_error(&noisy_samples(0.5, 1000));
+        let many = sample_error(&noisy_samples(0.5, 16000));
+        assert!((few / many - 4.0).abs() < 0.4, "{} {}", few, many);
+
+        // A single sample says nothing about the noise, and without any noise there's no error.
+        assert_eq!(sample_error(&noisy_samples(0.5, 1)), f32::INFINITY);
+        let flat = PixelSums {
+            luminance: 8.0,
+            luminance_squares: 4.0,
+            count: 16,
+            ..Default::default()
+        };
+        assert!(sample_error(&flat) < 1e-3);
+    }
+
+    #[test]
+    fn noisy_pixels_keep_getting_samples_until_they_converge() {
+        let adaptive = AdaptiveSampling {
+            threshold: 0.02,
+            min_samples: 8,
+            max_samples: 256,
+        };
+        // A flat left half and a noisy right half, far enough apart not to be neighbours.
+        let mut buffer = OutputBuffer::new(8, 1, 3).with_adaptive_sampling(adaptive);
+
+        let mut passes = 0;
+        while buffer.unconverged_pixels() > 0 {
+            for x in 0..8 {
+                let count = buffer.pass_samples(x, 0, 8);
+                let samples = if x < 4 {
+                    PixelSums {
+                        luminance: count as f32 * 0.5,
+                        luminance_squares: count as f32 * 0.25,
+                        weight: count as f32,
+                        count,
+                        ..Default::default()
+                    }
+                } else {
+                    noisy_samples(0.5, count)
+                };
+                buffer.add_samples(x, 0, &samples);
+            }
+            passes += 1;
+            assert!(passes <= 32);
+        }
+
+        let counts: Vec<u32> = buffer
+            .accumulation()
+            .iter()
+            .map(|sums| sums.count)
+            .collect();
+        assert!(counts[..3].iter().all(|&count| count == 8), "{:?}", counts);
+        assert!(counts[5..].iter().all(|&count| count > 64), "{:?}", counts);
+        assert!(counts.iter().all(|&count| count <= 256), "{:?}", counts);
+    }
+
+    #[test]
+    fn heatmap_goes_from_dark_to_bright() {
+        assert_eq!(heatmap_color(0.0), [0, 0, 4]);
+        assert_eq!(heatmap_color(1.0), [252, 255, 164]);
+        assert_eq!(heatmap_color(2.0), heatmap_color(1.0));
+        assert_eq!(heatmap_color(0.5), [188, 55, 84]);
+    }
+}
@@ -0,0 +1,253 @@
+use clap::ValueEnum;
+
+use crate::{
+    hittable::HitRecord,
+    ray::Ray,
+    vec3::{Color, Point3, Vec3},
+};
+
+/// Arbitrary output variables: extra images with what the camera rays hit first, for compositing
+/// and denoising. Pixels where nothing got hit are zero in all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Aov {
+    /// Distance from the camera.
+    Depth,
+    /// World space shading normal, facing the camera.
+    Normal,
+    /// Base color of the material.
+    Albedo,
+    /// World space position.
+    Position,
+    /// Texture coordinates.
+    Uv,
+    /// Which of the scene's objects got hit, counting from 1.
+    #[value(name = "object_id", alias = "object-id")]
+    ObjectId,
+    /// Which material got hit, counting from 1 in the order the scene gave them out.
+    #[value(name = "material_id", alias = "material-id")]
+    MaterialId,
+}
+
+impl Aov {
+    /// Name of the layer in multilayer EXR files, and the suffix of separate images. The same as
+    /// the name it's selected with on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+        }
+    }
+
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::Uv => &["U", "V"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+        }
+    }
+}
+
+/// Everything the AOVs need to know about the first hit of a single camera ray.
+pub struct FirstHit {
+    depth: f32,
+    normal: Vec3,
+    albedo: Color,
+    position: Point3,
+    uv: (f32, f32),
+    object_id: u32,
+    material_id: u32,
+}
+
+impl FirstHit {
+    pub fn new(ray: &Ray, rec: &HitRecord) -> Self {
+        Self {
+            depth: rec.t * ray.direction.length(),
+            normal: rec.normal,
+            albedo: rec.mat.albedo(rec),
+            position: rec.p,
+            uv: rec.uv,
+            object_id: rec.object_id,
+            material_id: rec.mat.id(),
+        }
+    }
+}
+
+/// The first hits of all the samples of a pixel. Everything gets averaged over the samples that
+/// hit something, apart from the IDs, which come from the first one.
+#[derive(Clone, Copy, Default)]
+pub struct AovPixel {
+    hits: u32,
+    depth: f32,
+    normal: Vec3,
+    albedo: Color,
+    position: Point3,
+    uv: (f32, f32),
+    object_id: u32,
+    material_id: u32,
+}
+
+impl AovPixel {
+    pub fn add(&mut self, hit: &FirstHit) {
+        if self.hits == 0 {
+            self.object_id = hit.object_id;
+            self.material_id = hit.material_id;
+        }
+
+        self.hits += 1;
+        self.depth += hit.depth;
+        self.normal += hit.normal;
+        self.albedo += hit.albedo;
+        self.position += hit.position;
+        self.uv.0 += hit.uv.0;
+        self.uv.1 += hit.uv.1;
+    }
+
+    /// Adds the samples of `other`, which were taken after the ones already in here.
+    pub fn merge(&mut self, other: &AovPixel) {
+        if self.hits == 0 {
+            self.object_id = other.object_id;
+            self.material_id = other.material_id;
+        }
+
+        self.hits += other.hits;
+        self.depth += other.depth;
+        self.normal += other.normal;
+        self.albedo += other.albedo;
+        self.position += other.position;
+        self.uv.0 += other.uv.0;
+        self.uv.1 += other.uv.1;
+    }
+
+    /// The average albedo, black if nothing got hit.
+    pub fn albedo(&self) -> Color {
+        if self.hits == 0 {
+            return Color::from(0.0);
+        }
+
+        self.albedo / self.hits as f32
+    }
+
+    /// The average normal, zero if nothing got hit.
+    pub fn normal(&self) -> Vec3 {
+        if self.normal.near_zero() {
+            return Vec3::from(0.0);
+        }
+
+        Vec3::unit_vector(self.normal)
+    }
+
+    /// The values of `aov`, one per channel.
+    pub fn values(&self, aov: Aov) -> Vec<f32> {
+        if self.hits == 0 {
+            return vec![0.0; aov.channels().len()];
+        }
+
+        let hits = self.hits as f32;
+        match aov {
+            Aov::Depth => vec![self.depth / hits],
+            Aov::Normal => {
+                let normal = self.normal();
+                vec![normal.x, normal.y, normal.z]
+            }
+            Aov::Albedo => {
+                let albedo = self.albedo();
+                vec![albedo.x, albedo.y, albedo.z]
+            }
+            Aov::Position => {
+                let position = self.position / hits;
+                vec![position.x, position.y, position.z]
+            }
+            Aov::Uv => vec![self.uv.0 / hits, self.uv.1 / hits],
+            Aov::ObjectId => vec![self.object_id as f32],
+            Aov::MaterialId => vec![self.material_id as f32],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        hittable::Hittable,
+        hittable_list::HittableList,
+        materials::{Lambertian, MaterialIds, Metal},
+        objects::Sphere,
+    };
+
+    /// Three spheres in a row along x, the first two sharing a material.
+    fn spheres() -> HittableList {
+        let mut ids = MaterialIds::new();
+        let shared = Arc::new(ids.assign(Lambertian::new(Color::new(0.2, 0.4, 0.6))));
+        let metal = ids.assign(Metal::new(Color::from(0.9), 0.0));
+
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            shared.clone(),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(3.0, 0.0, 0.0),
+            1.0,
+            shared,
+        )));
+        world.add(Arc::new(Sphere::new(
+            Point3::new(6.0, 0.0, 0.0),
+            1.0,
+            metal,
+        )));
+        world.with_object_ids()
+    }
+
+    /// The first hit of a ray straight down onto the sphere at `x`.
+    fn first_hit(world: &HittableList, x: f32) -> FirstHit {
+        let ray = Ray::new(Point3::new(x, 5.0, 0.0), Vec3::new(0.0, -2.0, 0.0), 0.0);
+        let rec = world.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        FirstHit::new(&ray, &rec)
+    }
+
+    #[test]
+    fn ids_come_from_the_scene() {
+        let world = spheres();
+
+        for (x, object_id, material_id) in [(0.0, 1.0, 1.0), (3.0, 2.0, 1.0), (6.0, 3.0, 2.0)] {
+            let mut pixel = AovPixel::default();
+            pixel.add(&first_hit(&world, x));
+
+            assert_eq!(pixel.values(Aov::ObjectId), vec![object_id]);
+            assert_eq!(pixel.values(Aov::MaterialId), vec![material_id]);
+        }
+    }
+
+    #[test]
+    fn pixels_average_their_hits_and_keep_the_first_ids() {
+        let world = spheres();
+
+        let mut pixel = AovPixel::default();
+        assert_eq!(pixel.values(Aov::Depth), vec![0.0]);
+        assert_eq!(pixel.values(Aov::Normal), vec![0.0; 3]);
+
+        pixel.add(&first_hit(&world, 0.0));
+        let mut other = AovPixel::default();
+        other.add(&first_hit(&world, 6.0));
+        pixel.merge(&other);
+
+        // Both hits are 4 away from where the rays start, straight below them.
+        assert_eq!(pixel.values(Aov::Depth), vec![4.0]);
+        assert_eq!(pixel.values(Aov::Position), vec![3.0, 1.0, 0.0]);
+        assert_eq!(pixel.values(Aov::Normal), vec![0.0, 1.0, 0.0]);
+        let albedo = pixel.values(Aov::Albedo);
+        assert!((albedo[0] - 0.55).abs() < 1e-6 && (albedo[2] - 0.75).abs() < 1e-6);
+        assert_eq!(pixel.values(Aov::ObjectId), vec![1.0]);
+        assert_eq!(pixel.values(Aov::MaterialId), vec![1.0]);
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use crate::{
+    environment::EnvironmentMap,
+    sky::Sky,
+    vec3::{Color, Vec3},
+};
+
+/// What rays that don't hit anything see.
+pub enum Background {
+    Color(Color),
+    /// Blends from `bottom` straight down to `top` straight up, like the sky in the first book.
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+    /// Should also be added to the scene's lights, to be sampled directly.
+    Environment(Arc<EnvironmentMap>),
+    /// Its `SunLight` should also be added to the scene's lights.
+    Sky(Arc<Sky>),
+}
+
+impl Background {
+    /// The light arriving from `direction`.
+    pub fn value(&self, direction: &Vec3) -> Color {
+        match self {
+            Self::Color(color) => *color,
+            Self::Gradient { bottom, top } => {
+                let t = 0.5 * (Vec3::unit_vector(*direction).y + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            }
+            Self::Environment(map) => map.value(direction),
+            Self::Sky(sky) => sky.value(direction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradients_blend_from_bottom_to_top() {
+        let background = Background::Gradient {
+            bottom: Color::new(1.0, 0.0, 0.0),
+            top: Color::new(0.0, 0.0, 1.0),
+        };
+
+        let up = background.value(&Vec3::new(0.0, 2.0, 0.0));
+        let down = background.value(&Vec3::new(0.0, -1.0, 0.0));
+        let sideways = background.value(&Vec3::new(3.0, 0.0, 4.0));
+        assert_eq!([up.x, up.y, up.z], [0.0, 0.0, 1.0]);
+        assert_eq!([down.x, down.y, down.z], [1.0, 0.0, 0.0]);
+        assert_eq!([sideways.x, sideways.y, sideways.z], [0.5, 0.0, 0.5]);
+    }
+}
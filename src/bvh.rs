@@ -1,125 +1,625 @@
-use core::panic;
-use std::{cmp::Ordering, rc::Rc};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
 
 use crate::{
     aabb::AABB,
     hittable::{HitRecord, Hittable},
     hittable_list::HittableList,
     ray::Ray,
+    vec3::Point3,
 };
-use rand::Rng;
-
-pub struct BVHNode {
-    pub left: Rc<dyn Hittable>,
-    pub right: Rc<dyn Hittable>,
-    pub bounding_box: AABB,
-}
-
-impl BVHNode {
-    pub fn new(src_objects: &[Rc<dyn Hittable>], t0: f32, t1: f32) -> Self {
-        // Mutable vec of the list of scene objects, so that we can sort them.
-        let mut objects: Vec<Rc<dyn Hittable>> =
-            src_objects.iter().map(|object| object.clone()).collect();
-
-        let left: Rc<dyn Hittable>;
-        let right: Rc<dyn Hittable>;
-
-        let axis: i32 = rand::thread_rng().gen_range(0..2);
-        let comparator = if axis == 0 {
-            box_x_compare
-        } else if axis == 1 {
-            box_y_compare
-        } else {
-            box_z_compare
-        };
 
-        if objects.len() == 1 {
-            left = objects[0].clone();
-            right = objects[0].clone();
-        } else if objects.len() == 2 {
-            if comparator(&objects[0], &objects[1]) == Ordering::Less {
-                left = objects[0].clone();
-                right = objects[1].clone();
-            } else {
-                left = objects[1].clone();
-                right = objects[0].clone();
-            }
-        } else {
-            objects.sort_by(comparator);
+/// Number of buckets the centroids get sorted into when looking for the best split.
+const SAH_BUCKETS: usize = 12;
+
+/// Cost of testing a ray against a node's box, relative to intersecting a primitive.
+const TRAVERSAL_COST: f32 = 0.125;
+
+/// Nodes with this many primitives or less become a leaf if splitting them wouldn't pay off.
+const MAX_LEAF_PRIMITIVES: usize = 4;
+
+/// Size of the traversal stack. Below `MAX_SAH_DEPTH` the nodes are split at the median, which
+/// keeps the tree shallow enough for this to never overflow.
+const STACK_SIZE: usize = 64;
+const MAX_SAH_DEPTH: usize = 32;
+
+/// How nodes get split while building a BVH.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SplitMethod {
+    /// The split with the lowest surface area heuristic cost.
+    #[default]
+    Sah,
+    /// Halfway the primitives, sorted along the longest axis. Only there to compare the surface
+    /// area heuristic against.
+    Median,
+}
 
-            let mid = objects.len() / 2;
-            left = Rc::new(BVHNode::new(&objects[0..mid], t0, t1));
-            right = Rc::new(BVHNode::new(&objects[mid..objects.len()], t0, t1));
+/// What building BVHs took. Meshes have their own BVH inside the one of the scene, those count
+/// as well.
+#[derive(Clone, Copy, Default)]
+pub struct BuildStats {
+    pub time: Duration,
+    pub nodes: usize,
+    pub primitives: usize,
+}
+
+/// How many node boxes and primitives a ray got tested against, see `Hittable::hit_counting`.
+#[derive(Clone, Copy, Default)]
+pub struct TraversalStats {
+    pub box_tests: u64,
+    pub primitive_tests: u64,
+}
+
+/// Builds all the BVHs of a scene with the same split method, keeping track of what that took.
+#[derive(Default)]
+pub struct BVHBuilder {
+    split_method: SplitMethod,
+    stats: BuildStats,
+}
+
+impl BVHBuilder {
+    pub fn new(split_method: SplitMethod) -> Self {
+        Self {
+            split_method,
+            stats: BuildStats::default(),
         }
+    }
 
-        let box_left = left
-            .bounding_box(t0, t1)
-            .expect("No bounding box in BVHNode constructor!");
-        let box_right = right
-            .bounding_box(t0, t1)
-            .expect("No bounding box in BVHNode constructor!");
+    pub fn build(&mut self, src_objects: &[Arc<dyn Hittable>], t0: f32, t1: f32) -> BVH {
+        assert!(
+            !src_objects.is_empty(),
+            "Can't build a BVH without any objects!"
+        );
+        assert!(
+            src_objects.len() <= u32::MAX as usize,
+            "Too many objects for a BVH!"
+        );
+
+        let mut primitives: Vec<BuildPrimitive> = src_objects
+            .iter()
+            .map(|object| BuildPrimitive::new(object, t0, t1))
+            .collect();
+
+        let begin = Instant::now();
+        let mut nodes = Vec::with_capacity(2 * primitives.len());
+        build(&mut primitives, 0, 0, self.split_method, &mut nodes);
+
+        self.stats.time += begin.elapsed();
+        self.stats.nodes += nodes.len();
+        self.stats.primitives += primitives.len();
+
+        BVH {
+            nodes,
+            primitives: primitives
+                .into_iter()
+                .map(|primitive| primitive.object)
+                .collect(),
+        }
+    }
+
+    pub fn build_list(&mut self, list: &HittableList, t0: f32, t1: f32) -> BVH {
+        self.build(&list.objects, t0, t1)
+    }
+
+    /// What building all the BVHs so far took.
+    pub fn stats(&self) -> BuildStats {
+        self.stats
+    }
+}
+
+/// A node of the flattened tree. The first child of an interior node directly follows it in the
+/// node array, so only the index of the second child is stored.
+#[derive(Clone, Copy)]
+struct LinearNode {
+    bounding_box: AABB,
+    /// Index of the first primitive for leaves, index of the second child for interior nodes.
+    offset: u32,
+    /// Number of primitives in a leaf, zero for interior nodes.
+    primitive_count: u16,
+    /// The axis interior nodes are split on.
+    axis: u8,
+}
+
+/// A bounding volume hierarchy, flattened into an array of nodes in depth-first order. The
+/// primitives are ordered so that every leaf refers to a contiguous range of them.
+#[allow(clippy::upper_case_acronyms)]
+pub struct BVH {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Arc<dyn Hittable>>,
+}
+
+/// An object together with the bounding box information needed while building the tree.
+struct BuildPrimitive {
+    object: Arc<dyn Hittable>,
+    bounding_box: AABB,
+    centroid: Point3,
+}
 
-        let bounding_box = AABB::surrounding_box(&box_left, &box_right);
+impl BuildPrimitive {
+    fn new(object: &Arc<dyn Hittable>, t0: f32, t1: f32) -> Self {
+        let bounding_box = object
+            .bounding_box(t0, t1)
+            .expect("No bounding box in BVH constructor!");
 
         Self {
-            left,
-            right,
+            object: object.clone(),
             bounding_box,
+            centroid: bounding_box.centroid(),
         }
     }
+}
+
+/// A split of a node's primitives into two sides.
+struct Split {
+    /// Surface area heuristic cost of the split, not yet divided by the parent's surface area.
+    cost: f32,
+    axis: usize,
+    /// Index of the first primitive of the second half.
+    mid: usize,
+}
+
+impl BVH {
+    /// Like `hit`, but also returns which of the objects got hit.
+    pub fn hit_object(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(HitRecord<'_>, &dyn Hittable)> {
+        let mut closest_hit = None;
+
+        self.traverse(ray, t_min, t_max, None, |objects, t_max| {
+            for object in objects {
+                if let Some(hit) = object.hit(ray, t_min, *t_max) {
+                    *t_max = hit.t;
+                    closest_hit = Some((hit, object.as_ref()));
+                }
+            }
+            false
+        });
+
+        closest_hit
+    }
+
+    /// Walks the nodes the ray passes through front to back, handing the primitives of every leaf
+    /// to `visit_leaf` along with the current `t_max`, which it can shrink. Stops early when
+    /// `visit_leaf` returns true. Adds the number of node boxes the ray gets tested against to
+    /// `box_tests`, if given.
+    fn traverse<'a>(
+        &'a self,
+        ray: &Ray,
+        t_min: f32,
+        mut t_max: f32,
+        mut box_tests: Option<&mut u64>,
+        mut visit_leaf: impl FnMut(&'a [Arc<dyn Hittable>], &mut f32) -> bool,
+    ) {
+        let dir_is_neg = [
+            ray.direction.x < 0.0,
+            ray.direction.y < 0.0,
+            ray.direction.z < 0.0,
+        ];
+
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0u32;
+
+        loop {
+            let node = &self.nodes[current as usize];
+            if let Some(box_tests) = &mut box_tests {
+                **box_tests += 1;
+            }
 
-    pub fn from(list: &HittableList, t0: f32, t1: f32) -> Self {
-        Self::new(&list.objects, t0, t1)
+            if node.bounding_box.hit(ray, t_min, t_max) {
+                if node.primitive_count > 0 {
+                    let first = node.offset as usize;
+                    let last = first + node.primitive_count as usize;
+                    if visit_leaf(&self.primitives[first..last], &mut t_max) {
+                        return;
+                    }
+                } else {
+                    // Visit the child on the near side of the split first, so that hits found
+                    // there can cull the far child.
+                    if dir_is_neg[node.axis as usize] {
+                        stack[stack_len] = current + 1;
+                        current = node.offset;
+                    } else {
+                        stack[stack_len] = node.offset;
+                        current += 1;
+                    }
+                    stack_len += 1;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                return;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
     }
 }
 
-impl Hittable for BVHNode {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        if !self.bounding_box.hit(&ray, t_min, t_max) {
-            return None;
+/// Recursively builds the nodes for `primitives`, reordering them so that leaves end up with
+/// contiguous ranges. `offset` is the index of `primitives[0]` in the final primitive list.
+/// Returns the index of the created node.
+fn build(
+    primitives: &mut [BuildPrimitive],
+    offset: usize,
+    depth: usize,
+    method: SplitMethod,
+    nodes: &mut Vec<LinearNode>,
+) -> u32 {
+    let bounding_box = primitives
+        .iter()
+        .skip(1)
+        .fold(primitives[0].bounding_box, |bounding_box, primitive| {
+            AABB::surrounding_box(&bounding_box, &primitive.bounding_box)
+        });
+
+    let index = nodes.len() as u32;
+    let leaf = LinearNode {
+        bounding_box,
+        offset: offset as u32,
+        primitive_count: primitives.len() as u16,
+        axis: 0,
+    };
+
+    if primitives.len() == 1 {
+        nodes.push(leaf);
+        return index;
+    }
+
+    // Past `MAX_SAH_DEPTH` nodes get split at the median, halving them every level.
+    let split = match method {
+        SplitMethod::Sah if depth < MAX_SAH_DEPTH => partition(primitives),
+        SplitMethod::Sah | SplitMethod::Median => median_split(primitives),
+    };
+
+    let (axis, mid) = match split {
+        Some(split) => {
+            // Intersecting every primitive in a leaf costs one unit each.
+            let split_cost = TRAVERSAL_COST + split.cost / bounding_box.surface_area();
+            if primitives.len() <= MAX_LEAF_PRIMITIVES && split_cost >= primitives.len() as f32 {
+                nodes.push(leaf);
+                return index;
+            }
+            (split.axis, split.mid)
+        }
+        None if primitives.len() <= MAX_LEAF_PRIMITIVES => {
+            nodes.push(leaf);
+            return index;
         }
+        // All the centroids are in the same spot, any split is as good as the other.
+        None => (0, primitives.len() / 2),
+    };
+
+    // The second child's index isn't known until the first child's subtree is built.
+    nodes.push(LinearNode {
+        bounding_box,
+        offset: 0,
+        primitive_count: 0,
+        axis: axis as u8,
+    });
+
+    let (left, right) = primitives.split_at_mut(mid);
+    build(left, offset, depth + 1, method, nodes);
+    let second_child = build(right, offset + mid, depth + 1, method, nodes);
+    nodes[index as usize].offset = second_child;
+
+    index
+}
+
+/// Reorders the primitives so that they are split in two along the plane with the lowest surface
+/// area heuristic cost, and returns that split.
+///
+/// The candidate planes lie between buckets of primitive centroids, on all three axes. When the
+/// centroids are all in the same spot there is nothing to split on, and `None` is returned.
+fn partition(primitives: &mut [BuildPrimitive]) -> Option<Split> {
+    let centroid_bounds = centroid_bounds(primitives);
 
-        let hit_left = self.left.hit(&ray, t_min, t_max);
-        let hit_right = self.right.hit(&ray, t_min, t_max);
+    // (cost, axis, index of the last bucket on the left side)
+    let mut best_split: Option<(f32, usize, usize)> = None;
 
-        match (hit_left, hit_right) {
-            (Some(x), _) | (_, Some(x)) => Some(x),
-            _ => None,
+    for axis in 0..3 {
+        let min = centroid_bounds.minimum[axis];
+        let extent = centroid_bounds.maximum[axis] - min;
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut boxes: [Option<AABB>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+
+        for primitive in primitives.iter() {
+            let b = bucket(primitive.centroid[axis], min, extent);
+            counts[b] += 1;
+            boxes[b] = Some(surround(boxes[b], &primitive.bounding_box));
+        }
+
+        // Sweep from the right, so that the left side can be accumulated in the loop below.
+        let mut right_costs = [0.0; SAH_BUCKETS];
+        let mut right_box: Option<AABB> = None;
+        let mut right_count = 0;
+        for b in (1..SAH_BUCKETS).rev() {
+            if let Some(bucket_box) = &boxes[b] {
+                right_box = Some(surround(right_box, bucket_box));
+            }
+            right_count += counts[b];
+            right_costs[b - 1] = right_box.map_or(0.0, |bb| bb.surface_area()) * right_count as f32;
+        }
+
+        let mut left_box: Option<AABB> = None;
+        let mut left_count = 0;
+        for split in 0..SAH_BUCKETS - 1 {
+            if let Some(bucket_box) = &boxes[split] {
+                left_box = Some(surround(left_box, bucket_box));
+            }
+            left_count += counts[split];
+
+            if left_count == 0 || left_count == primitives.len() {
+                continue;
+            }
+
+            let cost = left_box.map_or(0.0, |bb| bb.surface_area()) * left_count as f32
+                + right_costs[split];
+
+            if best_split.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best_split = Some((cost, axis, split));
+            }
         }
     }
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
-        Some(self.bounding_box)
+
+    let (cost, axis, split) = best_split?;
+    let min = centroid_bounds.minimum[axis];
+    let extent = centroid_bounds.maximum[axis] - min;
+
+    // In-place partition, everything in buckets up to and including `split` goes left.
+    let mut mid = 0;
+    for i in 0..primitives.len() {
+        if bucket(primitives[i].centroid[axis], min, extent) <= split {
+            primitives.swap(i, mid);
+            mid += 1;
+        }
     }
+
+    Some(Split { cost, axis, mid })
 }
 
-fn box_compare(a: &Rc<dyn Hittable>, b: &Rc<dyn Hittable>, axis: i32) -> Ordering {
-    let box_a = a
-        .bounding_box(0.0, 0.0)
-        .expect("No bounding box in BVHNode constructor!");
-    let box_b = b
-        .bounding_box(0.0, 0.0)
-        .expect("No bounding box in BVHNode constructor!");
+/// Reorders the primitives so that the first half has the lowest centroids along the axis the
+/// centroids are spread out the most on, and returns that split. Like `partition`, returns `None`
+/// when the centroids are all in the same spot.
+fn median_split(primitives: &mut [BuildPrimitive]) -> Option<Split> {
+    let centroid_bounds = centroid_bounds(primitives);
+    let extent = centroid_bounds.maximum - centroid_bounds.minimum;
+    let axis = (0..3)
+        .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+        .unwrap();
+    if extent[axis] <= 0.0 {
+        return None;
+    }
 
-    let is_greater = match axis {
-        0 => box_a.minimum.x < box_b.minimum.x,
-        1 => box_a.minimum.y < box_b.minimum.y,
-        2 => box_a.minimum.z < box_b.minimum.z,
-        _ => panic!("Axis out of range!"),
-    };
+    let mid = primitives.len() / 2;
+    primitives.select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
 
-    return if is_greater {
-        Ordering::Greater
-    } else {
-        Ordering::Less
+    // The cost only decides whether small nodes become leaves, same as for the other splits.
+    let side_cost = |side: &[BuildPrimitive]| {
+        let bounding_box = side
+            .iter()
+            .skip(1)
+            .fold(side[0].bounding_box, |bb, primitive| {
+                AABB::surrounding_box(&bb, &primitive.bounding_box)
+            });
+        bounding_box.surface_area() * side.len() as f32
     };
+    let cost = side_cost(&primitives[..mid]) + side_cost(&primitives[mid..]);
+
+    Some(Split { cost, axis, mid })
 }
 
-fn box_x_compare(a: &Rc<dyn Hittable>, b: &Rc<dyn Hittable>) -> Ordering {
-    box_compare(a, b, 0)
+fn centroid_bounds(primitives: &[BuildPrimitive]) -> AABB {
+    primitives.iter().skip(1).fold(
+        AABB::new(primitives[0].centroid, primitives[0].centroid),
+        |bounds, primitive| {
+            AABB::surrounding_box(&bounds, &AABB::new(primitive.centroid, primitive.centroid))
+        },
+    )
 }
-fn box_y_compare(a: &Rc<dyn Hittable>, b: &Rc<dyn Hittable>) -> Ordering {
-    box_compare(a, b, 1)
+
+fn bucket(centroid: f32, min: f32, extent: f32) -> usize {
+    let b = (SAH_BUCKETS as f32 * (centroid - min) / extent) as usize;
+    b.min(SAH_BUCKETS - 1)
 }
-fn box_z_compare(a: &Rc<dyn Hittable>, b: &Rc<dyn Hittable>) -> Ordering {
-    box_compare(a, b, 2)
+
+fn surround(a: Option<AABB>, b: &AABB) -> AABB {
+    match a {
+        Some(a) => AABB::surrounding_box(&a, b),
+        None => *b,
+    }
+}
+
+impl Hittable for BVH {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.hit_object(ray, t_min, t_max).map(|(rec, _)| rec)
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        let mut found = false;
+
+        self.traverse(ray, t_min, t_max, None, |objects, t_max| {
+            found = objects
+                .iter()
+                .any(|object| object.hit_any(ray, t_min, *t_max));
+            found
+        });
+
+        found
+    }
+
+    fn hit_counting(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord<'_>> {
+        let mut closest_hit = None;
+        let mut box_tests = 0;
+
+        self.traverse(ray, t_min, t_max, Some(&mut box_tests), |objects, t_max| {
+            for object in objects {
+                if let Some(hit) = object.hit_counting(ray, t_min, *t_max, stats) {
+                    *t_max = hit.t;
+                    closest_hit = Some(hit);
+                }
+            }
+            false
+        });
+
+        stats.box_tests += box_tests;
+        closest_hit
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let mut transmittance = 1.0;
+
+        self.traverse(ray, t_min, t_max, None, |objects, t_max| {
+            for object in objects {
+                transmittance *= object.transmittance(ray, t_min, *t_max);
+            }
+            transmittance <= 0.0
+        });
+
+        f32::max(transmittance, 0.0)
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(self.nodes[0].bounding_box)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        materials::Lambertian,
+        objects::Sphere,
+        vec3::{Color, Vec3},
+    };
+
+    /// A row of small spheres along the x axis, in a shuffled order.
+    fn spheres(count: usize) -> Vec<Arc<dyn Hittable>> {
+        (0..count)
+            .map(|i| {
+                let x = ((i * 7) % count) as f32;
+                Arc::new(Sphere::new(
+                    Point3::new(x, 0.1 * (i % 3) as f32, 0.0),
+                    0.25,
+                    Lambertian::new(Color::from(0.5)),
+                )) as Arc<dyn Hittable>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn build_stats_add_up_over_builds() {
+        let mut builder = BVHBuilder::new(SplitMethod::Sah);
+        builder.build(&spheres(10), 0.0, 1.0);
+        builder.build(&spheres(1), 0.0, 1.0);
+
+        // Splitting 10 objects takes at least 9 nodes, the single object gets one as well. Nodes
+        // split off with a single object get one too, but there can't be more of those than
+        // objects.
+        // Leaves hold up to `MAX_LEAF_PRIMITIVES` objects, so the 10 objects take at least a root
+        // and two leaves, and at most a node per object and one between each of them. The single
+        // object gets a node as well.
+        let stats = builder.stats();
+        assert!((4..21).contains(&stats.nodes));
+        assert_eq!(stats.primitives, 11);
+    }
+
+    #[test]
+    fn median_split_halves_the_longest_axis() {
+        let objects = spheres(11);
+        let mut primitives: Vec<BuildPrimitive> = objects
+            .iter()
+            .map(|object| BuildPrimitive::new(object, 0.0, 1.0))
+            .collect();
+
+        let split = median_split(&mut primitives).unwrap();
+        assert_eq!(split.axis, 0);
+        assert_eq!(split.mid, 5);
+
+        let mid = split.mid;
+        let left_max = primitives[..mid]
+            .iter()
+            .map(|p| p.centroid.x)
+            .fold(f32::MIN, f32::max);
+        let right_min = primitives[mid..]
+            .iter()
+            .map(|p| p.centroid.x)
+            .fold(f32::MAX, f32::min);
+        assert!(left_max < right_min);
+    }
+
+    #[test]
+    fn counting_finds_the_same_hits() {
+        for method in [SplitMethod::Sah, SplitMethod::Median] {
+            let bvh = BVHBuilder::new(method).build(&spheres(16), 0.0, 1.0);
+
+            for x in [0.0, 3.0, 7.5, 15.0, 20.0] {
+                let ray = Ray::new(Point3::new(x, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+                let mut stats = TraversalStats::default();
+
+                let hit = bvh.hit(&ray, 0.001, f32::INFINITY).map(|rec| rec.t);
+                let counted = bvh
+                    .hit_counting(&ray, 0.001, f32::INFINITY, &mut stats)
+                    .map(|rec| rec.t);
+                assert_eq!(hit, counted);
+                assert!(stats.box_tests >= 1);
+                assert!(stats.primitive_tests <= 16);
+                assert!(hit.is_none() || stats.primitive_tests > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn traversal_finds_the_nearest_hit() {
+        // Overlapping spheres along the ray, so leaves and nodes further away can have hits
+        // closer than the nearest one in nodes visited first.
+        let objects: Vec<Arc<dyn Hittable>> = (0..40)
+            .map(|i| {
+                let z = -((i * 13) % 40) as f32 * 0.3;
+                Arc::new(Sphere::new(
+                    Point3::new(0.05 * (i % 5) as f32, 0.0, z),
+                    1.0,
+                    Lambertian::new(Color::from(0.5)),
+                )) as Arc<dyn Hittable>
+            })
+            .collect();
+        let list = HittableList {
+            objects: objects.clone(),
+        };
+
+        for method in [SplitMethod::Sah, SplitMethod::Median] {
+            let bvh = BVHBuilder::new(method).build(&objects, 0.0, 1.0);
+
+            for (x, y) in [(0.0, 0.0), (0.3, 0.5), (-0.9, 0.1), (2.0, 0.0)] {
+                for direction in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0)] {
+                    let ray = Ray::new(Point3::new(x, y, 0.5), direction, 0.0);
+                    let expected = list.hit(&ray, 0.001, f32::INFINITY).map(|rec| rec.t);
+
+                    assert_eq!(
+                        bvh.hit(&ray, 0.001, f32::INFINITY).map(|rec| rec.t),
+                        expected
+                    );
+                    assert_eq!(bvh.hit_any(&ray, 0.001, f32::INFINITY), expected.is_some());
+                }
+            }
+        }
+    }
 }
@@ -1,9 +1,8 @@
 use crate::{
     ray::Ray,
-    utils::to_radians,
+    utils::{random_range, to_radians},
     vec3::{Point3, Vec3},
 };
-use rand::Rng;
 
 pub struct Camera {
     origin: Point3,
@@ -12,13 +11,13 @@ pub struct Camera {
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
     lens_radius: f32,
     time0: f32,
     time1: f32,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -53,7 +52,6 @@ impl Camera {
             vertical,
             u,
             v,
-            w,
             lens_radius,
             time0,
             time1,
@@ -68,7 +66,7 @@ impl Camera {
             self.lower_left_corner + s * self.horiozontal + t * self.vertical
                 - self.origin
                 - offset,
-            rand::thread_rng().gen_range(self.time0..self.time1),
+            random_range(self.time0, self.time1),
         )
     }
 }
@@ -0,0 +1,322 @@
+use std::{
+    fmt, fs,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{output_buffer::OutputBuffer, renderer::Pass, vec3::Color};
+
+/// Start of every checkpoint file, the last two characters are the version of the format.
+const MAGIC: &[u8; 8] = b"RTCKPT03";
+
+/// How far along a render is, in passes. Together with the samples accumulated in the
+/// `OutputBuffer`, that's all it takes to pick the render up again later.
+#[derive(Clone, Copy)]
+pub struct Progress {
+    /// The seed of every pass.
+    pub seed: u64,
+    pub passes: u32,
+    /// Samples per pixel taken so far, by the pixels that got the most with adaptive sampling.
+    pub samples: u32,
+}
+
+impl Progress {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            passes: 0,
+            samples: 0,
+        }
+    }
+
+    /// The next pass, taking `samples` samples per pixel.
+    pub fn next_pass(&self, samples: u32) -> Pass {
+        Pass {
+            samples,
+            seed: self.seed,
+        }
+    }
+
+    pub fn finish_pass(&mut self, pass: &Pass) {
+        self.passes += 1;
+        self.samples += pass.samples;
+    }
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io { path: PathBuf, source: io::Error },
+    Invalid { path: PathBuf, message: String },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "'{}': {}", path.display(), source),
+            Self::Invalid { path, message } => write!(f, "'{}': {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+/// Writes the samples in `buffer` and the `progress` of the render to `path`. The file gets
+/// replaced in one go, so a render that gets killed halfway through writing it still leaves the
+/// previous checkpoint behind.
+///
+/// Only the image itself is saved, AOVs and the guides of the denoiser start over when resuming.
+/// Without any new samples, there's nothing to denoise with, see `OutputBuffer::final_pixels`.
+pub fn save<P: AsRef<Path>>(
+    path: P,
+    buffer: &OutputBuffer,
+    progress: &Progress,
+) -> Result<(), CheckpointError> {
+    let path = path.as_ref();
+    let io_error = |source| CheckpointError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    write(&temp_path, buffer, progress).map_err(io_error)?;
+    fs::rename(&temp_path, path).map_err(io_error)
+}
+
+fn write(path: &Path, buffer: &OutputBuffer, progress: &Progress) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&buffer.width().to_le_bytes())?;
+    writer.write_all(&buffer.height().to_le_bytes())?;
+    writer.write_all(&progress.seed.to_le_bytes())?;
+    writer.write_all(&progress.passes.to_le_bytes())?;
+    writer.write_all(&progress.samples.to_le_bytes())?;
+
+    for sums in buffer.accumulation() {
+        for value in [
+            sums.color.x,
+            sums.color.y,
+            sums.color.z,
+            sums.coverage,
+            sums.weight,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&sums.count.to_le_bytes())?;
+        writer.write_all(&sums.luminance.to_le_bytes())?;
+        writer.write_all(&sums.luminance_squares.to_le_bytes())?;
+    }
+
+    writer.flush()
+}
+
+/// The seed of the render in the checkpoint at `path`. The scene needs to be built with it before
+/// the rest of the checkpoint can be loaded, in case it has anything random in it.
+pub fn read_seed<P: AsRef<Path>>(path: P) -> Result<u64, CheckpointError> {
+    let (_, header) = open(path.as_ref())?;
+    Ok(header.progress.seed)
+}
+
+/// Reads a checkpoint written by `save` into `buffer`, which has to be the same size as the image
+/// in the checkpoint. Returns how far along the render was.
+pub fn load<P: AsRef<Path>>(
+    path: P,
+    buffer: &mut OutputBuffer,
+) -> Result<Progress, CheckpointError> {
+    let path = path.as_ref();
+    let (mut reader, header) = open(path)?;
+
+    if (header.width, header.height) != (buffer.width(), buffer.height()) {
+        return Err(invalid(
+            path,
+            format!(
+                "the checkpoint is {}x{}, but the image is {}x{}",
+                header.width,
+                header.height,
+                buffer.width(),
+                buffer.height()
+            ),
+        ));
+    }
+
+    let truncated = |_| incomplete(path);
+    for sums in buffer.accumulation_mut() {
+        let mut values = [0.0; 5];
+        for value in values.iter_mut() {
+            *value = read_f32(&mut reader).map_err(truncated)?;
+        }
+
+        sums.color = Color::new(values[0], values[1], values[2]);
+        sums.coverage = values[3];
+        sums.weight = values[4];
+        sums.count = read_u32(&mut reader).map_err(truncated)?;
+        sums.luminance = read_f32(&mut reader).map_err(truncated)?;
+        sums.luminance_squares = read_f32(&mut reader).map_err(truncated)?;
+    }
+
+    Ok(header.progress)
+}
+
+/// Everything in a checkpoint before the pixels.
+struct Header {
+    width: u32,
+    height: u32,
+    progress: Progress,
+}
+
+/// Opens the checkpoint at `path` and reads its header, leaving the reader at the first pixel.
+fn open(path: &Path) -> Result<(BufReader<File>, Header), CheckpointError> {
+    let file = File::open(path).map_err(|source| CheckpointError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut reader = BufReader::new(file);
+    let truncated = |_| incomplete(path);
+
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic).map_err(truncated)?;
+    if &magic != MAGIC {
+        return Err(invalid(path, "not a checkpoint file".to_string()));
+    }
+
+    let width = read_u32(&mut reader).map_err(truncated)?;
+    let height = read_u32(&mut reader).map_err(truncated)?;
+
+    let mut progress = Progress::new(read_u64(&mut reader).map_err(truncated)?);
+    progress.passes = read_u32(&mut reader).map_err(truncated)?;
+    progress.samples = read_u32(&mut reader).map_err(truncated)?;
+
+    let header = Header {
+        width,
+        height,
+        progress,
+    };
+    Ok((reader, header))
+}
+
+fn invalid(path: &Path, message: String) -> CheckpointError {
+    CheckpointError::Invalid {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+/// Running out of data halfway through means the file got cut off.
+fn incomplete(path: &Path) -> CheckpointError {
+    invalid(path, "the checkpoint is incomplete".to_string())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(reader)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_buffer::PixelSums;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("checkpoint_test_{}.ckpt", name))
+    }
+
+    fn rendered_buffer() -> OutputBuffer {
+        let mut buffer = OutputBuffer::new(3, 2, 4);
+        for (i, (x, y)) in [(0, 0), (2, 0), (1, 1)].into_iter().enumerate() {
+            let samples = PixelSums {
+                color: Color::new(i as f32, 0.5, 8.0),
+                coverage: 3.0,
+                weight: 2.5,
+                count: 4,
+                luminance: 1.0 + i as f32,
+                luminance_squares: 2.0 + i as f32,
+            };
+            buffer.add_samples(x, y, &samples);
+        }
+        buffer
+    }
+
+    #[test]
+    fn renders_resume_where_they_left_off() {
+        let path = temp_path("round_trip");
+        let buffer = rendered_buffer();
+        let mut progress = Progress::new(42);
+        progress.finish_pass(&progress.next_pass(4));
+
+        save(&path, &buffer, &progress).unwrap();
+        let mut resumed = OutputBuffer::new(3, 2, 4);
+        let resumed_progress = load(&path, &mut resumed).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for (sums, expected) in resumed.accumulation().iter().zip(buffer.accumulation()) {
+            let (color, expected_color) = (sums.color, expected.color);
+            assert_eq!(
+                [color.x, color.y, color.z],
+                [expected_color.x, expected_color.y, expected_color.z]
+            );
+            assert_eq!(
+                [
+                    sums.coverage,
+                    sums.weight,
+                    sums.luminance,
+                    sums.luminance_squares
+                ],
+                [
+                    expected.coverage,
+                    expected.weight,
+                    expected.luminance,
+                    expected.luminance_squares
+                ]
+            );
+            assert_eq!(sums.count, expected.count);
+        }
+        assert_eq!(
+            (
+                resumed_progress.seed,
+                resumed_progress.passes,
+                resumed_progress.samples
+            ),
+            (42, 1, 4)
+        );
+        // Samples get their random numbers from their index, not from the pass they're in.
+        assert_eq!(resumed_progress.next_pass(4).seed, 42);
+    }
+
+    #[test]
+    fn checkpoints_have_to_match_the_image() {
+        let path = temp_path("mismatch");
+        save(&path, &rendered_buffer(), &Progress::new(1)).unwrap();
+
+        let result = load(&path, &mut OutputBuffer::new(2, 3, 4));
+        assert!(matches!(result, Err(CheckpointError::Invalid { .. })));
+
+        // Cut the file off halfway through the pixels.
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        let result = load(&path, &mut OutputBuffer::new(3, 2, 4));
+        assert!(matches!(result, Err(CheckpointError::Invalid { .. })));
+
+        std::fs::write(&path, b"P6\n3 2\n255\n").unwrap();
+        let result = load(&path, &mut OutputBuffer::new(3, 2, 4));
+        assert!(matches!(result, Err(CheckpointError::Invalid { .. })));
+
+        std::fs::remove_file(&path).unwrap();
+        let result = load(&path, &mut OutputBuffer::new(3, 2, 4));
+        assert!(matches!(result, Err(CheckpointError::Io { .. })));
+    }
+}
@@ -0,0 +1,283 @@
+use clap::Parser;
+
+use crate::{
+    adaptive::AdaptiveSampling,
+    aov::Aov,
+    bvh::SplitMethod,
+    filter::{Filter, FilterKind},
+    sampler::SamplerKind,
+    scenes::SceneInfo,
+    tonemap::{ToneMapper, ToneMapping},
+};
+
+/// Ray Tracing In One Weekend in Rust.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Name of the scene to render, see --list-scenes for the available ones.
+    #[arg(short, long, default_value = "cornell_box")]
+    pub scene: String,
+
+    /// Render a TOML scene description instead of one of the built-in scenes.
+    #[arg(short = 'f', long, conflicts_with = "scene")]
+    pub scene_file: Option<String>,
+
+    /// Print the names of all the available scenes and exit.
+    #[arg(long)]
+    pub list_scenes: bool,
+
+    /// File to write the rendered image to. `.exr` and `.hdr` files keep the full range of the
+    /// linear radiance, any other format gets tone mapped to 8 bits.
+    #[arg(short, long, default_value = "output.png")]
+    pub output: String,
+
+    /// Image width in pixels. If only one of width and height is given, the other one follows the
+    /// scene's aspect ratio.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Image height in pixels.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: Option<u32>,
+
+    /// Number of samples per pixel.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub spp: Option<u32>,
+
+    /// Seed for everything random, in the scene as well as in the samples. The same seed and
+    /// settings give the same image, no matter how many threads render it. Random by default.
+    #[arg(long, conflicts_with = "resume")]
+    pub seed: Option<u64>,
+
+    /// Where the random numbers of every sample come from, the scene's choice by default.
+    #[arg(long, value_enum)]
+    pub sampler: Option<SamplerKind>,
+
+    /// Keep adding samples to pixels that are still noisy, after they got the samples per pixel of
+    /// the scene or --spp. Takes an optional noise threshold, lower values take more samples.
+    #[arg(long, value_name = "THRESHOLD", num_args = 0..=1, default_missing_value = "0.02")]
+    pub adaptive: Option<f32>,
+
+    /// The most samples adaptive sampling gives a single pixel, 8 times --spp by default.
+    #[arg(long, requires = "adaptive")]
+    pub max_spp: Option<u32>,
+
+    /// Write a heatmap of the number of samples every pixel got to this file.
+    #[arg(long, value_name = "FILE")]
+    pub heatmap: Option<String>,
+
+    /// Render in passes of this many samples per pixel, instead of all of them at once.
+    #[arg(long, value_name = "SPP", value_parser = clap::value_parser!(u32).range(1..))]
+    pub pass_spp: Option<u32>,
+
+    /// Write the image, and the checkpoint if there is one, after every this many passes.
+    #[arg(long, value_name = "PASSES", value_parser = clap::value_parser!(u32).range(1..))]
+    pub save_every: Option<u32>,
+
+    /// Keep the progress of the render in this file, so that it can be resumed later.
+    #[arg(long, value_name = "FILE")]
+    pub checkpoint: Option<String>,
+
+    /// Continue the render in the checkpoint file, up to the number of samples per pixel of the
+    /// scene or --spp. The scene and its settings should be the same as before.
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+
+    /// How samples get spread over the pixels around them when reconstructing the image. Wider
+    /// filters smooth out jagged edges but blur, mitchell and lanczos stay sharper.
+    #[arg(long, value_enum, default_value_t = FilterKind::Box)]
+    pub filter: FilterKind,
+
+    /// How far in pixels the filter reaches from a sample, each filter has its own default.
+    #[arg(long, value_name = "PIXELS", value_parser = parse_radius)]
+    pub filter_radius: Option<f32>,
+
+    /// Maximum number of ray bounces.
+    #[arg(long)]
+    pub max_depth: Option<i32>,
+
+    /// Camera aperture, 0 disables depth of field.
+    #[arg(long)]
+    pub aperture: Option<f32>,
+
+    /// Vertical field of view in degrees.
+    #[arg(long)]
+    pub vfov: Option<f32>,
+
+    /// Print how long building the BVHs took, and how many boxes and primitives camera rays get
+    /// tested against, before rendering.
+    #[arg(long)]
+    pub bvh_stats: bool,
+
+    /// How to split the nodes of the BVHs, the surface area heuristic or the median split to
+    /// compare it with.
+    #[arg(long, value_enum, default_value_t = SplitMethod::Sah)]
+    pub bvh_split: SplitMethod,
+
+    /// Add an alpha channel to PNG and EXR images, which is transparent where the camera sees the
+    /// background.
+    #[arg(long)]
+    pub alpha: bool,
+
+    /// Extra passes to save along with the image, separated by commas. They become layers of EXR
+    /// images, or separate EXR files next to images in other formats.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub aov: Vec<Aov>,
+
+    /// Denoise the image, guided by the albedo and normals of what the camera sees. Takes an
+    /// optional strength, higher values blur more.
+    #[arg(
+        long,
+        value_name = "STRENGTH",
+        num_args = 0..=1,
+        default_missing_value = "1.0",
+        value_parser = parse_strength
+    )]
+    pub denoise: Option<f32>,
+
+    /// How to fit bright parts of the image into 8-bit output formats.
+    #[arg(long, value_enum, default_value_t = ToneMapper::Clamp)]
+    pub tonemap: ToneMapper,
+
+    /// Exposure compensation in stops (EV), applied before tone mapping.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub exposure: f32,
+
+    /// The luminance that becomes white with the extended-reinhard tone mapper.
+    #[arg(long, default_value_t = 4.0)]
+    pub white_point: f32,
+}
+
+impl Args {
+    pub fn nr_channels(&self) -> u32 {
+        if self.alpha {
+            4
+        } else {
+            3
+        }
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        ToneMapping {
+            operator: self.tonemap,
+            exposure: self.exposure,
+            white_point: self.white_point,
+        }
+    }
+
+    pub fn filter(&self) -> Filter {
+        Filter::new(self.filter, self.filter_radius)
+    }
+
+    /// The adaptive sampling settings, for a scene with `samples_per_pixel` samples per pixel.
+    pub fn adaptive_sampling(&self, samples_per_pixel: u32) -> Option<AdaptiveSampling> {
+        self.adaptive.map(|threshold| AdaptiveSampling {
+            threshold,
+            min_samples: samples_per_pixel,
+            max_samples: u32::max(
+                self.max_spp.unwrap_or(8 * samples_per_pixel),
+                samples_per_pixel,
+            ),
+        })
+    }
+
+    /// Overrides the scene's default render settings with the ones given on the command line.
+    pub fn apply_overrides(&self, scene: &mut SceneInfo) {
+        let aspect_ratio = scene.image_width as f32 / scene.image_height as f32;
+
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => {
+                scene.image_width = width;
+                scene.image_height = height;
+            }
+            (Some(width), None) => {
+                scene.image_width = width;
+                scene.image_height = u32::max((width as f32 / aspect_ratio) as u32, 1);
+            }
+            (None, Some(height)) => {
+                scene.image_width = u32::max((height as f32 * aspect_ratio) as u32, 1);
+                scene.image_height = height;
+            }
+            (None, None) => (),
+        }
+
+        if let Some(spp) = self.spp {
+            scene.samples_per_pixel = spp;
+        }
+        if let Some(sampler) = self.sampler {
+            scene.sampler = sampler;
+        }
+        if let Some(max_depth) = self.max_depth {
+            scene.max_depth = max_depth;
+        }
+        if let Some(aperture) = self.aperture {
+            scene.aperture = aperture;
+        }
+        if let Some(vfov) = self.vfov {
+            scene.vfov = vfov;
+        }
+    }
+}
+
+fn parse_strength(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(strength) if strength > 0.0 && strength.is_finite() => Ok(strength),
+        Ok(_) => Err("the strength has to be more than 0".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn parse_radius(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(radius) if radius > 0.0 && radius.is_finite() => Ok(radius),
+        Ok(_) => Err("the radius has to be more than 0".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(std::iter::once("rust_ray_tracing").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn rejects_empty_images_and_zero_samples() {
+        for flag in ["--width", "--height", "--spp"] {
+            assert!(parse(&[flag, "0"]).is_err(), "{} 0", flag);
+            assert!(parse(&[flag, "1"]).is_ok(), "{} 1", flag);
+        }
+    }
+
+    #[test]
+    fn width_alone_keeps_the_aspect_ratio() {
+        let mut scene = SceneInfo {
+            image_width: 400,
+            image_height: 200,
+            ..Default::default()
+        };
+        parse(&["--width", "100", "--spp", "8"])
+            .unwrap()
+            .apply_overrides(&mut scene);
+
+        assert_eq!((scene.image_width, scene.image_height), (100, 50));
+        assert_eq!(scene.samples_per_pixel, 8);
+    }
+
+    #[test]
+    fn denoise_strength_is_optional_but_has_to_be_positive() {
+        assert_eq!(parse(&[]).unwrap().denoise, None);
+        assert_eq!(parse(&["--denoise"]).unwrap().denoise, Some(1.0));
+        assert_eq!(parse(&["--denoise", "2.5"]).unwrap().denoise, Some(2.5));
+
+        for strength in ["0", "-1", "NaN", "inf", "strong"] {
+            assert!(
+                parse(&[&format!("--denoise={}", strength)]).is_err(),
+                "{}",
+                strength
+            );
+        }
+    }
+}
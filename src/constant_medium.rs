@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    hittable::{HitRecord, Hittable},
+    materials::Material,
+    ray::Ray,
+    utils::{random, INFINITY},
+    vec3::Vec3,
+};
+
+/// A volume of constant density, like smoke or fog, filling a closed boundary. Rays scatter off
+/// of the medium at a random distance inside of it, the denser the medium the sooner.
+pub struct ConstantMedium<M: Material> {
+    boundary: Arc<dyn Hittable>,
+    phase_function: M,
+    neg_inv_density: f32,
+}
+
+impl<M: Material> ConstantMedium<M> {
+    /// `phase_function` decides how light scatters in the medium, see `Isotropic`.
+    pub fn new(boundary: Arc<dyn Hittable>, density: f32, phase_function: M) -> Self {
+        Self {
+            boundary,
+            phase_function,
+            neg_inv_density: -1.0 / density,
+        }
+    }
+}
+
+/// The part of the ray between `t_min` and `t_max` that lies inside of `boundary`, as the `t` where
+/// it enters and the `t` where it leaves. Only works for convex boundaries.
+pub fn boundary_interval(
+    boundary: &dyn Hittable,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32)> {
+    // Find where the ray enters and leaves the boundary, even if that's behind the ray.
+    let t_enter = boundary.hit(ray, -INFINITY, INFINITY)?.t;
+    let t_exit = boundary.hit(ray, t_enter + 0.0001, INFINITY)?.t;
+
+    let t_enter = f32::max(t_enter, f32::max(t_min, 0.0));
+    let t_exit = f32::min(t_exit, t_max);
+    if t_enter >= t_exit {
+        return None;
+    }
+
+    Some((t_enter, t_exit))
+}
+
+impl<M: Material> Hittable for ConstantMedium<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t_enter, t_exit) = boundary_interval(&*self.boundary, ray, t_min, t_max)?;
+
+        let ray_length = ray.direction.length();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * random().ln();
+
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+
+        Some(HitRecord {
+            t,
+            p: ray.at(t),
+            uv: (0.0, 0.0),
+            // A volume has no surface, so these are arbitrary.
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            front_face: true,
+            mat: &self.phase_function,
+            object_id: 0,
+        })
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        match boundary_interval(&*self.boundary, ray, t_min, t_max) {
+            Some((t_enter, t_exit)) => {
+                let distance_inside_boundary = (t_exit - t_enter) * ray.direction.length();
+                f32::exp(distance_inside_boundary / self.neg_inv_density)
+            }
+            None => 1.0,
+        }
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aarect::Cuboid,
+        materials::Isotropic,
+        vec3::{Color, Point3},
+    };
+
+    fn cube(density: f32) -> ConstantMedium<Isotropic> {
+        let boundary = Arc::new(Cuboid::new(
+            Point3::from(-1.0),
+            Point3::from(1.0),
+            Isotropic::from_color(Color::from(1.0)),
+        ));
+        ConstantMedium::new(boundary, density, Isotropic::from_color(Color::from(1.0)))
+    }
+
+    #[test]
+    fn transmittance_falls_off_exponentially() {
+        const RAYS: usize = 20_000;
+
+        // The rays cross 2 units of the medium, so exp(-2 * density) of them make it through.
+        for density in [0.1, 0.5, 2.0] {
+            let medium = cube(density);
+            let ray = Ray::new(Point3::new(-5.0, 0.3, 0.2), Vec3::new(2.0, 0.0, 0.0), 0.0);
+
+            let mut through = 0;
+            for _ in 0..RAYS {
+                match medium.hit(&ray, 0.001, INFINITY) {
+                    Some(rec) => assert!((-1.0..=1.0).contains(&rec.p.x)),
+                    None => through += 1,
+                }
+            }
+
+            let transmittance = through as f32 / RAYS as f32;
+            assert!((transmittance - f32::exp(-2.0 * density)).abs() < 0.015);
+        }
+    }
+
+    #[test]
+    fn rays_inside_scatter_in_front_of_them() {
+        let medium = cube(100.0);
+        let ray = Ray::new(Point3::from(0.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        for _ in 0..100 {
+            let rec = medium.hit(&ray, 0.001, INFINITY).unwrap();
+            assert!(rec.t >= 0.001 && rec.t < 1.0);
+        }
+    }
+}
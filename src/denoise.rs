@@ -0,0 +1,307 @@
+use rayon::prelude::*;
+
+use crate::vec3::{Color, Vec3};
+
+/// Number of passes of the filter, every pass doubles the spacing of the taps.
+const ITERATIONS: u32 = 5;
+
+/// The B3 spline, the 1D kernel of every pass.
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// How much the color of neighbours may differ, after compressing it to `[0, 1)`, at strength 1.
+const COLOR_SIGMA: f32 = 0.25;
+
+/// How much the albedo of neighbours may differ.
+const ALBEDO_SIGMA: f32 = 0.05;
+
+/// Exponent for the cosine between the normals of neighbours, higher keeps edges sharper.
+const NORMAL_POWER: i32 = 64;
+
+/// How much brighter than all of its neighbours a pixel may be at strength 1, relative to the
+/// brightest of them, before it counts as a firefly.
+const FIREFLY_HEADROOM: f32 = 1.0;
+
+/// An edge-avoiding à-trous wavelet filter, from "Edge-Avoiding À-Trous Wavelet Transform for fast
+/// Global Illumination Filtering" by Dammertz et al. and as used in SVGF.
+///
+/// Every pass blurs the image with a 5x5 kernel whose taps spread out further every time, but
+/// neighbours only count as much as their color, albedo and normal resemble the pixel's. The
+/// albedo gets divided out before filtering and multiplied back in after, so that textures stay
+/// sharp. Fireflies get clamped closer to their neighbours first the stronger the filter is, which
+/// loses a bit of energy.
+pub struct Denoiser {
+    strength: f32,
+}
+
+/// What the filter knows about a pixel.
+#[derive(Clone, Copy)]
+struct GuidePixel {
+    albedo: Color,
+    normal: Vec3,
+}
+
+impl Denoiser {
+    /// With a `strength` of 1 most of the noise of a render with around 16 samples per pixel is
+    /// gone. Higher values blur more, lower values keep more detail, it has to be more than 0.
+    pub fn new(strength: f32) -> Self {
+        Self { strength }
+    }
+
+    /// Denoises `color`, in rows of `width` pixels, using the average `albedo` and `normal` of
+    /// every pixel as guides. Pixels that hit nothing should have a black albedo and a zero normal.
+    pub fn denoise(
+        &self,
+        width: usize,
+        color: &[Color],
+        albedo: &[Color],
+        normal: &[Vec3],
+    ) -> Vec<Color> {
+        let guides: Vec<GuidePixel> = albedo
+            .iter()
+            .zip(normal.iter())
+            .map(|(&albedo, &normal)| GuidePixel { albedo, normal })
+            .collect();
+
+        // Filter the light arriving at the surfaces, without the texture on them.
+        let mut illumination: Vec<Color> = color
+            .iter()
+            .zip(guides.iter())
+            .map(|(&c, guide)| demodulate(c, guide.albedo))
+            .collect();
+        suppress_fireflies(
+            width,
+            &mut illumination,
+            1.0 + FIREFLY_HEADROOM / self.strength,
+        );
+
+        for iteration in 0..ITERATIONS {
+            let step = 1 << iteration;
+            // Later passes only smooth out what's left, so they should leave the edges alone more.
+            let color_sigma = self.strength * COLOR_SIGMA / (1 << iteration) as f32;
+            illumination = self.pass(width, &illumination, &guides, step, color_sigma);
+        }
+
+        illumination
+            .iter()
+            .zip(guides.iter())
+            .map(|(&c, guide)| remodulate(c, guide.albedo))
+            .collect()
+    }
+
+    fn pass(
+        &self,
+        width: usize,
+        input: &[Color],
+        guides: &[GuidePixel],
+        step: usize,
+        color_sigma: f32,
+    ) -> Vec<Color> {
+        let height = input.len() / width;
+        let mut output = vec![Color::from(0.0); input.len()];
+
+        output
+            .par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, out) in row.iter_mut().enumerate() {
+                    let center = y * width + x;
+                    let center_color = compress(input[center]);
+
+                    let mut sum = Color::from(0.0);
+                    let mut weight_sum = 0.0;
+
+                    for (ky, ky_weight) in KERNEL.iter().enumerate() {
+                        let sy = y as isize + (ky as isize - 2) * step as isize;
+                        if sy < 0 || sy >= height as isize {
+                            continue;
+                        }
+
+                        for (kx, kx_weight) in KERNEL.iter().enumerate() {
+                            let sx = x as isize + (kx as isize - 2) * step as isize;
+                            if sx < 0 || sx >= width as isize {
+                                continue;
+                            }
+
+                            let sample = sy as usize * width + sx as usize;
+                            let weight = ky_weight
+                                * kx_weight
+                                * color_weight(center_color, compress(input[sample]), color_sigma)
+                                * guide_weight(&guides[center], &guides[sample]);
+
+                            sum += weight * input[sample];
+                            weight_sum += weight;
+                        }
+                    }
+
+                    // The center pixel always has a weight above zero.
+                    *out = sum / weight_sum;
+                }
+            });
+
+        output
+    }
+}
+
+/// Divides the albedo out of a color, where there is any.
+fn demodulate(color: Color, albedo: Color) -> Color {
+    let channel = |c: f32, a: f32| if a > 0.001 { c / a } else { c };
+    Color::new(
+        channel(color.x, albedo.x),
+        channel(color.y, albedo.y),
+        channel(color.z, albedo.z),
+    )
+}
+
+fn remodulate(illumination: Color, albedo: Color) -> Color {
+    let channel = |c: f32, a: f32| if a > 0.001 { c * a } else { c };
+    Color::new(
+        channel(illumination.x, albedo.x),
+        channel(illumination.y, albedo.y),
+        channel(illumination.z, albedo.z),
+    )
+}
+
+/// Darkens pixels that are more than `max_ratio` times as bright as all of their neighbours down to
+/// that. Fireflies are too different from their neighbours for the filter to blend them with
+/// anything, so they'd stay behind as bright specks.
+fn suppress_fireflies(width: usize, illumination: &mut [Color], max_ratio: f32) {
+    let height = illumination.len() / width;
+    let luminance: Vec<f32> = illumination.iter().map(Color::luminance).collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut max_neighbour = 0.0;
+            for sy in y.saturating_sub(1)..usize::min(y + 2, height) {
+                for sx in x.saturating_sub(1)..usize::min(x + 2, width) {
+                    if (sx, sy) != (x, y) {
+                        max_neighbour = f32::max(max_neighbour, luminance[sy * width + sx]);
+                    }
+                }
+            }
+
+            let center = luminance[y * width + x];
+            let limit = max_ratio * max_neighbour;
+            if center > limit {
+                illumination[y * width + x] *= limit / center;
+            }
+        }
+    }
+}
+
+/// Squeezes HDR colors into `[0, 1)`, so that bright lights don't throw off color differences.
+fn compress(color: Color) -> Color {
+    color.map(|c| c / (1.0 + c))
+}
+
+fn color_weight(center: Color, sample: Color, sigma: f32) -> f32 {
+    if sigma <= 0.0 {
+        return if (center - sample).near_zero() {
+            1.0
+        } else {
+            0.0
+        };
+    }
+
+    f32::exp(-(center - sample).length_squared() / (sigma * sigma))
+}
+
+fn guide_weight(center: &GuidePixel, sample: &GuidePixel) -> f32 {
+    let albedo_weight =
+        f32::exp(-(center.albedo - sample.albedo).length_squared() / (ALBEDO_SIGMA * ALBEDO_SIGMA));
+
+    // Pixels that hit nothing have no normal, they only blend with each other.
+    let normal_weight = match (center.normal.near_zero(), sample.normal.near_zero()) {
+        (true, true) => 1.0,
+        (false, false) => {
+            f32::max(Vec3::dot(&center.normal, &sample.normal), 0.0).powi(NORMAL_POWER)
+        }
+        _ => 0.0,
+    };
+
+    albedo_weight * normal_weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::random;
+
+    const WIDTH: usize = 32;
+
+    /// A gray image with noise on top, of a flat surface facing up.
+    fn noisy_image() -> (Vec<Color>, Vec<Color>, Vec<Vec3>) {
+        let color = (0..WIDTH * WIDTH)
+            .map(|_| Color::from(0.25 + 0.5 * random()))
+            .collect();
+        let albedo = vec![Color::from(0.5); WIDTH * WIDTH];
+        let normal = vec![Vec3::new(0.0, 1.0, 0.0); WIDTH * WIDTH];
+
+        (color, albedo, normal)
+    }
+
+    fn mean_and_variance(colors: &[Color]) -> (f32, f32) {
+        let n = colors.len() as f32;
+        let mean = colors.iter().map(|c| c.x).sum::<f32>() / n;
+        let variance = colors.iter().map(|c| (c.x - mean).powi(2)).sum::<f32>() / n;
+
+        (mean, variance)
+    }
+
+    #[test]
+    fn noise_gets_smoothed_out() {
+        let (color, albedo, normal) = noisy_image();
+        let (mean, variance) = mean_and_variance(&color);
+
+        let mut last_variance = variance;
+        for strength in [0.5, 1.0, 4.0] {
+            let denoised = Denoiser::new(strength).denoise(WIDTH, &color, &albedo, &normal);
+            let (denoised_mean, denoised_variance) = mean_and_variance(&denoised);
+
+            assert!(denoised_variance < last_variance, "{}", strength);
+            assert!((denoised_mean - mean).abs() < 0.05, "{}", strength);
+            last_variance = denoised_variance;
+        }
+    }
+
+    #[test]
+    fn edges_in_the_guides_stay_sharp() {
+        let (mut color, albedo, mut normal) = noisy_image();
+        // The right half is a bright wall, facing to the left.
+        for (i, (c, n)) in color.iter_mut().zip(normal.iter_mut()).enumerate() {
+            if i % WIDTH >= WIDTH / 2 {
+                *c += Color::from(4.0);
+                *n = Vec3::new(-1.0, 0.0, 0.0);
+            }
+        }
+
+        let denoised = Denoiser::new(4.0).denoise(WIDTH, &color, &albedo, &normal);
+        for row in denoised.chunks(WIDTH) {
+            assert!(row[WIDTH / 2 - 1].x < 1.0);
+            assert!(row[WIDTH / 2].x > 4.0);
+        }
+    }
+
+    #[test]
+    fn fireflies_get_clamped_more_the_stronger_the_filter_is() {
+        let firefly = |strength: f32| {
+            let mut illumination = vec![Color::from(1.0); 9];
+            illumination[4] = Color::from(100.0);
+            suppress_fireflies(3, &mut illumination, 1.0 + FIREFLY_HEADROOM / strength);
+
+            // Only the firefly itself changes.
+            assert!(illumination.iter().filter(|c| c.x == 1.0).count() == 8);
+            illumination[4].x
+        };
+
+        assert!((firefly(1.0) - 2.0).abs() < 1e-5);
+        assert!(firefly(0.01) > firefly(1.0));
+        assert!(firefly(100.0) < firefly(1.0));
+        assert!(firefly(100.0) > 1.0);
+
+        // Pixels that aren't brighter than that are left alone.
+        let mut illumination = vec![Color::from(1.0); 9];
+        illumination[4] = Color::from(1.5);
+        suppress_fireflies(3, &mut illumination, 2.0);
+        assert_eq!(illumination[4].x, 1.5);
+    }
+}
@@ -0,0 +1,200 @@
+use std::{fs, io, path::Path};
+
+use crate::{perlin::Perlin, utils::clamp, vec3::Point3};
+
+/// How dense a heterogeneous volume is at every point in space.
+pub trait DensityField: Send + Sync {
+    fn density(&self, p: &Point3) -> f32;
+
+    /// An upper bound of `density` everywhere, the tighter it is the faster the volume renders.
+    fn max_density(&self) -> f32;
+}
+
+/// Billowing clouds made from Perlin turbulence.
+pub struct NoiseDensity {
+    noise: Perlin,
+    scale: f32,
+    density: f32,
+}
+
+impl NoiseDensity {
+    /// `density` is the density of the thickest parts, `scale` the frequency of the noise.
+    pub fn new(scale: f32, density: f32) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+            density,
+        }
+    }
+}
+
+impl DensityField for NoiseDensity {
+    fn density(&self, p: &Point3) -> f32 {
+        self.density * clamp(self.noise.turb(&(self.scale * *p)), 0.0, 1.0)
+    }
+
+    fn max_density(&self) -> f32 {
+        self.density
+    }
+}
+
+/// Densities stored on a regular grid of voxels filling the box between `minimum` and `maximum`,
+/// interpolated in between the voxel centers. Outside of the box the density is zero.
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    densities: Vec<f32>,
+    minimum: Point3,
+    maximum: Point3,
+    max_density: f32,
+}
+
+impl VoxelGrid {
+    /// `densities` is ordered with x changing fastest, then y, then z. Negative densities are
+    /// clamped to zero. Panics if there isn't exactly one density per voxel.
+    pub fn new(resolution: [usize; 3], densities: Vec<f32>, p0: Point3, p1: Point3) -> Self {
+        assert_eq!(
+            densities.len(),
+            resolution.iter().product::<usize>(),
+            "Voxel grid needs one density per voxel!"
+        );
+
+        let densities: Vec<f32> = densities.into_iter().map(|d| f32::max(d, 0.0)).collect();
+        let max_density = densities.iter().copied().fold(0.0, f32::max);
+
+        Self {
+            resolution,
+            densities,
+            minimum: Point3::new(
+                f32::min(p0.x, p1.x),
+                f32::min(p0.y, p1.y),
+                f32::min(p0.z, p1.z),
+            ),
+            maximum: Point3::new(
+                f32::max(p0.x, p1.x),
+                f32::max(p0.y, p1.y),
+                f32::max(p0.z, p1.z),
+            ),
+            max_density,
+        }
+    }
+
+    /// Loads a file of raw little-endian 32-bit floats, in the order `new` expects.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        resolution: [usize; 3],
+        p0: Point3,
+        p1: Point3,
+    ) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        let voxel_count: usize = resolution.iter().product();
+        if voxel_count == 0 || bytes.len() != voxel_count * 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "expected {} voxels ({} bytes), but the file is {} bytes",
+                    voxel_count,
+                    voxel_count * 4,
+                    bytes.len()
+                ),
+            ));
+        }
+
+        let densities = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        Ok(Self::new(resolution, densities, p0, p1))
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        let [nx, ny, _] = self.resolution;
+        self.densities[x + nx * (y + ny * z)]
+    }
+}
+
+impl DensityField for VoxelGrid {
+    fn density(&self, p: &Point3) -> f32 {
+        let mut index = [0; 3];
+        let mut weight = [0.0; 3];
+
+        for axis in 0..3 {
+            if p[axis] < self.minimum[axis] || p[axis] > self.maximum[axis] {
+                return 0.0;
+            }
+
+            // Position in voxels, relative to the center of the first voxel.
+            let n = self.resolution[axis];
+            let extent = self.maximum[axis] - self.minimum[axis];
+            let x = (p[axis] - self.minimum[axis]) / extent * n as f32 - 0.5;
+            let x = clamp(x, 0.0, (n - 1) as f32);
+
+            index[axis] = usize::min(x as usize, n.saturating_sub(2));
+            weight[axis] = if n > 1 { x - index[axis] as f32 } else { 0.0 };
+        }
+
+        let [x, y, z] = index;
+        let next = |axis: usize, i: usize| usize::min(i + 1, self.resolution[axis] - 1);
+        let (x1, y1, z1) = (next(0, x), next(1, y), next(2, z));
+        let [wx, wy, wz] = weight;
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let c00 = lerp(self.voxel(x, y, z), self.voxel(x1, y, z), wx);
+        let c10 = lerp(self.voxel(x, y1, z), self.voxel(x1, y1, z), wx);
+        let c01 = lerp(self.voxel(x, y, z1), self.voxel(x1, y, z1), wx);
+        let c11 = lerp(self.voxel(x, y1, z1), self.voxel(x1, y1, z1), wx);
+
+        lerp(lerp(c00, c10, wy), lerp(c01, c11, wy), wz)
+    }
+
+    fn max_density(&self) -> f32 {
+        self.max_density
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voxels_get_interpolated_between_their_centers() {
+        let grid = VoxelGrid::new(
+            [2, 1, 1],
+            vec![-1.0, 1.0],
+            Point3::new(2.0, 1.0, 1.0),
+            Point3::new(0.0, 0.0, 0.0),
+        );
+        let density = |x: f32| grid.density(&Point3::new(x, 0.5, 0.5));
+
+        assert_eq!(grid.max_density(), 1.0);
+        // The negative density is clamped, and the voxel values hold from their centers outwards.
+        assert_eq!(density(0.2), 0.0);
+        assert_eq!(density(0.5), 0.0);
+        assert!((density(1.0) - 0.5).abs() < 1e-6);
+        assert!((density(1.25) - 0.75).abs() < 1e-6);
+        assert_eq!(density(1.9), 1.0);
+        assert_eq!(density(2.1), 0.0);
+        assert_eq!(grid.density(&Point3::new(1.0, 1.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn voxel_files_need_one_float_per_voxel() {
+        let path = std::env::temp_dir().join("density_test_voxels.raw");
+        let bytes: Vec<u8> = [0.25f32, 0.5, 0.75]
+            .iter()
+            .flat_map(|d| d.to_le_bytes())
+            .collect();
+        fs::write(&path, bytes).unwrap();
+
+        let (p0, p1) = (Point3::from(0.0), Point3::from(1.0));
+        let grid = VoxelGrid::load(&path, [3, 1, 1], p0, p1).unwrap();
+        assert_eq!(grid.max_density(), 0.75);
+        assert!((grid.density(&Point3::new(0.5, 0.5, 0.5)) - 0.5).abs() < 1e-6);
+
+        let err = VoxelGrid::load(&path, [2, 2, 1], p0, p1).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+/// A piecewise constant distribution over `[0, 1)`, proportional to a list of function values.
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    /// Negative values count as zero. If all values are zero, the distribution is uniform.
+    pub fn new(func: Vec<f32>) -> Self {
+        let n = func.len();
+        let func: Vec<f32> = func.into_iter().map(|f| f32::max(f, 0.0)).collect();
+
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for (i, f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f / n as f32);
+        }
+
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f32 / n as f32
+            };
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// The average of the function values.
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Turns `u` in `[0, 1)` into a sample, returned along with its density and the index of the
+    /// piece it's in.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let index = usize::min(
+            self.cdf.partition_point(|&c| c <= u).saturating_sub(1),
+            self.count() - 1,
+        );
+
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let du = if width > 0.0 {
+            (u - self.cdf[index]) / width
+        } else {
+            0.0
+        };
+
+        let x = f32::min(
+            (index as f32 + du) / self.count() as f32,
+            1.0 - f32::EPSILON,
+        );
+        (x, self.pdf(index), index)
+    }
+
+    /// Density of the samples in the piece at `index`.
+    pub fn pdf(&self, index: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[index] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A piecewise constant distribution over `[0, 1)²`, proportional to a grid of function values.
+pub struct Distribution2D {
+    /// The distribution within every row.
+    rows: Vec<Distribution1D>,
+    /// The distribution of the rows themselves.
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` is in row-major order, with `width` values per row.
+    pub fn new(func: &[f32], width: usize) -> Self {
+        let rows: Vec<Distribution1D> = func
+            .chunks_exact(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+
+        Self { rows, marginal }
+    }
+
+    /// Turns `u` in `[0, 1)²` into a sample `(x, y)`, returned along with its density.
+    pub fn sample(&self, u: (f32, f32)) -> ((f32, f32), f32) {
+        let (y, row_pdf, row) = self.marginal.sample(u.1);
+        let (x, column_pdf, _) = self.rows[row].sample(u.0);
+
+        ((x, y), row_pdf * column_pdf)
+    }
+
+    pub fn pdf(&self, p: (f32, f32)) -> f32 {
+        let row = usize::min((p.1 * self.rows.len() as f32) as usize, self.rows.len() - 1);
+        let columns = &self.rows[row];
+        let column = usize::min((p.0 * columns.count() as f32) as usize, columns.count() - 1);
+
+        self.marginal.pdf(row) * columns.pdf(column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_follow_the_function() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0, -2.0]);
+        assert_eq!(distribution.integral(), 1.0);
+
+        // The first quarter of the samples land in the first piece, the rest in the third.
+        let (x, pdf, index) = distribution.sample(0.125);
+        assert_eq!(index, 0);
+        assert!((x - 0.125).abs() < 1e-6);
+        assert_eq!(pdf, 1.0);
+
+        let (x, pdf, index) = distribution.sample(0.625);
+        assert_eq!(index, 2);
+        assert!((x - 0.625).abs() < 1e-6);
+        assert_eq!(pdf, 3.0);
+
+        assert_eq!(distribution.sample(0.999).2, 2);
+        assert_eq!(distribution.pdf(1), 0.0);
+        assert_eq!(distribution.pdf(3), 0.0);
+    }
+
+    #[test]
+    fn all_zero_functions_are_uniform() {
+        let distribution = Distribution1D::new(vec![0.0; 4]);
+
+        let (x, pdf, index) = distribution.sample(0.6);
+        assert!((x - 0.6).abs() < 1e-6);
+        assert_eq!((pdf, index), (1.0, 2));
+    }
+
+    #[test]
+    fn grids_sample_rows_and_then_columns() {
+        // All the weight is in the bottom right cell, and a bit in the top left one.
+        let distribution = Distribution2D::new(&[1.0, 0.0, 0.0, 3.0], 2);
+
+        let ((x, y), pdf) = distribution.sample((0.5, 0.9));
+        assert!(x >= 0.5 && y >= 0.5);
+        assert_eq!(pdf, distribution.pdf((x, y)));
+        assert!((pdf - 3.0).abs() < 1e-5);
+
+        let ((x, y), pdf) = distribution.sample((0.5, 0.1));
+        assert!(x < 0.5 && y < 0.5);
+        assert!((pdf - 1.0).abs() < 1e-5);
+
+        assert_eq!(distribution.pdf((0.75, 0.25)), 0.0);
+    }
+}
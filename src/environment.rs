@@ -0,0 +1,244 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use image::{codecs::hdr::HdrDecoder, ImageResult};
+
+use crate::{
+    distribution::Distribution2D,
+    lights::{Light, LightSample},
+    mat4::Mat4,
+    pdf::Pdf,
+    utils::{clamp, random_2d, INFINITY, PI},
+    vec3::{Color, Point3, Vec3},
+};
+
+/// Light coming from infinitely far away in every direction, looked up in an equirectangular
+/// (latitude-longitude) image. The top of the image is straight up, and the center of the image
+/// lies towards -z.
+///
+/// Directions get importance sampled by how bright the image is there, so that a small but bright
+/// sun in the image doesn't turn into fireflies.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    intensity: f32,
+    /// From world space directions to directions in the image, and back.
+    to_local: Mat4,
+    to_world: Mat4,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// `pixels` is in row-major order, starting at the top of the image.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        // The rows near the poles get squished together on the sphere, so they're less likely to
+        // be picked than their brightness alone would say.
+        let weights: Vec<f32> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, pixel)| {
+                let theta = PI * ((i / width) as f32 + 0.5) / height as f32;
+                pixel.luminance() * f32::sin(theta)
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+            intensity: 1.0,
+            to_local: Mat4::identity(),
+            to_world: Mat4::identity(),
+            distribution: Distribution2D::new(&weights, width),
+        }
+    }
+
+    /// Loads a Radiance `.hdr` or OpenEXR image, or anything else the `image` crate can read.
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let path = path.as_ref();
+        let is_hdr = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+
+        // The `image` crate only gives out 8-bit versions of .hdr files, unless asked directly.
+        if is_hdr {
+            let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder
+                .read_image_hdr()?
+                .into_iter()
+                .map(|rgb| Color::new(rgb[0], rgb[1], rgb[2]))
+                .collect();
+
+            return Ok(Self::new(
+                metadata.width as usize,
+                metadata.height as usize,
+                pixels,
+            ));
+        }
+
+        let img = image::open(path)?.into_rgb32f();
+        let pixels = img
+            .pixels()
+            .map(|rgb| Color::new(rgb[0], rgb[1], rgb[2]))
+            .collect();
+
+        Ok(Self::new(
+            img.width() as usize,
+            img.height() as usize,
+            pixels,
+        ))
+    }
+
+    /// Rotates the environment counter-clockwise around the y axis, when looking down from above.
+    pub fn with_rotation(mut self, degrees: f32) -> Self {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        self.to_world = Mat4::rotation(up, degrees);
+        self.to_local = Mat4::rotation(up, -degrees);
+        self
+    }
+
+    /// Scales the brightness of the image.
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// The light arriving from `direction`.
+    pub fn value(&self, direction: &Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+
+        let i = usize::min((u * self.width as f32) as usize, self.width - 1);
+        let j = usize::min((v * self.height as f32) as usize, self.height - 1);
+
+        self.intensity * self.pixels[j * self.width + i]
+    }
+
+    /// Where `direction` ends up in the image, with `(0, 0)` the top left corner.
+    fn direction_to_uv(&self, direction: &Vec3) -> (f32, f32) {
+        let d = Vec3::unit_vector(self.to_local.transform_vector(direction));
+
+        let theta = f32::acos(clamp(d.y, -1.0, 1.0));
+        let phi = f32::atan2(d.x, -d.z);
+
+        (0.5 + phi / (2.0 * PI), theta / PI)
+    }
+
+    fn uv_to_direction(&self, uv: (f32, f32)) -> Vec3 {
+        let theta = uv.1 * PI;
+        let phi = (uv.0 - 0.5) * 2.0 * PI;
+
+        let (sin_theta, cos_theta) = f32::sin_cos(theta);
+        let (sin_phi, cos_phi) = f32::sin_cos(phi);
+        let d = Vec3::new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi);
+
+        self.to_world.transform_vector(&d)
+    }
+
+    /// Density over solid angle of picking `direction`.
+    fn pdf_value(&self, direction: &Vec3) -> f32 {
+        let uv = self.direction_to_uv(direction);
+
+        // The image gets wrapped around the sphere, which stretches every pixel by sin(theta).
+        let sin_theta = f32::sin(uv.1 * PI);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        self.distribution.pdf(uv) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random_direction(&self) -> Vec3 {
+        let (uv, _) = self.distribution.sample(random_2d());
+        self.uv_to_direction(uv)
+    }
+}
+
+impl Light for EnvironmentMap {
+    fn sample(&self, _p: &Point3, _time: f32) -> Option<LightSample> {
+        let direction = Vec3::unit_vector(self.random_direction());
+
+        let pdf = self.pdf_value(&direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance: INFINITY,
+            radiance: self.value(&direction),
+            pdf,
+        })
+    }
+
+    fn pdf(&self, _origin: &Point3) -> Box<dyn Pdf + '_> {
+        Box::new(EnvironmentPdf { map: self })
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+}
+
+/// The directions `EnvironmentMap` picks, the same from everywhere in the scene.
+struct EnvironmentPdf<'a> {
+    map: &'a EnvironmentMap,
+}
+
+impl Pdf for EnvironmentPdf<'_> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        self.map.pdf_value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.map.random_direction()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dim 8×4 map with a single bright pixel.
+    fn map_with_sun() -> EnvironmentMap {
+        let mut pixels = vec![Color::from(0.1); 32];
+        pixels[8 + 5] = Color::from(100.0);
+        EnvironmentMap::new(8, 4, pixels)
+    }
+
+    #[test]
+    fn pdf_is_normalized_and_matches_the_samples() {
+        let map = map_with_sun();
+
+        const SAMPLES: usize = 200_000;
+        let total: f32 = (0..SAMPLES)
+            .map(|_| map.pdf_value(&Vec3::random_unit_vector()))
+            .sum();
+        assert!((4.0 * PI * total / SAMPLES as f32 - 1.0).abs() < 0.03);
+
+        // Nearly all the samples go towards the sun.
+        let sun = map.value(&map.uv_to_direction((5.5 / 8.0, 1.5 / 4.0)));
+        let mut towards_sun = 0;
+        for _ in 0..1000 {
+            let sample = map.sample(&Point3::from(0.0), 0.0).unwrap();
+            assert!((sample.pdf - map.pdf_value(&sample.direction)).abs() < 1e-3 * sample.pdf);
+            if sample.radiance.x == sun.x {
+                towards_sun += 1;
+            }
+        }
+        assert!(towards_sun > 900);
+    }
+
+    #[test]
+    fn rotation_and_intensity_apply_to_lookups_and_samples() {
+        let map = map_with_sun();
+        let sun = map.uv_to_direction((5.5 / 8.0, 1.5 / 4.0));
+
+        let rotated = map_with_sun().with_rotation(90.0).with_intensity(2.0);
+        let turned = Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), 90.0).transform_vector(&sun);
+
+        assert_eq!(rotated.value(&turned).x, 200.0);
+        assert!((rotated.pdf_value(&turned) - map.pdf_value(&sun)).abs() < 1e-3);
+        assert_eq!(rotated.value(&sun).x, 0.2);
+    }
+}
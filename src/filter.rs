@@ -0,0 +1,185 @@
+use clap::ValueEnum;
+
+use crate::utils::PI;
+
+/// The shape of a `Filter`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FilterKind {
+    /// Every sample only counts for the pixels it's within the radius of, all the same.
+    Box,
+    /// Weights fall off linearly towards the radius.
+    Tent,
+    /// A Gaussian with a standard deviation of a third of the radius, shifted down to reach zero at
+    /// the radius.
+    Gaussian,
+    /// The Mitchell-Netravali cubic with B = C = 1/3, sharper than the Gaussian.
+    Mitchell,
+    /// A sinc windowed by a wider sinc, the sharpest, but it can ring around hard edges.
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn default_radius(&self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+/// How samples get spread over the pixels around them. Every sample adds to all the pixels whose
+/// centers are within the radius, weighted by their distance to it, and every pixel is the
+/// weighted average of what it got. The weights are the product of the filter in x and in y.
+///
+/// Wider filters smooth out aliasing but blur, the Mitchell and Lanczos filters keep things sharper
+/// with negative weights further out. A box with a radius of 0.5 keeps every sample in its own
+/// pixel.
+#[derive(Clone, Copy, Debug)]
+pub struct Filter {
+    kind: FilterKind,
+    radius: f32,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::Box, None)
+    }
+}
+
+impl Filter {
+    /// A `kind` filter with `radius` in pixels, or the default radius for the kind.
+    pub fn new(kind: FilterKind, radius: Option<f32>) -> Self {
+        Self {
+            kind,
+            radius: radius.unwrap_or_else(|| kind.default_radius()),
+        }
+    }
+
+    /// How many pixels a sample reaches beyond the one it was taken for, on every side.
+    pub fn margin(&self) -> u32 {
+        f32::max(f32::ceil(self.radius - 0.5), 0.0) as u32
+    }
+
+    /// The weight along one axis of a sample `x` pixels away from the center of a pixel. The weight
+    /// of the sample is the product of those in x and in y.
+    pub fn evaluate(&self, x: f32) -> f32 {
+        let r = self.radius;
+        match self.kind {
+            // Half open, so that samples right on the border between pixels only land in one.
+            FilterKind::Box => {
+                if -r < x && x <= r {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            FilterKind::Tent => f32::max(1.0 - f32::abs(x) / r, 0.0),
+            FilterKind::Gaussian => {
+                let sigma = r / 3.0;
+                let gaussian = |x: f32| f32::exp(-x * x / (2.0 * sigma * sigma));
+                f32::max(gaussian(x) - gaussian(r), 0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * f32::abs(x) / r),
+            FilterKind::Lanczos => {
+                if f32::abs(x) < r {
+                    sinc(x) * sinc(x / r)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// The Mitchell-Netravali cubic, which reaches zero at `x` = 2.
+fn mitchell(x: f32) -> f32 {
+    const B: f32 = 1.0 / 3.0;
+    const C: f32 = 1.0 / 3.0;
+
+    let polynomial = if x >= 2.0 {
+        0.0
+    } else if x >= 1.0 {
+        (-B - 6.0 * C) * x * x * x
+            + (6.0 * B + 30.0 * C) * x * x
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    } else {
+        (12.0 - 9.0 * B - 6.0 * C) * x * x * x
+            + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+            + (6.0 - 2.0 * B)
+    };
+
+    polynomial / 6.0
+}
+
+fn sinc(x: f32) -> f32 {
+    if f32::abs(x) < 1e-5 {
+        return 1.0;
+    }
+
+    f32::sin(PI * x) / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The total weight a sample at `x` gives to the pixel centers around it.
+    fn total_weight(filter: &Filter, x: f32) -> f32 {
+        let margin = filter.margin() as i32;
+        (-margin..=margin)
+            .map(|pixel| filter.evaluate(x - pixel as f32))
+            .sum()
+    }
+
+    #[test]
+    fn samples_only_reach_the_pixels_within_the_margin() {
+        for kind in [
+            FilterKind::Box,
+            FilterKind::Tent,
+            FilterKind::Gaussian,
+            FilterKind::Mitchell,
+            FilterKind::Lanczos,
+        ] {
+            let filter = Filter::new(kind, None);
+            let reach = filter.margin() as f32 + 0.5;
+            assert!(filter.evaluate(0.0) > 0.0, "{:?}", kind);
+            assert_eq!(filter.evaluate(reach + 0.01), 0.0, "{:?}", kind);
+            assert_eq!(filter.evaluate(-reach - 0.01), 0.0, "{:?}", kind);
+        }
+
+        assert_eq!(Filter::default().margin(), 0);
+        assert_eq!(Filter::new(FilterKind::Mitchell, None).margin(), 2);
+        assert_eq!(Filter::new(FilterKind::Gaussian, Some(0.75)).margin(), 1);
+    }
+
+    #[test]
+    fn box_and_tent_weights_add_up_to_one_anywhere() {
+        let filters = [Filter::default(), Filter::new(FilterKind::Tent, None)];
+        for filter in filters {
+            for x in [-0.49, -0.3, 0.0, 0.25, 0.5] {
+                let total = total_weight(&filter, x);
+                assert!(
+                    (total - 1.0).abs() < 1e-6,
+                    "{:?} at {}: {}",
+                    filter,
+                    x,
+                    total
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mitchell_and_lanczos_have_negative_lobes() {
+        let mitchell = Filter::new(FilterKind::Mitchell, None);
+        let lanczos = Filter::new(FilterKind::Lanczos, None);
+        assert!(mitchell.evaluate(1.5) < 0.0);
+        assert!(lanczos.evaluate(1.5) < 0.0);
+        // Still, the sample's own pixel gets most of it.
+        assert!(mitchell.evaluate(0.0) > 0.5 * total_weight(&mitchell, 0.0));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
     aabb::AABB,
+    bvh::TraversalStats,
     materials::Material,
     ray::Ray,
     vec3::{Point3, Vec3},
@@ -12,6 +13,9 @@ pub struct HitRecord<'a> {
     pub normal: Vec3,
     pub mat: &'a dyn Material,
     pub front_face: bool,
+    /// Which of the scene's objects got hit, for the object ID pass. Zero unless the object was
+    /// given an ID with `HittableList::with_object_ids`.
+    pub object_id: u32,
 }
 
 impl<'a> HitRecord<'a> {
@@ -25,7 +29,61 @@ impl<'a> HitRecord<'a> {
     }
 }
 
-pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+/// Converts the density of uniformly picking a point on a flat surface with the given `area`, to a
+/// density over solid angle. The point is where a ray along `direction` hits the surface at `t`.
+pub fn solid_angle_pdf(direction: &Vec3, t: f32, normal: &Vec3, area: f32) -> f32 {
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = f32::abs(Vec3::dot(direction, normal) / direction.length());
+    if cosine <= 0.0 {
+        return 0.0;
+    }
+
+    distance_squared / (cosine * area)
+}
+
+pub trait Hittable: Send + Sync {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    /// Returns whether the ray hits anything between `t_min` and `t_max`, without looking for the
+    /// closest hit. Meant for shadow rays.
+    fn hit_any(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.hit(ray, t_min, t_max).is_some()
+    }
+
+    /// Same as `hit`, but counts the boxes and primitives the ray gets tested against in `stats`,
+    /// to compare BVHs with. Only what holds other objects needs to override it, anything else is
+    /// a single primitive.
+    fn hit_counting(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord<'_>> {
+        stats.primitive_tests += 1;
+        self.hit(ray, t_min, t_max)
+    }
+
+    /// Fraction of the light along the ray between `t_min` and `t_max` that makes it through. Solid
+    /// objects either block the ray or they don't, but volumes can let part of the light through.
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        if self.hit_any(ray, t_min, t_max) {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB>;
+
+    /// Probability density (over solid angle) of `random` picking `direction` from `origin`. Only
+    /// shapes that can be used as lights need to implement this and `random`.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f32 {
+        0.0
+    }
+
+    /// Picks a random direction from `origin` towards a point on the surface.
+    fn random(&self, _origin: &Point3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
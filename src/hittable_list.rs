@@ -1,13 +1,15 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
     aabb::AABB,
+    bvh::TraversalStats,
     hittable::{HitRecord, Hittable},
     ray::Ray,
+    vec3::{Point3, Vec3},
 };
 
 pub struct HittableList {
-    pub objects: Vec<Rc<dyn Hittable>>,
+    pub objects: Vec<Arc<dyn Hittable>>,
 }
 
 impl HittableList {
@@ -17,7 +19,7 @@ impl HittableList {
         }
     }
 
-    pub fn from(obj: Rc<dyn Hittable>) -> Self {
+    pub fn from(obj: Arc<dyn Hittable>) -> Self {
         let mut this = Self {
             objects: Vec::new(),
         };
@@ -27,55 +29,141 @@ impl HittableList {
         this
     }
 
-    pub fn clear(&mut self) {
-        self.objects.clear();
+    pub fn add(&mut self, obj: Arc<dyn Hittable>) {
+        self.objects.push(obj);
     }
 
-    pub fn add(&mut self, obj: Rc<dyn Hittable>) {
-        self.objects.push(obj);
+    /// Numbers the objects in the list from 1, in the order they were added. Hits on them report
+    /// that as their `object_id`, also when they end up in a `BVH`.
+    pub fn with_object_ids(self) -> Self {
+        let objects = self
+            .objects
+            .into_iter()
+            .enumerate()
+            .map(|(i, object)| {
+                Arc::new(WithObjectId {
+                    object,
+                    id: i as u32 + 1,
+                }) as Arc<dyn Hittable>
+            })
+            .collect();
+
+        Self { objects }
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut hit_anything: Option<HitRecord> = None;
         let mut closest_so_far = t_max;
 
         for object in self.objects.iter() {
-            match object.hit(ray, t_min, closest_so_far) {
-                Some(hit) => {
-                    closest_so_far = hit.t;
-                    hit_anything = Some(hit);
-                }
-                _ => (),
+            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                hit_anything = Some(hit);
             }
         }
 
         hit_anything
     }
 
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
-        if self.objects.is_empty() {
-            return None;
+    fn hit_any(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.objects
+            .iter()
+            .any(|object| object.hit_any(ray, t_min, t_max))
+    }
+
+    fn hit_counting(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord<'_>> {
+        let mut hit_anything: Option<HitRecord> = None;
+        let mut closest_so_far = t_max;
+
+        for object in self.objects.iter() {
+            if let Some(hit) = object.hit_counting(ray, t_min, closest_so_far, stats) {
+                closest_so_far = hit.t;
+                hit_anything = Some(hit);
+            }
         }
 
-        let temp_box = AABB::empty();
-        let mut output_box = AABB::empty();
-        let mut first_box = true;
+        hit_anything
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let mut transmittance = 1.0;
 
         for object in self.objects.iter() {
-            if object.bounding_box(t0, t1).is_none() {
-                return None;
+            transmittance *= object.transmittance(ray, t_min, t_max);
+            if transmittance <= 0.0 {
+                return 0.0;
             }
+        }
+
+        transmittance
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        let mut output_box: Option<AABB> = None;
+
+        for object in self.objects.iter() {
+            let temp_box = object.bounding_box(t0, t1)?;
 
-            output_box = if first_box {
-                temp_box.clone()
-            } else {
-                AABB::surrounding_box(&output_box, &temp_box)
+            output_box = match output_box {
+                Some(output_box) => Some(AABB::surrounding_box(&output_box, &temp_box)),
+                None => Some(temp_box),
             };
-            first_box = false;
         }
 
-        Some(output_box)
+        output_box
+    }
+}
+
+/// Stamps every hit on `object` with its object ID.
+struct WithObjectId {
+    object: Arc<dyn Hittable>,
+    id: u32,
+}
+
+impl Hittable for WithObjectId {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut rec = self.object.hit(ray, t_min, t_max)?;
+        rec.object_id = self.id;
+        Some(rec)
+    }
+
+    fn hit_counting(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord<'_>> {
+        let mut rec = self.object.hit_counting(ray, t_min, t_max, stats)?;
+        rec.object_id = self.id;
+        Some(rec)
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.object.hit_any(ray, t_min, t_max)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        self.object.transmittance(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.object.bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.object.random(origin)
     }
 }
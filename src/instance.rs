@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    bvh::TraversalStats,
+    hittable::{HitRecord, Hittable},
+    mat4::Mat4,
+    ray::Ray,
+    utils::{max, min},
+    vec3::{Point3, Vec3},
+};
+
+/// Places an object in the scene with an affine transform. The same object can be shared by many
+/// instances, each with its own placement.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    /// From object space to world space.
+    transform: Mat4,
+    inverse: Mat4,
+}
+
+impl Instance {
+    /// Panics if `transform` can't be inverted.
+    pub fn new(object: Arc<dyn Hittable>, transform: Mat4) -> Self {
+        let inverse = transform
+            .inverse()
+            .expect("Instance transform can't be inverted!");
+
+        Self {
+            object,
+            transform,
+            inverse,
+        }
+    }
+
+    /// The ray in object space. The direction isn't normalized, so distances along the ray stay
+    /// the same in both spaces.
+    fn to_object_space(&self, ray: &Ray) -> Ray {
+        Ray::new(
+            self.inverse.transform_point(&ray.origin),
+            self.inverse.transform_vector(&ray.direction),
+            ray.time,
+        )
+    }
+
+    /// Moves a hit on the object back into world space.
+    fn to_world_space<'a>(&self, mut rec: HitRecord<'a>) -> HitRecord<'a> {
+        rec.p = self.transform.transform_point(&rec.p);
+        rec.normal = Vec3::unit_vector(self.inverse.transform_normal(&rec.normal));
+
+        rec
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.object
+            .hit(&self.to_object_space(ray), t_min, t_max)
+            .map(|rec| self.to_world_space(rec))
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.object
+            .hit_any(&self.to_object_space(ray), t_min, t_max)
+    }
+
+    fn hit_counting(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord<'_>> {
+        self.object
+            .hit_counting(&self.to_object_space(ray), t_min, t_max, stats)
+            .map(|rec| self.to_world_space(rec))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        self.object
+            .transmittance(&self.to_object_space(ray), t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        let object_box = self.object.bounding_box(t0, t1)?;
+
+        let mut minimum = Point3::from(f32::INFINITY);
+        let mut maximum = Point3::from(f32::NEG_INFINITY);
+
+        // The bits of `i` pick the minimum or maximum on every axis.
+        for i in 0..8 {
+            let pick = |bit: usize, axis: usize| {
+                if i & bit == 0 {
+                    object_box.minimum[axis]
+                } else {
+                    object_box.maximum[axis]
+                }
+            };
+            let corner = Point3::new(pick(1, 0), pick(2, 1), pick(4, 2));
+            let corner = self.transform.transform_point(&corner);
+
+            minimum = Point3::new(
+                min(minimum.x, corner.x),
+                min(minimum.y, corner.y),
+                min(minimum.z, corner.z),
+            );
+            maximum = Point3::new(
+                max(maximum.x, corner.x),
+                max(maximum.y, corner.y),
+                max(maximum.z, corner.z),
+            );
+        }
+
+        Some(AABB::new(minimum, maximum))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        let direction = Vec3::unit_vector(*direction);
+        let object_direction = self.inverse.transform_vector(&direction);
+        let object_pdf = self
+            .object
+            .pdf_value(&self.inverse.transform_point(origin), &object_direction);
+
+        // Solid angles get stretched along with space, this is how much for a single direction.
+        object_pdf * self.inverse.determinant().abs() / object_direction.length().powi(3)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let object_direction = self.object.random(&self.inverse.transform_point(origin));
+        self.transform.transform_vector(&object_direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aarect::XZRect, materials::Lambertian, objects::Sphere, vec3::Color};
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Lambertian::new(Color::from(0.5)),
+        ))
+    }
+
+    #[test]
+    fn hits_are_transformed_into_world_space() {
+        // Squashed to half its height, then moved up.
+        let transform =
+            Mat4::translation(Vec3::new(0.0, 3.0, 0.0)) * Mat4::scale(Vec3::new(1.0, 0.5, 1.0));
+        let instance = Instance::new(unit_sphere(), transform);
+
+        let down = Ray::new(Point3::new(0.0, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = instance.hit(&down, 0.001, f32::INFINITY).unwrap();
+        assert!((rec.t - 6.5).abs() < 1e-4);
+        assert_near(rec.p, Point3::new(0.0, 3.5, 0.0));
+        assert_near(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!(instance.hit_any(&down, 0.001, f32::INFINITY));
+
+        // Sideways the normal of the squashed sphere tilts up, more than the position does.
+        let side = Ray::new(Point3::new(5.0, 3.25, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = instance.hit(&side, 0.001, f32::INFINITY).unwrap();
+        let x = f32::sqrt(0.75);
+        assert_near(rec.p, Point3::new(x, 3.25, 0.0));
+        assert_near(rec.normal, Vec3::unit_vector(Vec3::new(x, 1.0, 0.0)));
+
+        let below = Ray::new(Point3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(instance.hit(&below, 0.001, f32::INFINITY).is_none());
+        assert!(!instance.hit_any(&below, 0.001, f32::INFINITY));
+    }
+
+    #[test]
+    fn bounding_boxes_follow_rotations() {
+        let rotated = Instance::new(
+            unit_sphere(),
+            Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), 45.0)
+                * Mat4::translation(Vec3::new(2.0, 0.0, 0.0)),
+        );
+
+        // The box around the sphere turns with it, so its corners stick out √2 from the center.
+        let bounding_box = rotated.bounding_box(0.0, 1.0).unwrap();
+        let center = f32::sqrt(2.0);
+        let reach = f32::sqrt(2.0);
+        assert_near(
+            bounding_box.minimum,
+            Point3::new(center - reach, center - reach, -1.0),
+        );
+        assert_near(
+            bounding_box.maximum,
+            Point3::new(center + reach, center + reach, 1.0),
+        );
+    }
+
+    #[test]
+    fn light_pdfs_match_the_transformed_object() {
+        let material = || Lambertian::new(Color::from(1.0));
+        let instance = Instance::new(
+            Arc::new(XZRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, material())),
+            Mat4::translation(Vec3::new(1.0, 2.0, 0.0)) * Mat4::scale(Vec3::new(2.0, 1.0, 0.5)),
+        );
+        let rect = XZRect::new(-1.0, 3.0, -0.5, 0.5, 2.0, material());
+
+        let origin = Point3::new(0.5, 0.0, 0.2);
+        for direction in [
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, -0.1),
+            Vec3::new(-0.7, 2.0, 0.3),
+        ] {
+            let expected = rect.pdf_value(&origin, &direction);
+            assert!(expected > 0.0);
+            assert!((instance.pdf_value(&origin, &direction) - expected).abs() < 1e-3 * expected);
+        }
+
+        for _ in 0..100 {
+            let direction = instance.random(&origin);
+            assert!(rect
+                .hit(&Ray::new(origin, direction, 0.0), 0.001, f32::INFINITY)
+                .is_some());
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::sync::Arc;
+
+use crate::{
+    hittable::Hittable,
+    pdf::{HittablePdf, Pdf},
+    ray::Ray,
+    utils::{clamp, to_radians, INFINITY},
+    vec3::{Color, Point3, Vec3},
+};
+
+/// Light arriving at a point from a sampled spot on a light.
+pub struct LightSample {
+    /// Unit vector from the point towards the light.
+    pub direction: Vec3,
+    /// Distance to the sampled spot, shadow rays only need to look for occluders up to here.
+    pub distance: f32,
+    pub radiance: Color,
+    /// Probability density of having picked `direction`, over solid angle. Delta lights only have
+    /// the one direction, so they use 1.
+    pub pdf: f32,
+}
+
+/// Something the renderer can sample directly to light the scene, instead of waiting for scattered
+/// rays to hit it by chance.
+pub trait Light: Send + Sync {
+    /// Picks a spot on the light as seen from `p`. Returns `None` if the light can't reach `p`.
+    fn sample(&self, p: &Point3, time: f32) -> Option<LightSample>;
+
+    /// The distribution `sample` picks directions from, as seen from `origin`.
+    fn pdf(&self, origin: &Point3) -> Box<dyn Pdf + '_>;
+
+    /// Whether the light is infinitely far away, in which case only rays that escape the scene can
+    /// reach it.
+    fn is_infinite(&self) -> bool {
+        false
+    }
+
+    /// Whether the light only shines from a single point or direction. Scattered rays can never
+    /// hit those, so they can only be found by sampling them.
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+/// An emissive object in the scene. The same object should also be part of the world, so that
+/// it shows up in the image and casts shadows.
+pub struct AreaLight {
+    shape: Arc<dyn Hittable>,
+}
+
+impl AreaLight {
+    /// `shape` needs to implement `Hittable::random` and `Hittable::pdf_value`.
+    pub fn new(shape: Arc<dyn Hittable>) -> Self {
+        Self { shape }
+    }
+}
+
+impl Light for AreaLight {
+    fn sample(&self, p: &Point3, time: f32) -> Option<LightSample> {
+        let shape_pdf = HittablePdf::new(&*self.shape, *p);
+        let direction = Vec3::unit_vector(shape_pdf.generate());
+        let rec = self
+            .shape
+            .hit(&Ray::new(*p, direction, time), 0.001, INFINITY)?;
+
+        let pdf = shape_pdf.value(&direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance: rec.t,
+            radiance: rec.mat.emitted(&rec.uv, &rec.p),
+            pdf,
+        })
+    }
+
+    fn pdf(&self, origin: &Point3) -> Box<dyn Pdf + '_> {
+        Box::new(HittablePdf::new(&*self.shape, *origin))
+    }
+}
+
+/// A light in an infinitely small point, shining equally in all directions.
+pub struct PointLight {
+    position: Point3,
+    /// Color times brightness.
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Point3, _time: f32) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity / (distance * distance),
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, origin: &Point3) -> Box<dyn Pdf + '_> {
+        Box::new(DeltaPdf {
+            direction: self.position - *origin,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// A point light that only shines in a cone, fading out towards the edge of the cone.
+pub struct SpotLight {
+    light: PointLight,
+    /// Unit vector along the axis of the cone.
+    direction: Vec3,
+    /// Cosines of the angles from the axis where the falloff starts and ends.
+    cos_falloff_start: f32,
+    cos_total_width: f32,
+}
+
+impl SpotLight {
+    /// `cone_angle` is the angle in degrees between the axis and the edge of the cone, the light
+    /// fades out over the outermost `cone_delta` degrees of it.
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        cone_angle: f32,
+        cone_delta: f32,
+    ) -> Self {
+        Self {
+            light: PointLight::new(position, intensity),
+            direction: Vec3::unit_vector(direction),
+            cos_falloff_start: f32::cos(to_radians(cone_angle - cone_delta)),
+            cos_total_width: f32::cos(to_radians(cone_angle)),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+
+        let t = clamp(
+            (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width),
+            0.0,
+            1.0,
+        );
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Point3, time: f32) -> Option<LightSample> {
+        let mut sample = self.light.sample(p, time)?;
+
+        let falloff = self.falloff(Vec3::dot(&-sample.direction, &self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        sample.radiance *= falloff;
+
+        Some(sample)
+    }
+
+    fn pdf(&self, origin: &Point3) -> Box<dyn Pdf + '_> {
+        self.light.pdf(origin)
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Parallel light coming from infinitely far away, like the sun without a sky.
+pub struct DirectionalLight {
+    /// Unit vector pointing the way the light travels.
+    direction: Vec3,
+    /// Color times brightness, the light arriving at a surface facing the light head on.
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color) -> Self {
+        Self {
+            direction: Vec3::unit_vector(direction),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Point3, _time: f32) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, _origin: &Point3) -> Box<dyn Pdf + '_> {
+        Box::new(DeltaPdf {
+            direction: -self.direction,
+        })
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// The single direction towards a delta light. No scattered ray will ever pick it exactly, so its
+/// density is zero everywhere else.
+struct DeltaPdf {
+    direction: Vec3,
+}
+
+impl Pdf for DeltaPdf {
+    fn value(&self, _direction: &Vec3) -> f32 {
+        0.0
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.direction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aarect::XZRect, materials::DiffuseLight};
+
+    #[test]
+    fn point_lights_fall_off_with_the_squared_distance() {
+        let light = PointLight::new(Point3::new(0.0, 4.0, 0.0), Color::from(16.0));
+
+        let sample = light.sample(&Point3::new(0.0, 2.0, 0.0), 0.0).unwrap();
+        assert_eq!(sample.distance, 2.0);
+        assert_eq!(sample.radiance.x, 4.0);
+        assert_eq!([sample.direction.x, sample.direction.y], [0.0, 1.0]);
+
+        let sample = light.sample(&Point3::new(0.0, 0.0, 0.0), 0.0).unwrap();
+        assert_eq!(sample.radiance.x, 1.0);
+
+        assert!(light.is_delta() && !light.is_infinite());
+        assert_eq!(light.pdf(&Point3::from(0.0)).value(&sample.direction), 0.0);
+    }
+
+    #[test]
+    fn spot_lights_fade_out_at_the_edge_of_the_cone() {
+        // Pointing straight down, full brightness up to 20 degrees, nothing past 30.
+        let light = SpotLight::new(
+            Point3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Color::from(1.0),
+            30.0,
+            10.0,
+        );
+        let at_angle = |degrees: f32| {
+            let p = Point3::new(f32::tan(to_radians(degrees)), 0.0, 0.0);
+            let distance_squared = (Point3::new(0.0, 1.0, 0.0) - p).length_squared();
+            light
+                .sample(&p, 0.0)
+                .map_or(0.0, |sample| sample.radiance.x * distance_squared)
+        };
+
+        assert!((at_angle(0.0) - 1.0).abs() < 1e-5);
+        assert!((at_angle(15.0) - 1.0).abs() < 1e-5);
+        assert!(at_angle(22.0) < 1.0 && at_angle(22.0) > at_angle(25.0));
+        assert!(at_angle(28.0) < at_angle(25.0) && at_angle(28.0) > 0.0);
+        assert_eq!(at_angle(35.0), 0.0);
+        assert!(light.sample(&Point3::new(0.0, 2.0, 0.0), 0.0).is_none());
+    }
+
+    #[test]
+    fn directional_lights_are_the_same_everywhere() {
+        let light = DirectionalLight::new(Vec3::new(0.0, -2.0, 0.0), Color::from(3.0));
+
+        for p in [Point3::from(0.0), Point3::new(100.0, -5.0, 7.0)] {
+            let sample = light.sample(&p, 0.0).unwrap();
+            assert_eq!([sample.direction.x, sample.direction.y], [0.0, 1.0]);
+            assert_eq!(sample.radiance.x, 3.0);
+            assert_eq!(sample.distance, INFINITY);
+        }
+        assert!(light.is_delta() && light.is_infinite());
+    }
+
+    #[test]
+    fn area_lights_sample_their_shape() {
+        let light = AreaLight::new(Arc::new(XZRect::new(
+            -1.0,
+            1.0,
+            -1.0,
+            1.0,
+            2.0,
+            DiffuseLight::from_color(Color::from(5.0)),
+        )));
+        let origin = Point3::from(0.0);
+
+        for _ in 0..100 {
+            let sample = light.sample(&origin, 0.0).unwrap();
+            assert!(((sample.direction * sample.distance).y - 2.0).abs() < 1e-4);
+            assert_eq!(sample.radiance.x, 5.0);
+            assert!((light.pdf(&origin).value(&sample.direction) - sample.pdf).abs() < 1e-4);
+        }
+        assert!(!light.is_delta() && !light.is_infinite());
+    }
+}
@@ -1,118 +1,106 @@
-use std::io::Write;
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
+
+use clap::Parser;
 
 use crate::{
+    bvh::{BVHBuilder, BuildStats, TraversalStats},
     camera::Camera,
+    checkpoint::Progress,
+    denoise::Denoiser,
     hittable::Hittable,
-    hittable_list::HittableList,
     output_buffer::OutputBuffer,
-    ray::Ray,
-    utils::random,
+    scenes::SceneInfo,
     utils::INFINITY,
-    vec3::{Color, Point3, Vec3},
+    vec3::Vec3,
 };
 
 mod aabb;
 mod aarect;
+mod adaptive;
+mod aov;
+mod background;
 mod bvh;
 mod camera;
+mod checkpoint;
+mod cli;
+mod constant_medium;
+mod denoise;
+mod density;
+mod distribution;
+mod environment;
+mod filter;
 mod hittable;
 mod hittable_list;
+mod instance;
+mod lights;
+mod mat4;
 mod materials;
+mod obj;
 mod objects;
+mod onb;
 mod output_buffer;
+mod pdf;
 mod perlin;
 mod ray;
+mod renderer;
+mod sampler;
+mod scene_file;
 mod scenes;
+mod sky;
 mod texture;
+mod tonemap;
+mod triangle;
 mod utils;
 mod vec3;
+mod volume;
 
-struct SceneInfo {
-    pub world: HittableList,
-    pub background: Color,
-    pub lookfrom: Point3,
-    pub lookat: Point3,
-    pub vfov: f32,
-    pub aperture: f32,
-    pub image_width: u32,
-    pub image_height: u32,
-    pub samples_per_pixel: u32,
-}
+fn main() {
+    let args = cli::Args::parse();
 
-impl Default for SceneInfo {
-    fn default() -> Self {
-        Self {
-            world: HittableList::new(),
-            background: Color::from(0.0),
-            lookfrom: Point3::new(10.0, 2.0, 3.0),
-            lookat: Point3::from(0.0),
-            vfov: 20.0,
-            aperture: 0.0,
-            image_width: 400,
-            image_height: 225,
-            samples_per_pixel: 100,
+    if args.list_scenes {
+        for (name, _) in scenes::SCENES.iter() {
+            println!("{}", name);
         }
+        return;
     }
-}
 
-fn main() {
-    const NR_CHANNELS: u32 = 3;
-    const MAX_DEPTH: i32 = 50;
-
-    let scene_select = 0;
-    let scene: SceneInfo = match scene_select {
-        1 => SceneInfo {
-            world: scenes::random_scene(),
-            background: Color::new(0.7, 0.8, 1.0),
-            lookfrom: Point3::new(13.0, 2.0, 3.0),
-            lookat: Point3::from(0.0),
-            aperture: 0.1,
-            ..Default::default()
-        },
-        2 => SceneInfo {
-            world: scenes::two_spheres(),
-            background: Color::new(0.7, 0.8, 1.0),
-            lookfrom: Point3::new(13.0, 2.0, 3.0),
-            lookat: Point3::from(0.0),
-            ..Default::default()
-        },
-        3 => SceneInfo {
-            world: scenes::two_perlin_spheres(),
-            background: Color::new(0.7, 0.8, 1.0),
-            lookfrom: Point3::new(13.0, 2.0, 3.0),
-            lookat: Point3::from(0.0),
-            ..Default::default()
-        },
-        4 => SceneInfo {
-            world: scenes::earth(),
-            background: Color::new(0.7, 0.8, 1.0),
-            lookfrom: Point3::new(13.0, 2.0, 1.0),
-            lookat: Point3::from(0.0),
-            ..Default::default()
-        },
-        5 => SceneInfo {
-            world: scenes::simple_light(),
-            background: Color::new(0.0, 0.0, 0.0),
-            lookfrom: Point3::new(26.0, 3.0, 6.0),
-            lookat: Point3::new(0.0, 2.0, 0.0),
-            ..Default::default()
-        },
-        6 | _ => SceneInfo {
-            world: scenes::cornell_box(),
-            background: Color::new(0.0, 0.0, 0.0),
-            lookfrom: Point3::new(278.0, 278.0, -800.0),
-            lookat: Point3::new(278.0, 278.0, 0.0),
-            vfov: 40.0,
-            image_width: 600,
-            image_height: 600,
-            samples_per_pixel: 200,
-            ..Default::default()
+    // Resumed renders carry on with the seed they started with, the scene has to come out the same.
+    let seed = match (&args.checkpoint, args.resume) {
+        (Some(checkpoint), true) => match checkpoint::read_seed(checkpoint) {
+            Ok(seed) => seed,
+            Err(err) => {
+                eprintln!("Failed to load checkpoint: {}", err);
+                std::process::exit(1);
+            }
         },
+        _ => args.seed.unwrap_or_else(rand::random),
+    };
+    utils::seed_random(seed);
+
+    let mut bvh = BVHBuilder::new(args.bvh_split);
+    let mut scene = if let Some(scene_file) = &args.scene_file {
+        match scene_file::load(scene_file, &mut bvh) {
+            Ok(scene) => scene,
+            Err(err) => {
+                eprintln!("Failed to load scene: {}", err);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        match scenes::from_name(&args.scene, &mut bvh) {
+            Some(scene) => scene,
+            None => {
+                eprintln!(
+                    "Unknown scene '{}', use --list-scenes to see the available ones.",
+                    args.scene
+                );
+                std::process::exit(1);
+            }
+        }
     };
+    args.apply_overrides(&mut scene);
 
     let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
     let aspect_ratio = scene.image_width as f32 / scene.image_height as f32;
 
     let cam = Camera::new(
@@ -122,95 +110,178 @@ fn main() {
         scene.vfov,
         aspect_ratio,
         scene.aperture,
-        dist_to_focus,
-        0.0,
-        1.0,
+        scene.focus_dist,
+        scene.time0,
+        scene.time1,
     );
 
-    let mut buffer = OutputBuffer::new(scene.image_width, scene.image_height, NR_CHANNELS);
+    if args.bvh_stats {
+        print_bvh_stats(&scene, &cam, bvh.stats());
+    }
+
+    let mut buffer = OutputBuffer::new(scene.image_width, scene.image_height, args.nr_channels())
+        .with_tone_mapping(args.tone_mapping())
+        .with_aovs(args.aov.clone())
+        .with_filter(args.filter());
+    if let Some(strength) = args.denoise {
+        buffer = buffer.with_denoiser(Denoiser::new(strength));
+    }
+    let adaptive = args.adaptive_sampling(scene.samples_per_pixel);
+    if let Some(adaptive) = adaptive {
+        buffer = buffer.with_adaptive_sampling(adaptive);
+    }
+
+    let mut progress = Progress::new(seed);
+    if args.resume {
+        let checkpoint = args.checkpoint.as_ref().unwrap();
+        progress = match checkpoint::load(checkpoint, &mut buffer) {
+            Ok(progress) => progress,
+            Err(err) => {
+                eprintln!("Failed to load checkpoint: {}", err);
+                std::process::exit(1);
+            }
+        };
+        println!(
+            "Resuming after {} passes, {}SPP",
+            progress.passes, progress.samples
+        );
+    }
+
+    let pass_spp = args.pass_spp.unwrap_or(scene.samples_per_pixel);
+    // Adaptive sampling stops early once every pixel has converged.
+    let max_spp = adaptive.map_or(scene.samples_per_pixel, |adaptive| adaptive.max_samples);
 
     let now = chrono::Local::now();
     println!(
-        "Started render at {} - {}x{} @ {}SPP - ray depth: {}",
+        "Started render at {} - {}x{} @ {}SPP - ray depth: {} - {} threads - seed: {}",
         now.format("%H:%M:%S"),
         scene.image_width,
         scene.image_height,
         scene.samples_per_pixel,
-        MAX_DEPTH
+        scene.max_depth,
+        rayon::current_num_threads(),
+        seed
     );
 
     let begin = SystemTime::now();
 
-    for j in (0..scene.image_height).rev() {
-        print!("\rScanlines remaining: {:04}", j);
-        std::io::stdout().flush().unwrap();
-
-        for i in 0..scene.image_width {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+    let mut passes = 0;
+    while progress.samples < max_spp {
+        let pixels = buffer.unconverged_pixels();
+        if pixels == 0 {
+            break;
+        }
 
-            for _ in 0..scene.samples_per_pixel {
-                let u = ((i as f32) + random()) / (scene.image_width - 1) as f32;
-                let v = ((j as f32) + random()) / (scene.image_height - 1) as f32;
+        let pass = progress.next_pass(u32::min(pass_spp, max_spp - progress.samples));
+        renderer::render(&scene, &cam, &mut buffer, &pass);
+        progress.finish_pass(&pass);
+        passes += 1;
 
-                let r = cam.get_ray(u, v);
-                pixel_color += ray_color(&r, &scene.background, &scene.world, MAX_DEPTH);
-            }
+        if adaptive.is_some() {
+            println!(
+                "\rFinished pass {} - {}/{}SPP - {} pixels sampled",
+                progress.passes, progress.samples, max_spp, pixels
+            );
+        } else {
+            println!(
+                "\rFinished pass {} - {}/{}SPP",
+                progress.passes, progress.samples, max_spp
+            );
+        }
 
-            buffer.write_color(i, j, &pixel_color, scene.samples_per_pixel);
+        let done = progress.samples >= max_spp;
+        if !done && args.save_every.is_some_and(|every| passes % every == 0) {
+            save(&args, &buffer, &progress);
         }
     }
 
     let end = SystemTime::now().duration_since(begin).unwrap().as_secs();
 
+    if buffer.records_first_hits() && !buffer.has_first_hits() {
+        eprintln!(
+            "Warning: no samples were taken in this run, so the AOVs are empty and the image \
+             doesn't get denoised."
+        );
+    }
+
     let now = chrono::Local::now();
     println!(
-        "\rFinished rendering at {} - took {}s",
+        "Finished rendering at {} - took {}s",
         now.format("%H:%M:%S"),
         end
     );
-    println!("Writing buffer to file...");
 
-    if NR_CHANNELS != 3 && NR_CHANNELS != 4 {
-        panic!("Incorrect amount of channels! use either 3 or 4");
+    save(&args, &buffer, &progress);
+}
+
+/// Writes the image, and the checkpoint and heatmap if there should be any.
+fn save(args: &cli::Args, buffer: &OutputBuffer, progress: &Progress) {
+    if let Some(checkpoint) = &args.checkpoint {
+        if let Err(err) = checkpoint::save(checkpoint, buffer, progress) {
+            panic!("Failed to save checkpoint! {}", err);
+        }
+        println!("Wrote checkpoint to '{}'!", checkpoint);
     }
 
-    let color_type = if NR_CHANNELS == 3 {
-        image::ColorType::Rgb8
-    } else {
-        image::ColorType::Rgba8
-    };
+    if let Some(heatmap) = &args.heatmap {
+        if let Err(err) = buffer.save_heatmap(heatmap) {
+            panic!("Failed to save heatmap! {}", err);
+        }
+        println!("Wrote heatmap to '{}'!", heatmap);
+    }
 
-    match image::save_buffer(
-        "output.png",
-        &buffer.get_pixels(),
-        scene.image_width,
-        scene.image_height,
-        color_type,
-    ) {
+    println!("Writing buffer to file...");
+
+    match buffer.save(&args.output) {
         Err(what) => panic!("Something failed! {:?}", what),
-        _ => println!("Wrote image to 'output.png'!"),
+        Ok(paths) => {
+            for path in paths {
+                println!("Wrote image to '{}'!", path.display());
+            }
+        }
     }
 }
 
-fn ray_color(r: &Ray, background_color: &Color, world: &HittableList, depth: i32) -> Color {
-    // If we've exceeded the ray bounce limit, no more light is gathered.
-    if depth <= 0 {
-        return Color::from(0.0);
-    }
+/// Prints what building the BVHs of the scene took, and how much work it is to find what a camera
+/// ray through the center of every pixel hits first, to compare BVH builders with.
+fn print_bvh_stats(scene: &SceneInfo, cam: &Camera, build: BuildStats) {
+    println!(
+        "BVH build: {:.1}ms - {} nodes - {} primitives",
+        build.time.as_secs_f64() * 1000.0,
+        build.nodes,
+        build.primitives
+    );
 
-    match world.hit(&r, 0.001, INFINITY) {
-        Some(rec) => {
-            let (is_scattered, attenuation, scattered) = rec.mat.scatter(r, &rec);
-            let emitted = rec.mat.emitted(&rec.uv, &rec.p);
+    let (width, height) = (scene.image_width, scene.image_height);
+    let rays = (width * height) as f64;
+    let camera_rays = || {
+        (0..height).flat_map(move |j| {
+            (0..width).map(move |i| {
+                let u = (i as f32 + 0.5) / (width - 1) as f32;
+                let v = (j as f32 + 0.5) / (height - 1) as f32;
+                cam.get_ray(u, v)
+            })
+        })
+    };
 
-            if !is_scattered {
-                return emitted;
-            }
+    let mut traversal = TraversalStats::default();
+    for ray in camera_rays() {
+        scene
+            .world
+            .hit_counting(&ray, 0.001, INFINITY, &mut traversal);
+    }
 
-            return emitted
-                + attenuation * ray_color(&scattered, &background_color, world, depth - 1);
-        }
-        // If the ray hits nothing, return the background color
-        None => return background_color.clone(),
+    // Timed separately, the counting slows things down.
+    let begin = Instant::now();
+    for ray in camera_rays() {
+        scene.world.hit(&ray, 0.001, INFINITY);
     }
+    let elapsed = begin.elapsed();
+
+    println!(
+        "BVH traversal: {:.1} box tests/ray - {:.1} primitive tests/ray - {:.2}\u{b5}s/ray",
+        traversal.box_tests as f64 / rays,
+        traversal.primitive_tests as f64 / rays,
+        elapsed.as_secs_f64() * 1e6 / rays
+    );
 }
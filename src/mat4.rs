@@ -0,0 +1,168 @@
+use std::ops::Mul;
+
+use crate::{
+    utils::to_radians,
+    vec3::{Point3, Vec3},
+};
+
+/// A 4x4 matrix in row-major order, applied to column vectors. Only affine transforms are
+/// supported, so the bottom row is always `[0, 0, 0, 1]`.
+#[derive(Clone, Copy, Debug)]
+pub struct Mat4 {
+    m: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    /// Returns `None` if the bottom row isn't `[0, 0, 0, 1]`.
+    pub fn from_rows(m: [[f32; 4]; 4]) -> Option<Self> {
+        if m[3] != [0.0, 0.0, 0.0, 1.0] {
+            return None;
+        }
+
+        Some(Self { m })
+    }
+
+    pub fn identity() -> Self {
+        Self {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Self {
+            m: [
+                [1.0, 0.0, 0.0, offset.x],
+                [0.0, 1.0, 0.0, offset.y],
+                [0.0, 0.0, 1.0, offset.z],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn scale(factors: Vec3) -> Self {
+        Self {
+            m: [
+                [factors.x, 0.0, 0.0, 0.0],
+                [0.0, factors.y, 0.0, 0.0],
+                [0.0, 0.0, factors.z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Counter-clockwise rotation around `axis` when looking down the axis towards the origin.
+    pub fn rotation(axis: Vec3, degrees: f32) -> Self {
+        let a = Vec3::unit_vector(axis);
+        let (sin, cos) = f32::sin_cos(to_radians(degrees));
+        let t = 1.0 - cos;
+
+        Self {
+            m: [
+                [
+                    t * a.x * a.x + cos,
+                    t * a.x * a.y - sin * a.z,
+                    t * a.x * a.z + sin * a.y,
+                    0.0,
+                ],
+                [
+                    t * a.x * a.y + sin * a.z,
+                    t * a.y * a.y + cos,
+                    t * a.y * a.z - sin * a.x,
+                    0.0,
+                ],
+                [
+                    t * a.x * a.z - sin * a.y,
+                    t * a.y * a.z + sin * a.x,
+                    t * a.z * a.z + cos,
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Determinant of the upper-left 3x3 part, which is what scales volumes (and solid angles).
+    pub fn determinant(&self) -> f32 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Returns `None` if the matrix can't be inverted, because it squashes space flat.
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let m = &self.m;
+        let inv_det = 1.0 / det;
+
+        // The inverse of the linear part is its adjugate over the determinant.
+        let mut inv = [[0.0; 4]; 4];
+        inv[0][0] = (m[1][1] * m[2][2] - m[1][2] * m[2][1]) * inv_det;
+        inv[0][1] = (m[0][2] * m[2][1] - m[0][1] * m[2][2]) * inv_det;
+        inv[0][2] = (m[0][1] * m[1][2] - m[0][2] * m[1][1]) * inv_det;
+        inv[1][0] = (m[1][2] * m[2][0] - m[1][0] * m[2][2]) * inv_det;
+        inv[1][1] = (m[0][0] * m[2][2] - m[0][2] * m[2][0]) * inv_det;
+        inv[1][2] = (m[0][2] * m[1][0] - m[0][0] * m[1][2]) * inv_det;
+        inv[2][0] = (m[1][0] * m[2][1] - m[1][1] * m[2][0]) * inv_det;
+        inv[2][1] = (m[0][1] * m[2][0] - m[0][0] * m[2][1]) * inv_det;
+        inv[2][2] = (m[0][0] * m[1][1] - m[0][1] * m[1][0]) * inv_det;
+
+        // And the translation gets undone after the linear part is.
+        for row in inv.iter_mut().take(3) {
+            row[3] = -(row[0] * m[0][3] + row[1] * m[1][3] + row[2] * m[2][3]);
+        }
+        inv[3][3] = 1.0;
+
+        Some(Self { m: inv })
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        self.transform_vector(p) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    /// Transforms a direction, which unlike a point doesn't get translated.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    /// Transforms a normal with the transpose of this matrix. Call this on the inverse of the
+    /// matrix the surface was transformed with, to keep normals perpendicular to the surface.
+    pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        )
+    }
+}
+
+// mat * mat, applies `rhs` first and then `self`
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+
+        Self { m }
+    }
+}
@@ -1,51 +1,175 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
     hittable::HitRecord,
+    pdf::{CosinePdf, Pdf, SpherePdf},
     ray::Ray,
     texture::{SolidColor, Texture},
-    utils::random,
+    utils::{random, PI},
     vec3::{Color, Point3, Vec3},
 };
 
-pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> (bool, Color, Ray);
-    fn emitted(&self, uv: &(f32, f32), p: &Point3) -> Color {
+/// How a ray continues after hitting a material.
+pub enum ScatterRecord {
+    /// Scattering in a single direction, like a mirror or glass. The ray gets tinted by
+    /// `attenuation`.
+    Specular { ray: Ray, attenuation: Color },
+    /// Scattering in a direction picked from `pdf`. The ray gets weighted by `Material::bsdf`,
+    /// which lets the renderer combine it with sampling the lights.
+    Pdf(Box<dyn Pdf>),
+}
+
+pub trait Material: Send + Sync {
+    /// Returns `None` if the ray gets absorbed.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord>;
+    fn emitted(&self, _uv: &(f32, f32), _p: &Point3) -> Color {
         // Return black by default.
         Color::from(0.0)
     }
+
+    /// Whether `emitted` returns anything but black, in which case objects with this material
+    /// should also be added to the scene's lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Evaluates the BSDF for light leaving along `r_in` reversed, that arrives from `direction`,
+    /// times the cosine between `direction` and the normal. Volumes don't have a normal, they
+    /// return their phase function instead. Only needs to be implemented by materials that scatter
+    /// with `ScatterRecord::Pdf`.
+    fn bsdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::from(0.0)
+    }
+
+    /// The base color of the material at the hit, for the albedo pass. Materials that don't
+    /// reflect light are black.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::from(0.0)
+    }
+
+    /// Tells materials apart for the material ID pass. Zero unless the material was given an ID
+    /// with `MaterialIds` when the scene got built, objects sharing it through an `Arc` share the
+    /// ID too.
+    fn id(&self) -> u32 {
+        0
+    }
+}
+
+// Lets objects share a single material, e.g. `Sphere<Arc<dyn Material>>`.
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        (**self).scatter(r_in, rec)
+    }
+
+    fn emitted(&self, uv: &(f32, f32), p: &Point3) -> Color {
+        (**self).emitted(uv, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        (**self).is_emissive()
+    }
+
+    fn bsdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        (**self).bsdf(r_in, rec, direction)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        (**self).albedo(rec)
+    }
+
+    fn id(&self) -> u32 {
+        (**self).id()
+    }
+}
+
+/// Hands out the IDs for the material ID pass while a scene gets built, counting from 1.
+#[derive(Default)]
+pub struct MaterialIds {
+    last: u32,
+}
+
+impl MaterialIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives `material` the next ID.
+    pub fn assign<M: Material>(&mut self, material: M) -> WithMaterialId<M> {
+        self.last += 1;
+
+        WithMaterialId {
+            material,
+            id: self.last,
+        }
+    }
+}
+
+/// A material with an ID from `MaterialIds`, it behaves just like `material` otherwise.
+#[derive(Clone)]
+pub struct WithMaterialId<M> {
+    material: M,
+    id: u32,
+}
+
+impl<M: Material> Material for WithMaterialId<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.material.scatter(r_in, rec)
+    }
+
+    fn emitted(&self, uv: &(f32, f32), p: &Point3) -> Color {
+        self.material.emitted(uv, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn bsdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.material.bsdf(r_in, rec, direction)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
 }
 
 #[derive(Clone)]
 pub struct Lambertian {
-    pub albedo: Rc<dyn Texture>,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
         Self {
-            albedo: Rc::new(SolidColor::new(albedo)),
+            albedo: Arc::new(SolidColor::new(albedo)),
         }
     }
 
-    pub fn from_texture(albedo: Rc<dyn Texture>) -> Self {
+    pub fn from_texture(albedo: Arc<dyn Texture>) -> Self {
         Self { albedo }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> (bool, Color, Ray) {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf(Box::new(CosinePdf::new(&rec.normal))))
+    }
 
-        // Catch degenerate scatter direction
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
+    fn bsdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let cosine = Vec3::dot(&rec.normal, &Vec3::unit_vector(*direction));
+        if cosine <= 0.0 {
+            return Color::from(0.0);
         }
 
-        let scattered = Ray::new(rec.p, scatter_direction, r_in.time);
-        let attenuation = self.albedo.value(&rec.uv, rec.p);
+        self.albedo.value(&rec.uv, rec.p) * cosine / PI
+    }
 
-        (true, attenuation, scattered)
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(&rec.uv, rec.p)
     }
 }
 
@@ -62,17 +186,25 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> (bool, Color, Ray) {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         let reflected = Vec3::reflect(&Vec3::unit_vector(r_in.direction), &rec.normal);
+        if Vec3::dot(&reflected, &rec.normal) <= 0.0 {
+            return None;
+        }
 
-        let scattered = Ray::new(
-            rec.p,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
-            r_in.time,
-        );
-        let is_scattered = Vec3::dot(&reflected, &rec.normal) > 0.0;
+        // Fuzzy reflections are still treated as specular, there is no pdf to go with them.
+        Some(ScatterRecord::Specular {
+            ray: Ray::new(
+                rec.p,
+                reflected + self.fuzz * Vec3::random_in_unit_sphere(),
+                r_in.time,
+            ),
+            attenuation: self.albedo,
+        })
+    }
 
-        (is_scattered, self.albedo, scattered)
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
     }
 }
 
@@ -88,7 +220,7 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> (bool, Color, Ray) {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
         } else {
@@ -106,9 +238,14 @@ impl Material for Dielectric {
             Vec3::refract(&unit_direction, &rec.normal, refraction_ratio)
         };
 
-        let scattered = Ray::new(rec.p, direction, r_in.time);
+        Some(ScatterRecord::Specular {
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: Color::from(1.0),
+        })
+    }
 
-        (true, Color::from(1.0), scattered)
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::from(1.0)
     }
 }
 
@@ -121,11 +258,11 @@ fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
 
 #[derive(Clone)]
 pub struct DiffuseLight {
-    emit: Rc<dyn Texture>,
+    emit: Arc<dyn Texture>,
 }
 
 impl DiffuseLight {
-    pub fn new(emission_texture: Rc<dyn Texture>) -> Self {
+    pub fn new(emission_texture: Arc<dyn Texture>) -> Self {
         Self {
             emit: emission_texture,
         }
@@ -133,17 +270,53 @@ impl DiffuseLight {
 
     pub fn from_color(color: Color) -> Self {
         Self {
-            emit: Rc::new(SolidColor::new(color)),
+            emit: Arc::new(SolidColor::new(color)),
         }
     }
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, r_in: &Ray, _rec: &HitRecord) -> (bool, Color, Ray) {
-        (false, Color::from(0.0), r_in.clone())
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        None
     }
 
     fn emitted(&self, uv: &(f32, f32), p: &Point3) -> Color {
-        self.emit.value(uv, p.clone())
+        self.emit.value(uv, *p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+/// Scatters light equally in all directions, for use inside of volumes.
+#[derive(Clone)]
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+
+    pub fn from_color(color: Color) -> Self {
+        Self {
+            albedo: Arc::new(SolidColor::new(color)),
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf(Box::new(SpherePdf)))
+    }
+
+    fn bsdf(&self, _r_in: &Ray, rec: &HitRecord, _direction: &Vec3) -> Color {
+        self.albedo.value(&rec.uv, rec.p) / (4.0 * PI)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(&rec.uv, rec.p)
     }
 }
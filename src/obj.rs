@@ -0,0 +1,683 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::SplitWhitespace,
+    sync::Arc,
+};
+
+use crate::{
+    bvh::BVHBuilder,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    materials::{Dielectric, DiffuseLight, Lambertian, Material, MaterialIds, Metal},
+    texture::ImageTexture,
+    triangle::TriangleMesh,
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    NoFaces {
+        path: PathBuf,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => {
+                write!(f, "could not read '{}': {}", path.display(), source)
+            }
+            Self::Image { path, source } => {
+                write!(f, "could not load image '{}': {}", path.display(), source)
+            }
+            Self::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            Self::NoFaces { path } => write!(f, "'{}' has no faces", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Indices into the position, UV and normal lists of the file, for one corner of a face.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// The faces sharing a group name and material, these end up as one mesh.
+struct FaceGroup {
+    material: Option<String>,
+    // Line of the `usemtl` statement, for error reporting.
+    material_line: usize,
+    faces: Vec<[FaceVertex; 3]>,
+}
+
+/// The meshes loaded from an OBJ file.
+pub struct ObjModel {
+    pub objects: HittableList,
+    /// The meshes with an emissive material, these should also be added to the scene's lights.
+    pub emitters: Vec<Arc<dyn Hittable>>,
+}
+
+/// Loads a Wavefront OBJ file. Every face group (`o`/`g` and `usemtl` combination) becomes its own
+/// `TriangleMesh`, using the materials from the MTL libraries referenced in the file. Passing a
+/// `material` overrides all of those. The materials of the file get their IDs from `material_ids`.
+pub fn load<P: AsRef<Path>>(
+    path: P,
+    material: Option<Arc<dyn Material>>,
+    material_ids: &mut MaterialIds,
+    bvh: &mut BVHBuilder,
+) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let source = read(path)?;
+
+    parse(path, &source, material, material_ids, bvh)
+}
+
+/// Builds the meshes described by `source`, the contents of the OBJ file at `path`.
+fn parse(
+    path: &Path,
+    source: &str,
+    material: Option<Arc<dyn Material>>,
+    material_ids: &mut MaterialIds,
+    bvh: &mut BVHBuilder,
+) -> Result<ObjModel, ObjError> {
+    let parse_error = |line: usize, message: String| ObjError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut uvs: Vec<(f32, f32)> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+
+    let mut groups: Vec<FaceGroup> = Vec::new();
+    let mut group_name = String::new();
+    let mut material_name: Option<String> = None;
+    let mut material_line = 0;
+    let mut group_index: HashMap<(String, Option<String>), usize> = HashMap::new();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let line_nr = line_idx + 1;
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => {
+                positions.push(parse_vec3(&mut tokens).map_err(|m| parse_error(line_nr, m))?)
+            }
+            Some("vn") => {
+                normals.push(parse_vec3(&mut tokens).map_err(|m| parse_error(line_nr, m))?)
+            }
+            Some("vt") => {
+                let u = parse_float(tokens.next()).map_err(|m| parse_error(line_nr, m))?;
+                // The v coordinate is optional for 1D textures.
+                let v = match tokens.next() {
+                    Some(v) => parse_float(Some(v)).map_err(|m| parse_error(line_nr, m))?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            Some("f") => {
+                let corners = tokens
+                    .map(|token| {
+                        parse_face_vertex(token, positions.len(), uvs.len(), normals.len())
+                    })
+                    .collect::<Result<Vec<FaceVertex>, String>>()
+                    .map_err(|m| parse_error(line_nr, m))?;
+
+                if corners.len() < 3 {
+                    return Err(parse_error(
+                        line_nr,
+                        "a face needs at least 3 vertices".to_string(),
+                    ));
+                }
+
+                let key = (group_name.clone(), material_name.clone());
+                let index = *group_index.entry(key).or_insert_with(|| {
+                    groups.push(FaceGroup {
+                        material: material_name.clone(),
+                        material_line,
+                        faces: Vec::new(),
+                    });
+                    groups.len() - 1
+                });
+
+                // Triangulate polygons as a fan around their first vertex.
+                for i in 1..corners.len() - 1 {
+                    groups[index]
+                        .faces
+                        .push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            Some("o") | Some("g") => group_name = tokens.collect::<Vec<_>>().join(" "),
+            Some("usemtl") => {
+                material_name = tokens.next().map(|name| name.to_string());
+                material_line = line_nr;
+            }
+            // Material libraries are ignored when the materials get overridden anyway.
+            Some("mtllib") if material.is_none() => {
+                for library in tokens {
+                    let library_path = relative_to(path, library);
+                    materials.extend(load_mtl(&library_path, material_ids)?);
+                }
+            }
+            // Comments, smoothing groups, lines, ... don't matter to us.
+            _ => (),
+        }
+    }
+
+    // An empty list would leave nothing to put a bounding box around.
+    if groups.is_empty() {
+        return Err(ObjError::NoFaces {
+            path: path.to_path_buf(),
+        });
+    }
+
+    let mut model = ObjModel {
+        objects: HittableList::new(),
+        emitters: Vec::new(),
+    };
+
+    // Shared by all the groups without a material, so they also share the ID.
+    let mut fallback_material: Option<Arc<dyn Material>> = None;
+    for group in groups.iter() {
+        let group_material = match (&material, &group.material) {
+            (Some(material), _) => material.clone(),
+            (None, Some(name)) => match materials.get(name) {
+                Some(material) => material.clone(),
+                None => {
+                    return Err(ObjError::Parse {
+                        path: path.to_path_buf(),
+                        line: group.material_line,
+                        message: format!("unknown material '{}'", name),
+                    })
+                }
+            },
+            (None, None) => fallback_material
+                .get_or_insert_with(|| Arc::new(material_ids.assign(default_material())))
+                .clone(),
+        };
+
+        let is_emissive = group_material.is_emissive();
+        let mesh: Arc<dyn Hittable> = Arc::new(build_mesh(
+            group,
+            &positions,
+            &uvs,
+            &normals,
+            group_material,
+            bvh,
+        ));
+
+        if is_emissive {
+            model.emitters.push(mesh.clone());
+        }
+        model.objects.add(mesh);
+    }
+
+    Ok(model)
+}
+
+/// Turns the OBJ style separate position/UV/normal indices of a group into shared vertices.
+fn build_mesh(
+    group: &FaceGroup,
+    positions: &[Point3],
+    uvs: &[(f32, f32)],
+    normals: &[Vec3],
+    material: Arc<dyn Material>,
+    bvh: &mut BVHBuilder,
+) -> TriangleMesh {
+    let mut vertex_index: HashMap<FaceVertex, usize> = HashMap::new();
+    let mut vertices: Vec<FaceVertex> = Vec::new();
+    let mut indices: Vec<[usize; 3]> = Vec::with_capacity(group.faces.len());
+
+    for face in group.faces.iter() {
+        let mut triangle = [0; 3];
+        for (corner, face_vertex) in triangle.iter_mut().zip(face.iter()) {
+            *corner = *vertex_index.entry(*face_vertex).or_insert_with(|| {
+                vertices.push(*face_vertex);
+                vertices.len() - 1
+            });
+        }
+        indices.push(triangle);
+    }
+
+    // Meshes have either all or none of their vertices with normals/UVs, so when the file is
+    // missing them for some vertices they get dropped for the whole group.
+    let mesh_normals = vertices
+        .iter()
+        .map(|v| v.normal.map(|i| normals[i]))
+        .collect::<Option<Vec<Vec3>>>();
+    let mesh_uvs = vertices
+        .iter()
+        .map(|v| v.uv.map(|i| uvs[i]))
+        .collect::<Option<Vec<(f32, f32)>>>();
+    let mesh_positions = vertices.iter().map(|v| positions[v.position]).collect();
+
+    TriangleMesh::new(
+        mesh_positions,
+        mesh_normals,
+        mesh_uvs,
+        indices,
+        material,
+        bvh,
+    )
+}
+
+/// Material for faces that don't have one assigned.
+fn default_material() -> Arc<dyn Material> {
+    Arc::new(Lambertian::new(Color::from(0.8)))
+}
+
+/// The MTL properties we know how to turn into one of our materials.
+struct MtlDesc {
+    diffuse: Color,
+    /// Only set when the material has a `Ks`, metals without one take the diffuse color instead.
+    specular: Option<Color>,
+    emission: Color,
+    shininess: f32,
+    ior: f32,
+    dissolve: f32,
+    illum: u32,
+    diffuse_map: Option<PathBuf>,
+}
+
+impl Default for MtlDesc {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::from(0.8),
+            specular: None,
+            emission: Color::from(0.0),
+            shininess: 0.0,
+            ior: 1.5,
+            dissolve: 1.0,
+            illum: 2,
+            diffuse_map: None,
+        }
+    }
+}
+
+impl MtlDesc {
+    /// The color of reflective materials, which falls back to the diffuse color for materials
+    /// without a `Ks`.
+    fn metal_albedo(&self) -> Color {
+        self.specular.unwrap_or(self.diffuse)
+    }
+}
+
+fn load_mtl(
+    path: &Path,
+    material_ids: &mut MaterialIds,
+) -> Result<HashMap<String, Arc<dyn Material>>, ObjError> {
+    let source = read(path)?;
+
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    for (name, desc) in parse_mtl(path, &source)? {
+        let material = material_ids.assign(mtl_to_material(&desc)?);
+        materials.insert(name, Arc::new(material));
+    }
+
+    Ok(materials)
+}
+
+/// Reads the materials in `source`, the contents of the MTL file at `path`, in the order they're
+/// defined in.
+fn parse_mtl(path: &Path, source: &str) -> Result<Vec<(String, MtlDesc)>, ObjError> {
+    let parse_error = |line: usize, message: String| ObjError::Parse {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+
+    let mut descs: Vec<(String, MtlDesc)> = Vec::new();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let line_nr = line_idx + 1;
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens
+                .next()
+                .ok_or_else(|| parse_error(line_nr, "missing material name".to_string()))?;
+            descs.push((name.to_string(), MtlDesc::default()));
+            continue;
+        }
+
+        if keyword.starts_with('#') {
+            continue;
+        }
+
+        let desc = match descs.last_mut() {
+            Some((_, desc)) => desc,
+            None => {
+                return Err(parse_error(
+                    line_nr,
+                    format!("'{}' before the first 'newmtl'", keyword),
+                ))
+            }
+        };
+
+        let result = match keyword {
+            "Kd" => parse_vec3(&mut tokens).map(|c| desc.diffuse = c),
+            "Ks" => parse_vec3(&mut tokens).map(|c| desc.specular = Some(c)),
+            "Ke" => parse_vec3(&mut tokens).map(|c| desc.emission = c),
+            "Ns" => parse_float(tokens.next()).map(|f| desc.shininess = f),
+            "Ni" => parse_float(tokens.next()).map(|f| desc.ior = f),
+            "d" => parse_float(tokens.next()).map(|f| desc.dissolve = f),
+            "Tr" => parse_float(tokens.next()).map(|f| desc.dissolve = 1.0 - f),
+            "illum" => tokens
+                .next()
+                .and_then(|token| token.parse().ok())
+                .map(|illum| desc.illum = illum)
+                .ok_or_else(|| "expected an illumination model".to_string()),
+            // The file name is the last token, anything before it are options.
+            "map_Kd" => match tokens.last() {
+                Some(file) => {
+                    desc.diffuse_map = Some(relative_to(path, file));
+                    Ok(())
+                }
+                None => Err("missing texture file name".to_string()),
+            },
+            _ => Ok(()),
+        };
+
+        result.map_err(|m| parse_error(line_nr, m))?;
+    }
+
+    Ok(descs)
+}
+
+/// Picks the closest of our materials for an MTL material.
+fn mtl_to_material(desc: &MtlDesc) -> Result<Arc<dyn Material>, ObjError> {
+    if desc.emission.length_squared() > 0.0 {
+        return Ok(Arc::new(DiffuseLight::from_color(desc.emission)));
+    }
+
+    // Transparent, or one of the refraction illumination models.
+    if desc.dissolve < 1.0 || matches!(desc.illum, 4 | 6 | 7 | 9) {
+        return Ok(Arc::new(Dielectric::new(desc.ior)));
+    }
+
+    // Reflection illumination models, roughness follows from the Phong exponent.
+    if matches!(desc.illum, 3 | 5 | 8) {
+        let fuzz = f32::sqrt(2.0 / (desc.shininess + 2.0));
+        return Ok(Arc::new(Metal::new(desc.metal_albedo(), fuzz)));
+    }
+
+    match &desc.diffuse_map {
+        Some(map) => {
+            let texture = ImageTexture::open(map).map_err(|source| ObjError::Image {
+                path: map.clone(),
+                source,
+            })?;
+            Ok(Arc::new(Lambertian::from_texture(Arc::new(texture))))
+        }
+        None => Ok(Arc::new(Lambertian::new(desc.diffuse))),
+    }
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Resolves a file referenced from `path` relative to the directory it's in.
+fn relative_to(path: &Path, file: &str) -> PathBuf {
+    path.parent().unwrap_or(Path::new("")).join(file)
+}
+
+fn parse_float(token: Option<&str>) -> Result<f32, String> {
+    let token = token.ok_or_else(|| "expected a number".to_string())?;
+    token
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", token))
+}
+
+fn parse_vec3(tokens: &mut SplitWhitespace) -> Result<Vec3, String> {
+    Ok(Vec3::new(
+        parse_float(tokens.next())?,
+        parse_float(tokens.next())?,
+        parse_float(tokens.next())?,
+    ))
+}
+
+/// Parses one `v`, `v/vt`, `v//vn` or `v/vt/vn` corner of a face, turning the 1-based (or negative,
+/// relative to the end) indices into 0-based ones.
+fn parse_face_vertex(
+    token: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<FaceVertex, String> {
+    let mut parts = token.split('/');
+
+    let position = match parts.next() {
+        Some(index) => resolve_index(index, position_count, "position")?,
+        None => return Err(format!("invalid face vertex '{}'", token)),
+    };
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, uv_count, "texture coordinate")?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, normal_count, "normal")?),
+    };
+
+    Ok(FaceVertex {
+        position,
+        uv,
+        normal,
+    })
+}
+
+fn resolve_index(index: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let parsed: i64 = index
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid {} index", index, kind))?;
+
+    let resolved = if parsed < 0 {
+        count as i64 + parsed
+    } else {
+        parsed - 1
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} out of range, there are only {}",
+            kind, parsed, count
+        ));
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    fn parse_str(source: &str) -> Result<HittableList, ObjError> {
+        parse(
+            Path::new("models/test.obj"),
+            source,
+            None,
+            &mut MaterialIds::new(),
+            &mut BVHBuilder::default(),
+        )
+        .map(|model| model.objects)
+    }
+
+    fn parse_mtl_str(source: &str) -> Result<Vec<(String, MtlDesc)>, ObjError> {
+        parse_mtl(Path::new("models/test.mtl"), source)
+    }
+
+    /// The line a `Parse` error points at.
+    fn error_line<T>(result: Result<T, ObjError>) -> usize {
+        match result {
+            Err(ObjError::Parse { line, .. }) => line,
+            Err(err) => panic!("expected a parse error, got '{}'", err),
+            Ok(_) => panic!("expected a parse error, the file loaded"),
+        }
+    }
+
+    fn down(x: f32, y: f32) -> Ray {
+        Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn polygons_get_triangulated() {
+        let list = parse_str(
+            "# a unit quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+f 1 2 3 4
+",
+        )
+        .unwrap();
+
+        assert_eq!(list.objects.len(), 1);
+        for (x, y) in [(0.2, 0.7), (0.7, 0.2)] {
+            let rec = list.hit(&down(x, y), 0.001, f32::INFINITY).unwrap();
+            assert!((rec.t - 1.0).abs() < 1e-5);
+        }
+        assert!(list.hit(&down(1.2, 0.5), 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn faces_use_normals_and_uvs_with_relative_indices() {
+        let list = parse_str(
+            "v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 0 1
+vn 0 1 0
+f -3/-3/1 -2/-2/1 -1/-1/1
+",
+        )
+        .unwrap();
+
+        let rec = list.hit(&down(0.25, 0.5), 0.001, f32::INFINITY).unwrap();
+        assert!((rec.normal.y - 1.0).abs() < 1e-5);
+        assert!((rec.uv.0 - 0.25).abs() < 1e-5 && (rec.uv.1 - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn groups_and_materials_make_separate_meshes() {
+        let list = parse_str(
+            "v 0 0 0
+v 1 0 0
+v 0 1 0
+o first
+f 1 2 3
+o second
+f 1 2 3
+o first
+f 3 2 1
+",
+        )
+        .unwrap();
+
+        assert_eq!(list.objects.len(), 2);
+    }
+
+    #[test]
+    fn files_without_faces_are_an_error() {
+        let result = parse_str("v 0 0 0\nv 1 0 0\nv 0 1 0\n");
+        assert!(matches!(result, Err(ObjError::NoFaces { .. })));
+        assert!(matches!(parse_str(""), Err(ObjError::NoFaces { .. })));
+    }
+
+    #[test]
+    fn errors_point_at_their_line() {
+        assert_eq!(error_line(parse_str("v 0 0 0\nv 1 0\n")), 2);
+        assert_eq!(error_line(parse_str("v 0 0 0\nv 1 0 0\nf 1 2\n")), 3);
+        assert_eq!(error_line(parse_str("v 0 0 0\nf 1 2 3\n")), 2);
+        assert_eq!(error_line(parse_str("v 0 0 0\nf 1/x 1 1\n")), 2);
+        assert_eq!(
+            error_line(parse_str(
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl missing\nf 1 2 3\n"
+            )),
+            4
+        );
+    }
+
+    #[test]
+    fn mtl_properties_are_read() {
+        let descs = parse_mtl_str(
+            "# two materials
+newmtl gold
+Kd 0.8 0.6 0.2
+Ks 1.0 0.8 0.4
+Ns 200
+illum 3
+
+newmtl glass
+d 0.5
+Ni 1.33
+",
+        )
+        .unwrap();
+
+        assert_eq!(descs.len(), 2);
+        let (name, gold) = &descs[0];
+        assert_eq!(name, "gold");
+        assert_eq!(gold.illum, 3);
+        assert_eq!(gold.shininess, 200.0);
+        assert_eq!(gold.metal_albedo().z, 0.4);
+
+        let (name, glass) = &descs[1];
+        assert_eq!(name, "glass");
+        assert_eq!(glass.dissolve, 0.5);
+        assert_eq!(glass.ior, 1.33);
+    }
+
+    #[test]
+    fn metals_without_ks_take_the_diffuse_color() {
+        let descs = parse_mtl_str("newmtl copper\nKd 0.9 0.5 0.3\nillum 3\n").unwrap();
+        let copper = &descs[0].1;
+
+        assert!(copper.specular.is_none());
+        assert_eq!(copper.metal_albedo().x, 0.9);
+    }
+
+    #[test]
+    fn mtl_errors_point_at_their_line() {
+        assert_eq!(error_line(parse_mtl_str("Kd 1 1 1\n")), 1);
+        assert_eq!(error_line(parse_mtl_str("newmtl a\n\nKd 1 x 1\n")), 3);
+        assert_eq!(error_line(parse_mtl_str("newmtl\n")), 1);
+    }
+}
@@ -2,8 +2,9 @@ use crate::{
     aabb::AABB,
     hittable::{HitRecord, Hittable},
     materials::Material,
+    onb::ONB,
     ray::Ray,
-    utils::PI,
+    utils::{random_2d, INFINITY, PI},
     vec3::{Point3, Vec3},
 };
 
@@ -31,7 +32,7 @@ impl<M: Material> Sphere<M> {
 }
 
 impl<M: Material> Hittable for Sphere<M> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let oc = ray.origin - self.center;
         let a = ray.direction.length_squared();
         let half_b = Vec3::dot(&oc, &ray.direction);
@@ -60,6 +61,7 @@ impl<M: Material> Hittable for Sphere<M> {
             uv: Self::get_uv(&outward_normal),
             p,
             mat: &self.material,
+            object_id: 0,
             // These two are set by set_face_normal
             normal: Vec3::from(0.0),
             front_face: true,
@@ -78,6 +80,52 @@ impl<M: Material> Hittable for Sphere<M> {
 
         Some(output_box)
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        if self
+            .hit(&Ray::new(*origin, *direction, 0.0), 0.001, INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+
+        // From the inside, the whole sphere of directions is sampled uniformly.
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
+        }
+
+        let cos_theta_max = f32::sqrt(1.0 - radius_squared / distance_squared);
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector();
+        }
+
+        let uvw = ONB::build_from_w(&direction);
+        uvw.local(&random_to_sphere(self.radius, distance_squared))
+    }
+}
+
+/// Uniformly samples a direction in the cone that a sphere at `distance_squared` covers, around
+/// the z axis.
+fn random_to_sphere(radius: f32, distance_squared: f32) -> Vec3 {
+    let (r1, r2) = random_2d();
+    let z = 1.0 + r2 * (f32::sqrt(1.0 - radius * radius / distance_squared) - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let x = f32::cos(phi) * f32::sqrt(1.0 - z * z);
+    let y = f32::sin(phi) * f32::sqrt(1.0 - z * z);
+
+    Vec3::new(x, y, z)
 }
 
 pub struct MovingSphere<M: Material> {
@@ -116,8 +164,8 @@ pub fn center<M: Material>(sphere: &MovingSphere<M>, time: f32) -> Point3 {
 }
 
 impl<M: Material> Hittable for MovingSphere<M> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin - center(&self, ray.time);
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let oc = ray.origin - center(self, ray.time);
         let a = ray.direction.length_squared();
         let half_b = Vec3::dot(&oc, &ray.direction);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -139,12 +187,13 @@ impl<M: Material> Hittable for MovingSphere<M> {
         }
 
         let p = ray.at(root);
-        let outward_normal = (p - center(&self, ray.time)) / self.radius;
+        let outward_normal = (p - center(self, ray.time)) / self.radius;
         let mut rec = HitRecord {
             t: root,
             uv: (0.0, 0.0),
             p,
             mat: &self.material,
+            object_id: 0,
             // These two are set by set_face_normal
             normal: Vec3::from(0.0),
             front_face: true,
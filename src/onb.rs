@@ -0,0 +1,29 @@
+use crate::vec3::Vec3;
+
+/// An orthonormal basis, used to turn directions sampled around the z axis into world space.
+#[allow(clippy::upper_case_acronyms)]
+pub struct ONB {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl ONB {
+    /// Builds a basis with `w` pointing along `n`.
+    pub fn build_from_w(n: &Vec3) -> Self {
+        let w = Vec3::unit_vector(*n);
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = Vec3::unit_vector(Vec3::cross(&w, &a));
+        let u = Vec3::cross(&w, &v);
+
+        Self { u, v, w }
+    }
+
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
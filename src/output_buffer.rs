@@ -1,42 +1,575 @@
-use crate::{utils::clamp, vec3::Color};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
 
+use exr::prelude::{AnyChannel, AnyChannels, FlatSamples, Image, SmallVec, WritableImage};
+use image::{
+    codecs::hdr::HdrEncoder,
+    error::{EncodingError, ImageFormatHint},
+    ImageError, ImageFormat, ImageResult, Rgb,
+};
+
+use crate::{
+    adaptive::{self, AdaptiveSampling},
+    aov::{Aov, AovPixel},
+    denoise::Denoiser,
+    filter::Filter,
+    tonemap::ToneMapping,
+    vec3::{Color, Vec3},
+};
+
+/// Named channels of an OpenEXR image, with their values in rows starting at the top of the image.
+type Channels = Vec<(String, Vec<f32>)>;
+
+/// Pixels with less alpha than this count as empty, dividing their colors by it would only blow up
+/// whatever noise is left in them.
+const MIN_ALPHA: f32 = 1e-4;
+
+/// The samples that went into a single pixel, summed up. With a reconstruction filter wider than
+/// the pixel, that includes samples taken for its neighbours, weighted by the filter.
+#[derive(Clone, Copy, Default)]
+pub struct PixelSums {
+    pub color: Color,
+    /// How many of the samples hit something.
+    pub coverage: f32,
+    /// Sum of the filter weights of the samples.
+    pub weight: f32,
+    /// The number of samples taken for this pixel itself.
+    pub count: u32,
+    /// Sums of the luminance of the pixel's own samples and of its square, to tell how noisy the
+    /// pixel is.
+    pub luminance: f32,
+    pub luminance_squares: f32,
+}
+
+impl PixelSums {
+    pub fn merge(&mut self, other: &PixelSums) {
+        self.color += other.color;
+        self.coverage += other.coverage;
+        self.weight += other.weight;
+        self.count += other.count;
+        self.luminance += other.luminance;
+        self.luminance_squares += other.luminance_squares;
+    }
+}
+
+/// The rendered image, kept as linear radiance until it gets saved.
+///
+/// With 4 channels the image gets an alpha channel, which is the fraction of the camera rays that
+/// hit something. The colors are premultiplied by it, since the background isn't part of them.
+///
+/// Any AOVs get saved along with the image, see `save`. The denoiser only runs when saving, so
+/// more samples can still be added afterwards. With adaptive sampling, `pass_samples` decides
+/// which pixels get more samples. Every pixel is the weighted average of the samples around it, the
+/// `Filter` decides how far they reach and how much they count.
 pub struct OutputBuffer {
-    buffer: Vec<u8>,
+    /// Sums of all samples taken so far for every pixel, in rows starting at the top of the image.
+    sums: Vec<PixelSums>,
     width: u32,
     height: u32,
     nr_channels: u32,
+    tone_mapping: ToneMapping,
+    aovs: Vec<Aov>,
+    /// Only allocated when there are AOVs to save, or the denoiser needs them.
+    aov_pixels: Vec<AovPixel>,
+    /// Whether any first hits got added to `aov_pixels`. They aren't part of checkpoints, so a
+    /// resumed render that takes no more samples has none.
+    has_first_hits: bool,
+    denoiser: Option<Denoiser>,
+    adaptive: Option<AdaptiveSampling>,
+    filter: Filter,
 }
 
 impl OutputBuffer {
+    /// `nr_channels` is either 3 for RGB or 4 for RGBA. Radiance `.hdr` files can't store alpha, so
+    /// those are always RGB.
     pub fn new(width: u32, height: u32, nr_channels: u32) -> Self {
-        let mut v = Vec::<u8>::new();
-        v.resize((width * height * nr_channels) as usize, 0);
+        if nr_channels != 3 && nr_channels != 4 {
+            panic!("Incorrect amount of channels! use either 3 or 4");
+        }
+
+        let pixel_count = (width * height) as usize;
+
         Self {
-            buffer: v,
+            sums: vec![PixelSums::default(); pixel_count],
             width,
             height,
             nr_channels,
+            tone_mapping: ToneMapping::default(),
+            aovs: Vec::new(),
+            aov_pixels: Vec::new(),
+            has_first_hits: false,
+            denoiser: None,
+            adaptive: None,
+            filter: Filter::default(),
+        }
+    }
+
+    /// How the radiance gets turned into colors for 8-bit images.
+    pub fn with_tone_mapping(mut self, tone_mapping: ToneMapping) -> Self {
+        self.tone_mapping = tone_mapping;
+        self
+    }
+
+    /// Saves these AOVs along with the image.
+    pub fn with_aovs(mut self, aovs: Vec<Aov>) -> Self {
+        self.aovs = aovs;
+        self.allocate_aov_pixels();
+        self
+    }
+
+    /// Denoises the image before saving it. The AOVs stay as they are.
+    pub fn with_denoiser(mut self, denoiser: Denoiser) -> Self {
+        self.denoiser = Some(denoiser);
+        self.allocate_aov_pixels();
+        self
+    }
+
+    /// Only adds samples to pixels that are still noisy, see `pass_samples`.
+    pub fn with_adaptive_sampling(mut self, adaptive: AdaptiveSampling) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Spreads the samples over the pixels around them with this filter, instead of keeping them
+    /// in the pixel they were taken for.
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    fn allocate_aov_pixels(&mut self) {
+        if self.records_first_hits() && self.aov_pixels.is_empty() {
+            self.aov_pixels = vec![AovPixel::default(); self.sums.len()];
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.nr_channels == 4
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    /// The sums of every pixel, in rows starting at the top of the image. For checkpoints.
+    pub fn accumulation(&self) -> &[PixelSums] {
+        &self.sums
+    }
+
+    pub fn accumulation_mut(&mut self) -> &mut [PixelSums] {
+        &mut self.sums
+    }
+
+    /// Whether the samples need to come with the first hits of their camera rays, see
+    /// `add_first_hits`.
+    pub fn records_first_hits(&self) -> bool {
+        !self.aovs.is_empty() || self.denoiser.is_some()
+    }
+
+    /// Adds samples to the pixel at `(x, y)`, where `y` counts from the bottom of the image.
+    pub fn add_samples(&mut self, x: u32, y: u32, sums: &PixelSums) {
+        let idx = self.index(x, y);
+        self.sums[idx].merge(sums);
+    }
+
+    /// Adds the first hits of the camera rays of the samples taken for the pixel at `(x, y)`, where
+    /// `y` counts from the bottom of the image.
+    pub fn add_first_hits(&mut self, x: u32, y: u32, first_hits: &AovPixel) {
+        let idx = self.index(x, y);
+        self.aov_pixels[idx].merge(first_hits);
+        self.has_first_hits = true;
+    }
+
+    /// Whether the first hits the AOVs and the denoiser need got recorded, see `add_first_hits`.
+    pub fn has_first_hits(&self) -> bool {
+        self.has_first_hits
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((self.height - y - 1) * self.width + x) as usize
+    }
+
+    /// How many samples the pixel at `(x, y)`, where `y` counts from the bottom, got so far.
+    pub fn sample_count(&self, x: u32, y: u32) -> u32 {
+        self.sums[self.index(x, y)].count
+    }
+
+    /// How many samples the pixel at `(x, y)`, where `y` counts from the bottom, should get in a
+    /// pass of `samples` samples per pixel. That's all of them, unless adaptive sampling decides
+    /// the pixel has converged or is close to its maximum.
+    pub fn pass_samples(&self, x: u32, y: u32, samples: u32) -> u32 {
+        let adaptive = match &self.adaptive {
+            Some(adaptive) => adaptive,
+            None => return samples,
+        };
+
+        // Average the error over the pixel and its neighbours, a handful of samples can easily all
+        // miss a small bright light, which would make a single pixel look converged.
+        let count = self.sample_count(x, y);
+        let mut error = 0.0;
+        let mut neighbours = 0;
+        for ny in y.saturating_sub(1)..u32::min(y + 2, self.height) {
+            for nx in x.saturating_sub(1)..u32::min(x + 2, self.width) {
+                let sums = &self.sums[self.index(nx, ny)];
+                error += adaptive::error(sums.luminance, sums.luminance_squares, sums.count);
+                neighbours += 1;
+            }
+        }
+
+        if !adaptive.needs_samples(count, error / neighbours as f32) {
+            return 0;
+        }
+
+        u32::min(samples, adaptive.max_samples - count)
+    }
+
+    /// How many pixels would get samples in the next pass.
+    pub fn unconverged_pixels(&self) -> usize {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.pass_samples(x, y, 1) > 0)
+            .count()
+    }
+
+    /// The weighted average radiance and alpha of every pixel, in rows starting at the top of the
+    /// image.
+    pub fn pixels(&self) -> impl Iterator<Item = (Color, f32)> + '_ {
+        self.sums.iter().map(|sums| {
+            // Filters with negative lobes can leave pixels without any weight at all.
+            if sums.weight <= 0.0 {
+                (Color::from(0.0), 0.0)
+            } else {
+                (sums.color / sums.weight, sums.coverage / sums.weight)
+            }
+        })
+    }
+
+    /// Writes the image to `path`. OpenEXR (`.exr`) and Radiance (`.hdr`) files keep the linear
+    /// radiance as is, anything else gets tone mapped to 8 bits per channel.
+    ///
+    /// The AOVs become layers of the same file for OpenEXR, for other formats they're written to
+    /// separate OpenEXR files next to it, e.g. `image.depth.exr`. Returns the paths of all the files
+    /// that got written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<Vec<PathBuf>> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let pixels = self.final_pixels();
+
+        match extension.as_deref() {
+            Some("exr") => {
+                let mut channels = self.beauty_channels(&pixels);
+                for (aov, aov_channels) in self.aov_channels() {
+                    channels.extend(aov_channels.into_iter().map(|(channel, samples)| {
+                        (format!("{}.{}", aov.name(), channel), samples)
+                    }));
+                }
+                self.write_exr(path, channels)?;
+
+                return Ok(vec![path.to_path_buf()]);
+            }
+            Some("hdr") => self.save_hdr(path, &pixels)?,
+            _ => self.save_ldr(path, &pixels)?,
+        }
+
+        let mut paths = vec![path.to_path_buf()];
+        for (aov, channels) in self.aov_channels() {
+            let aov_path = path.with_extension(format!("{}.exr", aov.name()));
+            self.write_exr(&aov_path, channels)?;
+            paths.push(aov_path);
+        }
+
+        Ok(paths)
+    }
+
+    /// Writes a heatmap of the number of samples every pixel got to `path`, as an 8-bit image.
+    /// The pixels with the most samples are the brightest.
+    pub fn save_heatmap<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        let most = self
+            .sums
+            .iter()
+            .map(|sums| sums.count)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let data: Vec<u8> = self
+            .sums
+            .iter()
+            .flat_map(|sums| adaptive::heatmap_color(sums.count as f32 / most as f32))
+            .collect();
+
+        image::save_buffer(path, &data, self.width, self.height, image::ColorType::Rgb8)
+    }
+
+    /// The average radiance and alpha of every pixel, denoised if there is a denoiser. Without
+    /// any first hits to guide it, the denoiser would blur across every edge, so then it's skipped.
+    fn final_pixels(&self) -> Vec<(Color, f32)> {
+        let mut pixels: Vec<(Color, f32)> = self.pixels().collect();
+
+        if let Some(denoiser) = self.denoiser.as_ref().filter(|_| self.has_first_hits) {
+            let colors: Vec<Color> = pixels.iter().map(|&(color, _)| color).collect();
+            let albedo: Vec<Color> = self.aov_pixels.iter().map(AovPixel::albedo).collect();
+            let normal: Vec<Vec3> = self.aov_pixels.iter().map(AovPixel::normal).collect();
+
+            let denoised = denoiser.denoise(self.width as usize, &colors, &albedo, &normal);
+            for (pixel, color) in pixels.iter_mut().zip(denoised) {
+                pixel.0 = color;
+            }
+        }
+
+        pixels
+    }
+
+    /// The channels of the image itself, for OpenEXR. That expects premultiplied alpha, just like
+    /// the buffer has it.
+    fn beauty_channels(&self, pixels: &[(Color, f32)]) -> Channels {
+        let mut channels: Channels = ["R", "G", "B", "A"]
+            .iter()
+            .take(self.nr_channels as usize)
+            .map(|&name| (name.to_string(), Vec::with_capacity(self.sums.len())))
+            .collect();
+
+        for &(color, alpha) in pixels {
+            let (color, alpha) = if self.has_alpha() {
+                clamp_alpha(color, alpha)
+            } else {
+                (color, alpha)
+            };
+            for (i, value) in [color.x, color.y, color.z, alpha]
+                .into_iter()
+                .take(channels.len())
+                .enumerate()
+            {
+                channels[i].1.push(value);
+            }
+        }
+
+        channels
+    }
+
+    /// The channels of every AOV.
+    fn aov_channels(&self) -> Vec<(Aov, Channels)> {
+        self.aovs
+            .iter()
+            .map(|&aov| {
+                let mut channels: Channels = aov
+                    .channels()
+                    .iter()
+                    .map(|&name| (name.to_string(), Vec::with_capacity(self.sums.len())))
+                    .collect();
+
+                for pixel in self.aov_pixels.iter() {
+                    for (i, value) in pixel.values(aov).into_iter().enumerate() {
+                        channels[i].1.push(value);
+                    }
+                }
+
+                (aov, channels)
+            })
+            .collect()
+    }
+
+    /// Writes the channels to a single-part OpenEXR file.
+    fn write_exr(&self, path: &Path, channels: Channels) -> ImageResult<()> {
+        let channels: SmallVec<_> = channels
+            .into_iter()
+            .map(|(name, samples)| AnyChannel::new(name.as_str(), FlatSamples::F32(samples)))
+            .collect();
+        let image = Image::from_channels(
+            (self.width as usize, self.height as usize),
+            AnyChannels::sort(channels),
+        );
+
+        image.write().to_file(path).map_err(|err| {
+            ImageError::Encoding(EncodingError::new(
+                ImageFormatHint::Exact(ImageFormat::OpenExr),
+                err,
+            ))
+        })
+    }
+
+    fn save_hdr(&self, path: &Path, pixels: &[(Color, f32)]) -> ImageResult<()> {
+        let data: Vec<Rgb<f32>> = pixels.iter().map(|(c, _)| Rgb([c.x, c.y, c.z])).collect();
+        let writer = BufWriter::new(File::create(path)?);
+
+        HdrEncoder::new(writer).encode(&data, self.width as usize, self.height as usize)
+    }
+
+    fn save_ldr(&self, path: &Path, pixels: &[(Color, f32)]) -> ImageResult<()> {
+        let color_type = if self.nr_channels == 3 {
+            image::ColorType::Rgb8
+        } else {
+            image::ColorType::Rgba8
+        };
+
+        image::save_buffer(
+            path,
+            &self.to_ldr(pixels),
+            self.width,
+            self.height,
+            color_type,
+        )
+    }
+
+    /// Tone maps the image to sRGB with 8 bits per channel. 8-bit formats like PNG store straight
+    /// alpha, so the colors get divided by it again first.
+    fn to_ldr(&self, pixels: &[(Color, f32)]) -> Vec<u8> {
+        let mut buffer = vec![0; (self.width * self.height * self.nr_channels) as usize];
+
+        for (pixel, &(color, alpha)) in buffer
+            .chunks_exact_mut(self.nr_channels as usize)
+            .zip(pixels)
+        {
+            if self.has_alpha() {
+                let (color, alpha) = clamp_alpha(color, alpha);
+                let color = if alpha > 0.0 { color / alpha } else { color };
+                pixel[..3].copy_from_slice(&self.tone_mapping.encode_srgb8(color));
+                pixel[3] = (255.0 * alpha + 0.5) as u8;
+            } else {
+                pixel[..3].copy_from_slice(&self.tone_mapping.encode_srgb8(color));
+            }
+        }
+
+        buffer
+    }
+}
+/// Filters with negative lobes can push the alpha of a pixel below 0 or above 1 near edges. This
+/// clamps it, and empties pixels with hardly any alpha left.
+fn clamp_alpha(color: Color, alpha: f32) -> (Color, f32) {
+    if alpha < MIN_ALPHA {
+        (Color::from(0.0), 0.0)
+    } else {
+        (color, f32::min(alpha, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(color: Color, coverage: f32, count: u32) -> PixelSums {
+        PixelSums {
+            color,
+            coverage,
+            weight: count as f32,
+            count,
+            ..Default::default()
         }
     }
 
-    pub fn write_color(&mut self, x: u32, y: u32, color: &Color, samples_per_pixel: u32) {
-        let mut r = color.x;
-        let mut g = color.y;
-        let mut b = color.z;
+    /// A 2×2 image, with a bright pixel in the bottom left corner.
+    fn buffer() -> OutputBuffer {
+        let mut buffer = OutputBuffer::new(2, 2, 3);
+        buffer.add_samples(0, 0, &samples(Color::new(8.0, 4.0, 2.0), 2.0, 2));
+        buffer.add_samples(0, 0, &samples(Color::new(4.0, 0.0, 0.0), 2.0, 2));
+        buffer.add_samples(1, 1, &samples(Color::from(0.25), 1.0, 1));
+        buffer
+    }
 
-        // Divide the color by the number of samples and gamma-correct for gamma=2.0
-        let scale = 1.0 / (samples_per_pixel as f32);
-        r = f32::sqrt(scale * r);
-        g = f32::sqrt(scale * g);
-        b = f32::sqrt(scale * b);
+    #[test]
+    fn pixels_average_their_samples_from_the_top_row_down() {
+        let pixels: Vec<Color> = buffer().pixels().map(|(color, _)| color).collect();
 
-        let idx = (((self.height - y - 1) * self.width + x) * self.nr_channels) as usize;
-        self.buffer[idx + 0] = (256.0 * clamp(r, 0.0, 0.999)) as u8;
-        self.buffer[idx + 1] = (256.0 * clamp(g, 0.0, 0.999)) as u8;
-        self.buffer[idx + 2] = (256.0 * clamp(b, 0.0, 0.999)) as u8;
+        assert_eq!([pixels[2].x, pixels[2].y, pixels[2].z], [3.0, 1.0, 0.5]);
+        assert_eq!(pixels[1].x, 0.25);
+        // No samples at all comes out black.
+        assert_eq!(pixels[0].x, 0.0);
     }
 
-    pub fn get_pixels(&self) -> &[u8] {
-        &self.buffer
+    #[test]
+    fn hdr_files_keep_the_radiance() {
+        let buffer = buffer();
+
+        for extension in ["exr", "hdr"] {
+            let path = std::env::temp_dir().join(format!("output_buffer_test.{}", extension));
+            buffer.save(&path).unwrap();
+
+            // The `image` crate only reads .hdr files as 8-bit, unless asked directly.
+            let pixels: Vec<Rgb<f32>> = if extension == "hdr" {
+                let file = std::io::BufReader::new(File::open(&path).unwrap());
+                image::codecs::hdr::HdrDecoder::new(file)
+                    .unwrap()
+                    .read_image_hdr()
+                    .unwrap()
+            } else {
+                let image = image::open(&path).unwrap().into_rgb32f();
+                image.pixels().copied().collect()
+            };
+            std::fs::remove_file(&path).unwrap();
+
+            let bright = pixels[2];
+            assert!(
+                (bright[0] - 3.0).abs() < 0.05,
+                "{}: {:?}",
+                extension,
+                bright
+            );
+            assert!(
+                (bright[2] - 0.5).abs() < 0.01,
+                "{}: {:?}",
+                extension,
+                bright
+            );
+            assert!((pixels[1][1] - 0.25).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn ldr_output_is_srgb_encoded_and_clamped() {
+        let buffer = buffer();
+        let ldr = buffer.to_ldr(&buffer.final_pixels());
+
+        assert_eq!(&ldr[6..9], &[255, 255, 188]);
+        assert_eq!(ldr[3], 137);
+        assert_eq!(&ldr[0..3], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn alpha_is_the_coverage_and_gets_divided_out_of_8_bit_images() {
+        let mut buffer = OutputBuffer::new(2, 1, 4);
+        // Two of the four samples hit something with a radiance of 0.5, the others saw nothing.
+        buffer.add_samples(0, 0, &samples(Color::from(1.0), 2.0, 4));
+
+        let pixels: Vec<(Color, f32)> = buffer.pixels().collect();
+        assert_eq!(pixels[0].0.x, 0.25);
+        assert_eq!(pixels[0].1, 0.5);
+
+        let ldr = buffer.to_ldr(&buffer.final_pixels());
+        assert_eq!(&ldr[0..4], &[188, 188, 188, 128]);
+        // Pixels without samples are fully transparent.
+        assert_eq!(&ldr[4..8], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn alpha_stays_between_0_and_1() {
+        let buffer = OutputBuffer::new(3, 1, 4);
+        let pixels = [
+            (Color::from(0.5), -0.25),
+            (Color::from(1e-3), 1e-6),
+            (Color::from(2.0), 1.5),
+        ];
+
+        let ldr = buffer.to_ldr(&pixels);
+        assert_eq!(&ldr[0..4], &[0, 0, 0, 0]);
+        assert_eq!(&ldr[4..8], &[0, 0, 0, 0]);
+        assert_eq!(ldr[11], 255);
+
+        let channels = buffer.beauty_channels(&pixels);
+        assert_eq!(channels[3].1, [0.0, 0.0, 1.0]);
+        assert_eq!(channels[0].1[..2], [0.0, 0.0]);
     }
 }
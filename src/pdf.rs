@@ -0,0 +1,176 @@
+use crate::{
+    hittable::Hittable,
+    onb::ONB,
+    utils::{random_2d, random_index, PI},
+    vec3::{Point3, Vec3},
+};
+
+/// A distribution of directions that can be both sampled and evaluated.
+pub trait Pdf {
+    /// Probability density of `direction`, over solid angle.
+    fn value(&self, direction: &Vec3) -> f32;
+    fn generate(&self) -> Vec3;
+}
+
+/// Directions around a normal, with a density proportional to the cosine with the normal.
+pub struct CosinePdf {
+    uvw: ONB,
+}
+
+impl CosinePdf {
+    pub fn new(w: &Vec3) -> Self {
+        Self {
+            uvw: ONB::build_from_w(w),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let cosine = Vec3::dot(&Vec3::unit_vector(*direction), &self.uvw.w);
+        if cosine <= 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.uvw.local(&random_cosine_direction())
+    }
+}
+
+fn random_cosine_direction() -> Vec3 {
+    let (r1, r2) = random_2d();
+    let z = f32::sqrt(1.0 - r2);
+
+    let phi = 2.0 * PI * r1;
+    let x = f32::cos(phi) * f32::sqrt(r2);
+    let y = f32::sin(phi) * f32::sqrt(r2);
+
+    Vec3::new(x, y, z)
+}
+
+/// Directions spread evenly over the whole sphere.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+}
+
+/// Directions from `origin` towards points on an object.
+pub struct HittablePdf<'a> {
+    origin: Point3,
+    object: &'a dyn Hittable,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(object: &'a dyn Hittable, origin: Point3) -> Self {
+        Self { origin, object }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        self.object.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.object.random(&self.origin)
+    }
+}
+
+/// An equally weighted mix of other distributions.
+pub struct MixturePdf<'a> {
+    pdfs: Vec<Box<dyn Pdf + 'a>>,
+}
+
+impl<'a> MixturePdf<'a> {
+    /// Returns `None` without any `pdfs`, there's nothing to sample then.
+    pub fn new(pdfs: Vec<Box<dyn Pdf + 'a>>) -> Option<Self> {
+        if pdfs.is_empty() {
+            return None;
+        }
+
+        Some(Self { pdfs })
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let total: f32 = self.pdfs.iter().map(|pdf| pdf.value(direction)).sum();
+        total / self.pdfs.len() as f32
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.pdfs[random_index(self.pdfs.len())].generate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aarect::XZRect, materials::Lambertian, vec3::Color};
+
+    /// Estimates the integral of the density over all directions, which should be 1.
+    fn integrate(pdf: &dyn Pdf) -> f32 {
+        const SAMPLES: usize = 200_000;
+        let total: f32 = (0..SAMPLES)
+            .map(|_| pdf.value(&Vec3::random_unit_vector()))
+            .sum();
+
+        4.0 * PI * total / SAMPLES as f32
+    }
+
+    #[test]
+    fn cosine_pdf_is_normalized_and_samples_the_hemisphere() {
+        let normal = Vec3::unit_vector(Vec3::new(1.0, 2.0, -0.5));
+        let pdf = CosinePdf::new(&normal);
+
+        assert!((integrate(&pdf) - 1.0).abs() < 0.02);
+        assert!((pdf.value(&normal) - 1.0 / PI).abs() < 1e-5);
+        assert_eq!(pdf.value(&-normal), 0.0);
+        for _ in 0..1000 {
+            assert!(Vec3::dot(&pdf.generate(), &normal) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn hittable_pdf_is_normalized_and_samples_the_object() {
+        let rect = XZRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, Lambertian::new(Color::from(0.5)));
+        let pdf = HittablePdf::new(&rect, Point3::new(0.0, 0.5, 0.0));
+
+        assert!((integrate(&pdf) - 1.0).abs() < 0.03);
+        assert_eq!(pdf.value(&Vec3::new(0.0, 1.0, 0.0)), 0.0);
+        for _ in 0..1000 {
+            assert!(pdf.value(&pdf.generate()) > 0.0);
+        }
+    }
+
+    #[test]
+    fn mixtures_average_their_pdfs() {
+        assert!(MixturePdf::new(Vec::new()).is_none());
+
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let mixture = MixturePdf::new(vec![
+            Box::new(CosinePdf::new(&up)),
+            Box::new(CosinePdf::new(&-up)),
+        ])
+        .unwrap();
+
+        assert!((integrate(&mixture) - 1.0).abs() < 0.02);
+        assert!((mixture.value(&up) - 0.5 / PI).abs() < 1e-5);
+        assert!((mixture.value(&-up) - 0.5 / PI).abs() < 1e-5);
+
+        let below = (0..1000)
+            .filter(|_| Vec3::dot(&mixture.generate(), &up) < 0.0)
+            .count();
+        assert!((400..600).contains(&below));
+    }
+}
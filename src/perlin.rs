@@ -1,6 +1,7 @@
-use rand::Rng;
-
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    utils::random_index,
+    vec3::{Point3, Vec3},
+};
 
 const POINT_COUNT: usize = 256;
 
@@ -14,7 +15,7 @@ pub struct Perlin {
 impl Perlin {
     pub fn new() -> Self {
         let mut ranvec: Vec<Vec3> = Vec::with_capacity(POINT_COUNT);
-        for i in 0..POINT_COUNT {
+        for _ in 0..POINT_COUNT {
             ranvec.push(Vec3::random_in_unit_sphere());
         }
 
@@ -67,7 +68,7 @@ impl Perlin {
     }
 
     pub fn turb(&self, p: &Point3) -> f32 {
-        self.turb_scale(&p, 7)
+        self.turb_scale(p, 7)
     }
 
     pub fn turb_scale(&self, p: &Point3, depth: i32) -> f32 {
@@ -96,12 +97,10 @@ fn generate_perm() -> Vec<i32> {
     randfloat
 }
 
-fn permutate(p: &mut Vec<i32>) {
+fn permutate(p: &mut [i32]) {
     for i in (0..p.len()).rev() {
-        let target = rand::thread_rng().gen_range(0..p.len());
-        let tmp = p[i];
-        p[i] = p[target];
-        p[target] = tmp;
+        let target = random_index(p.len());
+        p.swap(i, target);
     }
 }
 
@@ -111,19 +110,19 @@ fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
     let ww = w * w * (3.0 - 2.0 * w);
     let mut accum = 0.0;
 
-    for i in 0..2 {
-        for j in 0..2 {
-            for k in 0..2 {
-                let weight_v = Vec3::new(u - (i as f32), v - (j as f32), w - (k as f32));
-
+    for (i, c_i) in c.iter().enumerate() {
+        for (j, c_ij) in c_i.iter().enumerate() {
+            for (k, c_ijk) in c_ij.iter().enumerate() {
                 let i_f = i as f32;
                 let j_f = j as f32;
                 let k_f = k as f32;
 
+                let weight_v = Vec3::new(u - i_f, v - j_f, w - k_f);
+
                 accum += (i_f * uu + (1.0 - i_f) * (1.0 - uu))
                     * (j_f * vv + (1.0 - j_f) * (1.0 - vv))
                     * (k_f * ww + (1.0 - k_f) * (1.0 - ww))
-                    * Vec3::dot(&c[i][j][k], &weight_v);
+                    * Vec3::dot(c_ijk, &weight_v);
             }
         }
     }
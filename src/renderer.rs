@@ -0,0 +1,527 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+
+use crate::{
+    aov::{AovPixel, FirstHit},
+    camera::Camera,
+    filter::Filter,
+    hittable::{HitRecord, Hittable},
+    materials::ScatterRecord,
+    output_buffer::{OutputBuffer, PixelSums},
+    pdf::{MixturePdf, Pdf},
+    ray::Ray,
+    scenes::SceneInfo,
+    utils::{random_2d, random_index, set_sampler, start_sample, INFINITY},
+    vec3::{Color, Point3, Vec3},
+};
+
+/// Width and height (in pixels) of the square tiles the image gets split up in.
+pub const TILE_SIZE: u32 = 32;
+
+/// A rectangular region of the image, from `(x0, y0)` inclusive to `(x1, y1)` exclusive.
+#[derive(Clone, Copy)]
+struct Tile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+fn make_tiles(width: u32, height: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+
+    // Start at the top of the image, so that tiles finish roughly in reading order.
+    for y0 in (0..height).step_by(TILE_SIZE as usize).rev() {
+        for x0 in (0..width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                x0,
+                y0,
+                x1: u32::min(x0 + TILE_SIZE, width),
+                y1: u32::min(y0 + TILE_SIZE, height),
+            });
+        }
+    }
+
+    tiles
+}
+
+/// What rendering a tile adds to the image. The filter spreads the samples beyond the tile itself,
+/// so `sums` covers its `area`, the tile grown by the filter's margin, in row-major order. The first
+/// hits of the camera rays only count for the pixels of the tile.
+struct TileFilm {
+    area: Tile,
+    sums: Vec<PixelSums>,
+    first_hits: Vec<AovPixel>,
+}
+
+impl TileFilm {
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((y - self.area.y0) * (self.area.x1 - self.area.x0) + x - self.area.x0) as usize
+    }
+}
+
+/// A pass over the whole image, which adds the same number of samples to every pixel, apart from
+/// the ones the buffer's adaptive sampling leaves out.
+#[derive(Clone, Copy)]
+pub struct Pass {
+    pub samples: u32,
+    /// Where the random numbers of the render come from. Every sample derives its own from this,
+    /// its pixel and its index, so the samples don't depend on which thread happens to render which
+    /// tile, or on how the render got split up in passes.
+    pub seed: u64,
+}
+
+/// Renders a pass of the scene into `buffer`, spreading the tiles of the image over the rayon
+/// thread pool.
+pub fn render(scene: &SceneInfo, cam: &Camera, buffer: &mut OutputBuffer, pass: &Pass) {
+    let tiles = make_tiles(scene.image_width, scene.image_height);
+    let tiles_remaining = AtomicUsize::new(tiles.len());
+    let transparent_background = buffer.has_alpha();
+    let first_hits = buffer.records_first_hits();
+    let filter = *buffer.filter();
+
+    // The index of the first sample and the number of samples for every pixel, in rows starting at
+    // the bottom of the image.
+    let width = scene.image_width;
+    let pixel_samples: Vec<(u32, u32)> = (0..width * scene.image_height)
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            (
+                buffer.sample_count(x, y),
+                buffer.pass_samples(x, y, pass.samples),
+            )
+        })
+        .collect();
+
+    let films: Vec<TileFilm> = tiles
+        .par_iter()
+        .map(|tile| {
+            set_sampler(scene.sampler.create(pass.seed, scene.samples_per_pixel));
+            let film = render_tile(
+                scene,
+                cam,
+                tile,
+                &filter,
+                &pixel_samples,
+                transparent_background,
+                first_hits,
+            );
+
+            let remaining = tiles_remaining.fetch_sub(1, Ordering::Relaxed) - 1;
+            print!("\rTiles remaining: {:04}", remaining);
+            std::io::stdout().flush().unwrap();
+
+            film
+        })
+        .collect();
+
+    // Neighbouring tiles overlap where the filter reaches beyond them. Adding them up in the same
+    // order every time keeps the image the same, whichever tiles happened to finish first.
+    for (tile, film) in tiles.iter().zip(films) {
+        let mut sums = film.sums.iter();
+        for j in film.area.y0..film.area.y1 {
+            for i in film.area.x0..film.area.x1 {
+                buffer.add_samples(i, j, sums.next().unwrap());
+            }
+        }
+
+        // There are none of these unless the buffer records first hits.
+        let pixels = (tile.y0..tile.y1).flat_map(|j| (tile.x0..tile.x1).map(move |i| (i, j)));
+        for ((i, j), first_hits) in pixels.zip(film.first_hits.iter()) {
+            buffer.add_first_hits(i, j, first_hits);
+        }
+    }
+}
+
+/// Renders the samples of a pass for a single tile, splatting them onto the pixels around them
+/// with the `filter`. `pixel_samples` holds the index of the first sample and the number of samples
+/// for every pixel of the image. With a `transparent_background`, camera rays that miss everything
+/// don't pick up the background. The `first_hits` of the camera rays only get recorded when asked
+/// for.
+fn render_tile(
+    scene: &SceneInfo,
+    cam: &Camera,
+    tile: &Tile,
+    filter: &Filter,
+    pixel_samples: &[(u32, u32)],
+    transparent_background: bool,
+    first_hits: bool,
+) -> TileFilm {
+    let margin = filter.margin();
+    let area = Tile {
+        x0: tile.x0.saturating_sub(margin),
+        y0: tile.y0.saturating_sub(margin),
+        x1: u32::min(tile.x1 + margin, scene.image_width),
+        y1: u32::min(tile.y1 + margin, scene.image_height),
+    };
+    let mut film = TileFilm {
+        area,
+        sums: vec![PixelSums::default(); ((area.x1 - area.x0) * (area.y1 - area.y0)) as usize],
+        first_hits: Vec::new(),
+    };
+    // The filter weights of a sample for the columns of pixels it reaches.
+    let mut column_weights = Vec::with_capacity(2 * margin as usize + 1);
+
+    for j in tile.y0..tile.y1 {
+        for i in tile.x0..tile.x1 {
+            let (first_sample, count) = pixel_samples[(j * scene.image_width + i) as usize];
+            let own = film.index(i, j);
+            film.sums[own].count += count;
+            let mut aov = AovPixel::default();
+
+            let (x0, x1) = (i.saturating_sub(margin), u32::min(i + margin + 1, area.x1));
+            let (y0, y1) = (j.saturating_sub(margin), u32::min(j + margin + 1, area.y1));
+
+            for index in first_sample..first_sample + count {
+                start_sample((i, j), index);
+
+                let (du, dv) = random_2d();
+                let u = ((i as f32) + du) / (scene.image_width - 1) as f32;
+                let v = ((j as f32) + dv) / (scene.image_height - 1) as f32;
+
+                let r = cam.get_ray(u, v);
+                let (color, coverage) = match scene.world.hit(&r, 0.001, INFINITY) {
+                    Some(rec) => {
+                        if first_hits {
+                            aov.add(&FirstHit::new(&r, &rec));
+                        }
+                        (shade(&r, &rec, scene, scene.max_depth, None), 1.0)
+                    }
+                    None if transparent_background => (Color::from(0.0), 0.0),
+                    None => (scene.background.value(&r.direction), 0.0),
+                };
+
+                let luminance = color.luminance();
+                film.sums[own].luminance += luminance;
+                film.sums[own].luminance_squares += luminance * luminance;
+
+                // Offsets from the centers of the pixels, relative to this one to keep them exact.
+                column_weights.clear();
+                column_weights
+                    .extend((x0..x1).map(|x| filter.evaluate(x as f32 - i as f32 + 0.5 - du)));
+
+                for y in y0..y1 {
+                    let row_weight = filter.evaluate(y as f32 - j as f32 + 0.5 - dv);
+                    if row_weight == 0.0 {
+                        continue;
+                    }
+
+                    for (x, &column_weight) in (x0..x1).zip(column_weights.iter()) {
+                        let weight = row_weight * column_weight;
+                        if weight == 0.0 {
+                            continue;
+                        }
+
+                        let idx = film.index(x, y);
+                        let sums = &mut film.sums[idx];
+                        sums.color += weight * color;
+                        sums.coverage += weight * coverage;
+                        sums.weight += weight;
+                    }
+                }
+            }
+
+            if first_hits {
+                film.first_hits.push(aov);
+            }
+        }
+    }
+
+    film
+}
+
+/// Where a ray that was scattered by sampling a material's pdf came from, needed to weigh the
+/// light it finds against sampling the lights directly.
+struct PdfScatter {
+    origin: Point3,
+    pdf: f32,
+}
+
+/// Traces a ray through the scene. `pdf_scatter` is set when the ray was scattered by a material
+/// that also sampled the lights directly.
+fn ray_color(r: &Ray, scene: &SceneInfo, depth: i32, pdf_scatter: Option<PdfScatter>) -> Color {
+    // If we've exceeded the ray bounce limit, no more light is gathered.
+    if depth <= 0 {
+        return Color::from(0.0);
+    }
+
+    let rec = match scene.world.hit(r, 0.001, INFINITY) {
+        Some(rec) => rec,
+        // If the ray hits nothing, return the background
+        None => {
+            let mut background = scene.background.value(&r.direction);
+            if let Some(pdf_scatter) = pdf_scatter {
+                let light_pdf = lights_pdf(scene, &pdf_scatter.origin, &r.direction, true);
+                background *= power_heuristic(pdf_scatter.pdf, light_pdf);
+            }
+            return background;
+        }
+    };
+
+    shade(r, &rec, scene, depth, pdf_scatter)
+}
+
+/// The light leaving the hit `rec` back along `r`, see `ray_color`.
+fn shade(
+    r: &Ray,
+    rec: &HitRecord,
+    scene: &SceneInfo,
+    depth: i32,
+    pdf_scatter: Option<PdfScatter>,
+) -> Color {
+    let mut emitted = rec.mat.emitted(&rec.uv, &rec.p);
+    if let Some(pdf_scatter) = pdf_scatter {
+        if rec.mat.is_emissive() {
+            let light_pdf = lights_pdf(scene, &pdf_scatter.origin, &r.direction, false);
+            emitted *= power_heuristic(pdf_scatter.pdf, light_pdf);
+        }
+    }
+
+    match rec.mat.scatter(r, rec) {
+        None => emitted,
+        Some(ScatterRecord::Specular { ray, attenuation }) => {
+            emitted + attenuation * ray_color(&ray, scene, depth - 1, None)
+        }
+        Some(ScatterRecord::Pdf(pdf)) => {
+            let direct = sample_lights(r, rec, scene, &*pdf);
+
+            let direction = Vec3::unit_vector(pdf.generate());
+            let pdf_value = pdf.value(&direction);
+            if pdf_value <= 0.0 {
+                return emitted + direct;
+            }
+
+            let scattered = Ray::new(rec.p, direction, r.time);
+            let weight = rec.mat.bsdf(r, rec, &direction) / pdf_value;
+            let pdf_scatter = PdfScatter {
+                origin: rec.p,
+                pdf: pdf_value,
+            };
+
+            emitted + direct + weight * ray_color(&scattered, scene, depth - 1, Some(pdf_scatter))
+        }
+    }
+}
+
+/// Estimates the light arriving at a hit straight from the scene's lights, by picking a spot on
+/// one of them and tracing a shadow ray towards it. `material_pdf` is the distribution the material
+/// scatters rays with, the two strategies are combined with multiple importance sampling.
+fn sample_lights(r: &Ray, rec: &HitRecord, scene: &SceneInfo, material_pdf: &dyn Pdf) -> Color {
+    if scene.lights.is_empty() {
+        return Color::from(0.0);
+    }
+
+    let light_count = scene.lights.len();
+    let light = &scene.lights[random_index(light_count)];
+
+    let sample = match light.sample(&rec.p, r.time) {
+        Some(sample) => sample,
+        None => return Color::from(0.0),
+    };
+
+    // No need for a shadow ray if the material doesn't scatter light from there anyway.
+    let bsdf = rec.mat.bsdf(r, rec, &sample.direction);
+    if bsdf.near_zero() {
+        return Color::from(0.0);
+    }
+
+    let shadow_ray = Ray::new(rec.p, sample.direction, r.time);
+    let transmittance = scene
+        .world
+        .transmittance(&shadow_ray, 0.001, sample.distance - 0.001);
+    if transmittance <= 0.0 {
+        return Color::from(0.0);
+    }
+
+    // Every light is picked with the same probability.
+    let light_pdf = sample.pdf / light_count as f32;
+    let weight = if light.is_delta() {
+        1.0
+    } else {
+        power_heuristic(light_pdf, material_pdf.value(&sample.direction))
+    };
+
+    bsdf * sample.radiance * transmittance * weight / light_pdf
+}
+
+/// Density of `sample_lights` picking `direction` from `origin`. Only the lights that the ray could
+/// have reached count: the infinite ones if it escaped the scene, or the others if it didn't.
+fn lights_pdf(scene: &SceneInfo, origin: &Point3, direction: &Vec3, escaped: bool) -> f32 {
+    let reachable: Vec<_> = scene
+        .lights
+        .iter()
+        .filter(|light| light.is_infinite() == escaped)
+        .map(|light| light.pdf(origin))
+        .collect();
+
+    // Every light is picked with the same probability, so the reachable ones only get picked
+    // for their share of the time.
+    let share = reachable.len() as f32 / scene.lights.len() as f32;
+    MixturePdf::new(reachable).map_or(0.0, |pdf| share * pdf.value(direction))
+}
+
+/// Veach's power heuristic (with an exponent of 2), the weight for a sample taken with a strategy
+/// of density `pdf`, when another strategy with density `other_pdf` could have taken it as well.
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+
+    a / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        aarect::XZRect,
+        adaptive::AdaptiveSampling,
+        aov::Aov,
+        bvh::BVHBuilder,
+        checkpoint::Progress,
+        environment::EnvironmentMap,
+        filter::FilterKind,
+        lights::{AreaLight, Light},
+        materials::DiffuseLight,
+        scenes,
+        utils::seed_random,
+    };
+
+    const SEED: u64 = 42;
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        for (width, height) in [(1, 1), (32, 32), (33, 31), (100, 75), (400, 225)] {
+            let mut covered = vec![0; (width * height) as usize];
+            for tile in make_tiles(width, height) {
+                assert!(tile.x0 < tile.x1 && tile.x1 <= width);
+                assert!(tile.y0 < tile.y1 && tile.y1 <= height);
+                assert!(tile.x1 - tile.x0 <= TILE_SIZE && tile.y1 - tile.y0 <= TILE_SIZE);
+
+                for j in tile.y0..tile.y1 {
+                    for i in tile.x0..tile.x1 {
+                        covered[(j * width + i) as usize] += 1;
+                    }
+                }
+            }
+
+            assert!(
+                covered.iter().all(|&count| count == 1),
+                "{}x{}",
+                width,
+                height
+            );
+        }
+    }
+
+    #[test]
+    fn lights_pdf_only_counts_the_reachable_lights() {
+        let lamp = Arc::new(XZRect::new(
+            -1.0,
+            1.0,
+            -1.0,
+            1.0,
+            2.0,
+            DiffuseLight::from_color(Color::from(1.0)),
+        ));
+        let sky = Arc::new(EnvironmentMap::new(2, 1, vec![Color::from(1.0); 2]));
+        let scene = SceneInfo {
+            lights: vec![Arc::new(AreaLight::new(lamp.clone())), sky.clone()],
+            ..Default::default()
+        };
+
+        let origin = Point3::from(0.0);
+        let direction = Vec3::new(0.1, 1.0, 0.2);
+        let lamp_pdf = lamp.pdf_value(&origin, &direction);
+        let sky_pdf = sky.pdf(&origin).value(&direction);
+        assert!(lamp_pdf > 0.0 && sky_pdf > 0.0);
+
+        // Either light gets picked half the time.
+        assert!((lights_pdf(&scene, &origin, &direction, false) - 0.5 * lamp_pdf).abs() < 1e-6);
+        assert!((lights_pdf(&scene, &origin, &direction, true) - 0.5 * sky_pdf).abs() < 1e-6);
+
+        let no_lights = SceneInfo::default();
+        assert_eq!(lights_pdf(&no_lights, &origin, &direction, false), 0.0);
+        assert_eq!(lights_pdf(&no_lights, &origin, &direction, true), 0.0);
+    }
+
+    /// Builds a small `random_scene` and renders a few adaptive passes of it with a wide filter on
+    /// a pool of `threads` threads. Returns the bits of everything the buffer accumulated.
+    fn render_with_threads(threads: usize) -> Vec<[u32; 8]> {
+        seed_random(SEED);
+        let mut scene = scenes::random_scene(&mut BVHBuilder::default());
+        scene.image_width = 48;
+        scene.image_height = 27;
+        scene.samples_per_pixel = 4;
+        scene.max_depth = 8;
+
+        let cam = Camera::new(
+            scene.lookfrom,
+            scene.lookat,
+            Vec3::new(0.0, 1.0, 0.0),
+            scene.vfov,
+            scene.image_width as f32 / scene.image_height as f32,
+            scene.aperture,
+            scene.focus_dist,
+            scene.time0,
+            scene.time1,
+        );
+
+        let mut buffer = OutputBuffer::new(scene.image_width, scene.image_height, 3)
+            .with_aovs(vec![Aov::Depth])
+            .with_adaptive_sampling(AdaptiveSampling {
+                threshold: 0.02,
+                min_samples: 4,
+                max_samples: 16,
+            })
+            .with_filter(Filter::new(FilterKind::Mitchell, None));
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let mut progress = Progress::new(SEED);
+        pool.install(|| {
+            for _ in 0..4 {
+                let pass = progress.next_pass(4);
+                render(&scene, &cam, &mut buffer, &pass);
+                progress.finish_pass(&pass);
+            }
+        });
+
+        buffer
+            .accumulation()
+            .iter()
+            .map(|sums| {
+                [
+                    sums.color.x.to_bits(),
+                    sums.color.y.to_bits(),
+                    sums.color.z.to_bits(),
+                    sums.coverage.to_bits(),
+                    sums.weight.to_bits(),
+                    sums.count,
+                    sums.luminance.to_bits(),
+                    sums.luminance_squares.to_bits(),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renders_are_the_same_on_any_number_of_threads() {
+        let single = render_with_threads(1);
+        let multi = render_with_threads(7);
+
+        assert_eq!(single.len(), multi.len());
+        if let Some(pixel) = (0..single.len()).find(|&i| single[i] != multi[i]) {
+            panic!(
+                "pixel {} differs: {:?} on 1 thread, {:?} on 7",
+                pixel, single[pixel], multi[pixel]
+            );
+        }
+    }
+}
@@ -0,0 +1,569 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::utils::mix_seed;
+
+/// Where the values in `[0, 1)` that drive every random decision of a sample come from.
+///
+/// Every sample of a pixel asks for a sequence of dimensions: first the position in the pixel, then
+/// the lens, the time, and then whatever the lights and materials along the path need. Samplers
+/// other than `Independent` spread the values of a dimension evenly over the samples of a pixel,
+/// which converges faster than purely random values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    /// Unrelated random values.
+    Independent,
+    /// Every batch of samples per pixel gets one sample in every stratum, with a random offset
+    /// within it.
+    Stratified,
+    /// The Halton sequence, Owen scrambled differently for every pixel.
+    Halton,
+    /// The Sobol sequence with hash-based Owen scrambling, from "Practical Hash-based Owen
+    /// Scrambling" by Brent Burley.
+    Sobol,
+}
+
+impl SamplerKind {
+    /// A new sampler, whose values only depend on `seed`, the pixel and the index of the sample.
+    /// Stratification works in batches of `samples_per_pixel` samples.
+    pub fn create(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        let state = SampleState::new(seed);
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler { state }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler {
+                state,
+                samples_per_pixel: u32::max(samples_per_pixel, 1),
+            }),
+            SamplerKind::Halton => Box::new(HaltonSampler { state }),
+            SamplerKind::Sobol => Box::new(SobolSampler { state }),
+        }
+    }
+}
+
+pub trait Sampler {
+    /// Starts sample `index` of the pixel at `pixel`, going back to the first dimension.
+    fn start_sample(&mut self, pixel: (u32, u32), index: u32);
+
+    /// The value of the next dimension.
+    fn next_1d(&mut self) -> f32;
+
+    /// The values of the next two dimensions, which are spread out evenly as pairs as well.
+    fn next_2d(&mut self) -> (f32, f32);
+}
+
+/// The dimensions of Halton sequence, beyond these the values are random.
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Direction numbers of the second dimension of the Sobol sequence, the first one just reverses
+/// the bits of the index.
+const SOBOL_DIRECTIONS: [u32; 32] = sobol_directions();
+
+const fn sobol_directions() -> [u32; 32] {
+    let mut directions = [0; 32];
+    directions[0] = 1 << 31;
+
+    let mut i = 1;
+    while i < 32 {
+        directions[i] = directions[i - 1] ^ (directions[i - 1] >> 1);
+        i += 1;
+    }
+
+    directions
+}
+
+/// Which sample of which pixel is being taken, shared by all the samplers.
+struct SampleState {
+    seed: u64,
+    /// Identifies the pixel, derived from the seed.
+    pixel_seed: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl SampleState {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn start_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel_seed = mix_seed(self.seed, (pixel.0 as u64) << 32 | pixel.1 as u64);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    /// Claims the next `count` dimensions, returning the first one.
+    fn take_dimensions(&mut self, count: u32) -> u32 {
+        let dimension = self.dimension;
+        self.dimension = self.dimension.wrapping_add(count);
+        dimension
+    }
+
+    /// A hash of the pixel and `value`, the same for every sample of the pixel.
+    fn pixel_hash(&self, value: u64) -> u64 {
+        mix_seed(self.pixel_seed, value)
+    }
+
+    /// A random value for `dimension` of the current sample.
+    fn random(&self, dimension: u32) -> f32 {
+        let sample_seed = mix_seed(self.pixel_seed, self.index as u64);
+        to_float(mix_seed(sample_seed, dimension as u64) as u32)
+    }
+}
+
+struct IndependentSampler {
+    state: SampleState,
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.state.start_sample(pixel, index);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.state.take_dimensions(1);
+        self.state.random(dimension)
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let dimension = self.state.take_dimensions(2);
+        (
+            self.state.random(dimension),
+            self.state.random(dimension.wrapping_add(1)),
+        )
+    }
+}
+
+struct StratifiedSampler {
+    state: SampleState,
+    samples_per_pixel: u32,
+}
+
+impl StratifiedSampler {
+    /// The stratum of the current sample, out of `strata`, for `dimension`. Every batch of samples
+    /// visits the strata in a different order.
+    fn stratum(&self, dimension: u32, strata: u32) -> u32 {
+        let batch = self.state.index / self.samples_per_pixel;
+        let seed = self
+            .state
+            .pixel_hash((batch as u64) << 32 | dimension as u64);
+        permute(
+            self.state.index % self.samples_per_pixel,
+            strata,
+            seed as u32,
+        )
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.state.start_sample(pixel, index);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.state.take_dimensions(1);
+        let stratum = self.stratum(dimension, self.samples_per_pixel);
+
+        (stratum as f32 + self.state.random(dimension)) / self.samples_per_pixel as f32
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let dimension = self.state.take_dimensions(2);
+
+        // A grid that's as square as possible, with at least a cell for every sample.
+        let columns = u32::max(f32::sqrt(self.samples_per_pixel as f32) as u32, 1);
+        let rows = self.samples_per_pixel.div_ceil(columns);
+        let stratum = self.stratum(dimension, columns * rows);
+
+        (
+            ((stratum % columns) as f32 + self.state.random(dimension)) / columns as f32,
+            ((stratum / columns) as f32 + self.state.random(dimension.wrapping_add(1)))
+                / rows as f32,
+        )
+    }
+}
+
+struct HaltonSampler {
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    fn value(&self, dimension: u32) -> f32 {
+        let prime = match PRIMES.get(dimension as usize) {
+            Some(&prime) => prime,
+            None => return self.state.random(dimension),
+        };
+
+        let seed = self.state.pixel_hash(dimension as u64);
+        scrambled_radical_inverse(prime, self.state.index, seed)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.state.start_sample(pixel, index);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.state.take_dimensions(1);
+        self.value(dimension)
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let dimension = self.state.take_dimensions(2);
+        (self.value(dimension), self.value(dimension.wrapping_add(1)))
+    }
+}
+
+/// Only uses the first two dimensions of the Sobol sequence, which are well distributed as pairs,
+/// and shuffles the order of the samples independently for every pair of dimensions asked for.
+struct SobolSampler {
+    state: SampleState,
+}
+
+impl SobolSampler {
+    /// The index of the current sample, shuffled for `dimension`, and the seed for scrambling the
+    /// values.
+    fn shuffled_index(&self, dimension: u32) -> (u32, u64) {
+        let seed = self.state.pixel_hash(dimension as u64);
+        (nested_uniform_scramble(self.state.index, seed as u32), seed)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.state.start_sample(pixel, index);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.state.take_dimensions(1);
+        let (index, seed) = self.shuffled_index(dimension);
+
+        to_float(nested_uniform_scramble(
+            index.reverse_bits(),
+            (seed >> 32) as u32,
+        ))
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let dimension = self.state.take_dimensions(2);
+        let (index, seed) = self.shuffled_index(dimension);
+
+        let x = nested_uniform_scramble(index.reverse_bits(), (seed >> 32) as u32);
+        let y = nested_uniform_scramble(sobol(index), mix_seed(seed, 1) as u32);
+        (to_float(x), to_float(y))
+    }
+}
+
+/// The largest `f32` below 1.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Turns all the bits of `x` into a value in `[0, 1)`.
+fn to_float(x: u32) -> f32 {
+    // An f32 can only hold 24 bits without rounding up to 1.
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Mirrors the digits of `index` in base `base` around the decimal point, shuffling every digit
+/// with a permutation that depends on `seed` and the digits before it (an Owen scramble). Without
+/// that, the large bases would only cover a sliver of `[0, 1)` with a few samples.
+fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u64) -> f32 {
+    // Much quicker with bit tricks in base 2.
+    if base == 2 {
+        return to_float(nested_uniform_scramble(index.reverse_bits(), seed as u32));
+    }
+
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed = 0.0;
+    let mut scale = inverse_base;
+    let mut digits = 0u64;
+
+    // Keep going after the last digit of the index, the zeros beyond it get shuffled as well.
+    while scale > f32::EPSILON as f64 / 4.0 {
+        let digit = index % base;
+        let digit_seed = mix_seed(seed, digits);
+        reversed += permute(digit, base, digit_seed as u32) as f64 * scale;
+
+        digits = digits
+            .wrapping_mul(base as u64)
+            .wrapping_add(digit as u64 + 1);
+        index /= base;
+        scale *= inverse_base;
+    }
+
+    f32::min(reversed as f32, ONE_MINUS_EPSILON)
+}
+
+/// The second dimension of the Sobol sequence, as a fraction of 2^32.
+fn sobol(index: u32) -> u32 {
+    // Without branches, the bits of shuffled indices are too random to predict.
+    SOBOL_DIRECTIONS
+        .iter()
+        .enumerate()
+        .fold(0, |value, (bit, direction)| {
+            value ^ (direction & 0u32.wrapping_sub((index >> bit) & 1))
+        })
+}
+
+/// An Owen scramble of the bits of `x`, from the highest to the lowest: every bit gets flipped
+/// depending on the ones above it.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// A hash in which every bit only depends on the ones below it, from Burley's paper.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+/// Element `i` of a random permutation of `0..length`, from "Correlated Multi-Jittered Sampling"
+/// by Andrew Kensler.
+fn permute(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    // Shuffles within the next power of two, until the result falls inside the permutation.
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+
+        if i < length {
+            break;
+        }
+    }
+
+    // In 64 bits, wrapping around at 2^32 would map some elements onto the same one.
+    ((i as u64 + seed as u64) % length as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    /// Which of `count` equal intervals of `[0, 1)` `value` falls in.
+    fn interval(value: f32, count: u32) -> u32 {
+        (value * count as f32) as u32
+    }
+
+    #[test]
+    fn permute_is_a_permutation() {
+        for length in [1, 2, 3, 5, 7, 10, 12, 13, 16, 17, 100, 1000] {
+            for seed in [0, 1, 0x1234_5678, 0xdead_beef, u32::MAX] {
+                let mut seen = vec![false; length as usize];
+                for i in 0..length {
+                    let j = permute(i, length, seed);
+                    assert!(j < length, "permute({i}, {length}, {seed}) = {j}");
+                    assert!(
+                        !seen[j as usize],
+                        "permute(_, {length}, {seed}) repeats {j}"
+                    );
+                    seen[j as usize] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nested_uniform_scramble_keeps_blocks_together() {
+        // Every bit only depends on the ones above it, so aligned blocks of 2^k values get
+        // shuffled among themselves.
+        for seed in [0, 7, 0xdead_beef] {
+            for block in [0u32, 1, 1000] {
+                let mut seen = [false; 16];
+                let scrambled: Vec<u32> = (0..16)
+                    .map(|i| nested_uniform_scramble(block * 16 + i, seed))
+                    .collect();
+                for &x in &scrambled {
+                    assert_eq!(x >> 4, scrambled[0] >> 4);
+                    assert!(!seen[(x & 15) as usize]);
+                    seen[(x & 15) as usize] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_is_a_net_in_blocks_of_powers_of_two() {
+        for block in [0u32, 3] {
+            let points: Vec<(u32, u32)> = (block * 16..block * 16 + 16)
+                .map(|i| (i.reverse_bits(), sobol(i)))
+                .collect();
+            assert_one_per_elementary_interval(&points, 4);
+        }
+    }
+
+    #[test]
+    fn scrambled_radical_inverse_stratifies_in_its_base() {
+        for base in [2, 3, 5, 7] {
+            for seed in [0, 42, 0xdead_beef] {
+                let mut seen = vec![false; base as usize];
+                for i in 0..base {
+                    let value = scrambled_radical_inverse(base, i, seed);
+                    assert!((0.0..1.0).contains(&value));
+                    let stratum = interval(value, base) as usize;
+                    assert!(!seen[stratum], "base {base}, seed {seed}: {value}");
+                    seen[stratum] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samplers_stay_in_the_unit_interval() {
+        for kind in KINDS {
+            for spp in [1, 5, 16] {
+                let mut sampler = kind.create(12345, spp);
+                for pixel in [(0, 0), (17, 3), (4000, 3000)] {
+                    for index in (0..64).chain([1000, u32::MAX - 1]) {
+                        sampler.start_sample(pixel, index);
+                        for _ in 0..40 {
+                            let value = sampler.next_1d();
+                            assert!((0.0..1.0).contains(&value), "{kind:?}: {value}");
+                            let (u, v) = sampler.next_2d();
+                            assert!((0.0..1.0).contains(&u), "{kind:?}: {u}");
+                            assert!((0.0..1.0).contains(&v), "{kind:?}: {v}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The first `count` samples of a pixel, `pairs` 2D values each.
+    fn samples(sampler: &mut dyn Sampler, count: u32, pairs: usize) -> Vec<Vec<(f32, f32)>> {
+        (0..count)
+            .map(|index| {
+                sampler.start_sample((3, 7), index);
+                (0..pairs).map(|_| sampler.next_2d()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stratified_covers_every_stratum_once() {
+        let mut sampler = SamplerKind::Stratified.create(99, 16);
+        let mut seen = [[false; 16]; 4];
+        for index in 0..16 {
+            sampler.start_sample((3, 7), index);
+            for seen in seen.iter_mut() {
+                let stratum = interval(sampler.next_1d(), 16) as usize;
+                assert!(!seen[stratum]);
+                seen[stratum] = true;
+            }
+        }
+
+        let points = samples(&mut *sampler, 16, 4);
+        for pair in 0..4 {
+            let mut seen = [[false; 4]; 4];
+            for sample in &points {
+                let (u, v) = sample[pair];
+                let cell = &mut seen[interval(v, 4) as usize][interval(u, 4) as usize];
+                assert!(!*cell);
+                *cell = true;
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_handles_non_square_sample_counts() {
+        for spp in [2u32, 3, 5, 8, 12, 15] {
+            let columns = u32::max(f32::sqrt(spp as f32) as u32, 1);
+            let rows = spp.div_ceil(columns);
+            let mut sampler = SamplerKind::Stratified.create(7, spp);
+            let points = samples(&mut *sampler, spp, 3);
+
+            for pair in 0..3 {
+                let mut seen = vec![false; (columns * rows) as usize];
+                for sample in &points {
+                    let (u, v) = sample[pair];
+                    let cell = (interval(v, rows) * columns + interval(u, columns)) as usize;
+                    assert!(!seen[cell], "{spp} spp: two samples in cell {cell}");
+                    seen[cell] = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_covers_every_elementary_interval_once() {
+        let mut sampler = SamplerKind::Sobol.create(2024, 16);
+        let mut seen = [[false; 16]; 4];
+        for index in 0..16 {
+            sampler.start_sample((3, 7), index);
+            for seen in seen.iter_mut() {
+                let stratum = interval(sampler.next_1d(), 16) as usize;
+                assert!(!seen[stratum]);
+                seen[stratum] = true;
+            }
+        }
+
+        let points = samples(&mut *sampler, 16, 4);
+        for pair in 0..4 {
+            let pair: Vec<(u32, u32)> = points
+                .iter()
+                .map(|sample| {
+                    let (u, v) = sample[pair];
+                    (interval(u, 16) << 28, interval(v, 16) << 28)
+                })
+                .collect();
+            assert_one_per_elementary_interval(&pair, 4);
+        }
+    }
+
+    /// Checks that every elementary interval of `2^-bits` in area holds exactly one of the points,
+    /// which are fractions of 2^32.
+    fn assert_one_per_elementary_interval(points: &[(u32, u32)], bits: u32) {
+        assert_eq!(points.len(), 1 << bits);
+        for x_bits in 0..=bits {
+            let y_bits = bits - x_bits;
+            let mut seen = vec![false; 1 << bits];
+            for &(x, y) in points {
+                let x_interval = x.checked_shr(32 - x_bits).unwrap_or(0);
+                let y_interval = y.checked_shr(32 - y_bits).unwrap_or(0);
+                let cell = ((y_interval << x_bits) | x_interval) as usize;
+                assert!(!seen[cell], "{x_bits}x{y_bits} bits: two points in {cell}");
+                seen[cell] = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,1222 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    aarect::{Cuboid, XYRect, XZRect, YZRect},
+    background::Background,
+    bvh::BVHBuilder,
+    constant_medium::ConstantMedium,
+    density::{DensityField, NoiseDensity, VoxelGrid},
+    environment::EnvironmentMap,
+    hittable::Hittable,
+    hittable_list::HittableList,
+    instance::Instance,
+    lights::{AreaLight, DirectionalLight, Light, PointLight, SpotLight},
+    mat4::Mat4,
+    materials::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, MaterialIds, Metal},
+    obj::{self, ObjError},
+    objects::{MovingSphere, Sphere},
+    sampler::SamplerKind,
+    scenes::SceneInfo,
+    sky::{Sky, SunLight},
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture},
+    triangle::{Triangle, TriangleMesh},
+    vec3::{Color, Vec3},
+    volume::HeterogeneousMedium,
+};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Obj {
+        path: PathBuf,
+        line: usize,
+        source: ObjError,
+    },
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => {
+                write!(f, "could not read '{}': {}", path.display(), source)
+            }
+            Self::Obj { path, line, source } => {
+                write!(f, "{}:{}: {}", path.display(), line, source)
+            }
+            Self::Parse { path, source } => {
+                write!(f, "could not parse '{}': {}", path.display(), source)
+            }
+            Self::Invalid {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    camera: CameraDesc,
+    /// Black if not given.
+    background: Option<Spanned<BackgroundDesc>>,
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialDesc>>,
+    /// Objects that only show up in the scene through instances.
+    #[serde(default)]
+    shapes: BTreeMap<String, Spanned<ObjectDesc>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+    /// Lights that aren't objects, emissive objects become lights on their own.
+    #[serde(default)]
+    lights: Vec<Spanned<LightDesc>>,
+}
+
+/// Everything in `SceneInfo` apart from the world and the background, missing fields fall back to
+/// `SceneInfo::default()`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: [f32; 3],
+    lookat: [f32; 3],
+    vfov: Option<f32>,
+    aperture: Option<f32>,
+    focus_dist: Option<f32>,
+    time0: Option<f32>,
+    time1: Option<f32>,
+    image_width: Option<u32>,
+    image_height: Option<u32>,
+    samples_per_pixel: Option<u32>,
+    max_depth: Option<i32>,
+    sampler: Option<SamplerKind>,
+}
+
+/// Either a plain color, or one of the kinds of backgrounds in `BackgroundKind`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackgroundDesc {
+    Color([f32; 3]),
+    Kind(BackgroundKind),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundKind {
+    /// An equirectangular `.hdr` or `.exr` image, which lights the scene as well. `rotation` is in
+    /// degrees around the y axis.
+    Environment {
+        path: String,
+        #[serde(default)]
+        rotation: f32,
+        intensity: Option<f32>,
+    },
+    /// The analytic sky and sun from the Preetham model, see `Sky::new` for the parameters.
+    /// `sun_radius` is in degrees.
+    Sky {
+        sun_elevation: f32,
+        sun_azimuth: f32,
+        turbidity: f32,
+        intensity: Option<f32>,
+        sun_radius: Option<f32>,
+    },
+    /// Blends from `bottom` straight down to `top` straight up, white to light blue by default.
+    Gradient {
+        bottom: Option<[f32; 3]>,
+        top: Option<[f32; 3]>,
+    },
+}
+
+/// Either an inline color or the name of an entry in the `[textures]` table.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color([f32; 3]),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid { color: [f32; 3] },
+    Checker { even: TextureRef, odd: TextureRef },
+    Noise { scale: f32 },
+    Image { path: String },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: TextureRef,
+    },
+    Metal {
+        albedo: [f32; 3],
+        #[serde(default)]
+        fuzz: f32,
+    },
+    Dielectric {
+        ir: f32,
+    },
+    DiffuseLight {
+        emit: TextureRef,
+    },
+    /// Scatters evenly in all directions, for use inside of a `constant_medium` or `volume`.
+    Isotropic {
+        albedo: TextureRef,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+        material: String,
+    },
+    MovingSphere {
+        center0: [f32; 3],
+        center1: [f32; 3],
+        time0: f32,
+        time1: f32,
+        radius: f32,
+        material: String,
+    },
+    XyRect {
+        x0: f32,
+        x1: f32,
+        y0: f32,
+        y1: f32,
+        k: f32,
+        material: String,
+    },
+    XzRect {
+        x0: f32,
+        x1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+        material: String,
+    },
+    YzRect {
+        y0: f32,
+        y1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f32; 3]; 3],
+        normals: Option<[[f32; 3]; 3]>,
+        uvs: Option<[[f32; 2]; 3]>,
+        material: String,
+    },
+    /// An axis-aligned box between two opposite corners.
+    Box {
+        p0: [f32; 3],
+        p1: [f32; 3],
+        material: String,
+    },
+    Mesh {
+        positions: Vec<[f32; 3]>,
+        normals: Option<Vec<[f32; 3]>>,
+        uvs: Option<Vec<[f32; 2]>>,
+        indices: Vec<[usize; 3]>,
+        material: String,
+    },
+    /// A Wavefront OBJ file, using its MTL materials unless `material` is given.
+    Obj {
+        path: String,
+        material: Option<String>,
+    },
+    /// Smoke or fog filling an entry of the `[shapes]` table.
+    ConstantMedium {
+        boundary: String,
+        density: f32,
+        material: String,
+    },
+    /// A medium of varying density filling an entry of the `[shapes]` table. `scattering` and
+    /// `absorption` are per unit of density, `emission` is the glow of the absorbing part.
+    Volume {
+        boundary: String,
+        density: DensityDesc,
+        scattering: f32,
+        #[serde(default)]
+        absorption: f32,
+        #[serde(default)]
+        emission: [f32; 3],
+        material: String,
+    },
+    /// An entry of the `[shapes]` table, placed with `transform`.
+    Instance {
+        shape: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum DensityDesc {
+    /// Perlin turbulence, `density` is the density of the thickest parts.
+    Noise { scale: f32, density: f32 },
+    /// A file of raw little-endian floats, one per voxel with x changing fastest, filling the box
+    /// between `p0` and `p1`.
+    Voxels {
+        path: String,
+        resolution: [usize; 3],
+        p0: [f32; 3],
+        p1: [f32; 3],
+    },
+}
+
+/// Lights that only shine from a point or a direction. `color` is white by default, and gets
+/// multiplied by `intensity`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightDesc {
+    Point {
+        position: [f32; 3],
+        color: Option<[f32; 3]>,
+        intensity: f32,
+    },
+    /// Shines along `direction` in a cone of `cone_angle` degrees around it, fading out over the
+    /// outer `cone_delta` degrees.
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        color: Option<[f32; 3]>,
+        intensity: f32,
+        cone_angle: Option<f32>,
+        cone_delta: Option<f32>,
+    },
+    /// Parallel light travelling along `direction`.
+    Directional {
+        direction: [f32; 3],
+        color: Option<[f32; 3]>,
+        intensity: f32,
+    },
+}
+
+/// One step of an instance's transform, the steps get applied in order.
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum TransformDesc {
+    Translate {
+        translate: [f32; 3],
+    },
+    /// Counter-clockwise rotation around the `rotate` axis.
+    Rotate {
+        rotate: [f32; 3],
+        degrees: f32,
+    },
+    Scale {
+        scale: [f32; 3],
+    },
+    /// A row-major affine matrix.
+    Matrix {
+        matrix: [[f32; 4]; 4],
+    },
+}
+
+/// Loads a TOML scene description, see `scenes/` for examples.
+pub fn load<P: AsRef<Path>>(path: P, bvh: &mut BVHBuilder) -> Result<SceneInfo, SceneFileError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|source| SceneFileError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    parse(path, &source, bvh)
+}
+
+/// Builds the scene described by `source`, the contents of the scene file at `path`.
+fn parse(path: &Path, source: &str, bvh: &mut BVHBuilder) -> Result<SceneInfo, SceneFileError> {
+    let desc: SceneDesc = toml::from_str(source).map_err(|source| SceneFileError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let mut builder = SceneBuilder {
+        path,
+        source,
+        desc: &desc,
+        textures: HashMap::new(),
+        textures_in_progress: HashSet::new(),
+        materials: HashMap::new(),
+        material_ids: MaterialIds::new(),
+        bvh,
+        shapes: HashMap::new(),
+        shapes_in_progress: HashSet::new(),
+    };
+
+    let mut objects = HittableList::new();
+    let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+    for object in desc.objects.iter() {
+        let object = builder.build_object(object)?;
+        objects.add(object.hittable);
+        for emitter in object.emitters {
+            lights.push(Arc::new(AreaLight::new(emitter)));
+        }
+    }
+
+    for light in desc.lights.iter() {
+        lights.push(builder.light(light)?);
+    }
+
+    let background = match &desc.background {
+        Some(background) => builder.background(background)?,
+        None => Background::Color(Color::from(0.0)),
+    };
+    match &background {
+        Background::Environment(map) => lights.push(map.clone()),
+        Background::Sky(sky) => lights.push(Arc::new(SunLight::new(sky.clone()))),
+        Background::Color(_) | Background::Gradient { .. } => {}
+    }
+
+    let defaults = SceneInfo::default();
+    let camera = &desc.camera;
+    let time0 = camera.time0.unwrap_or(defaults.time0);
+    let time1 = camera.time1.unwrap_or(defaults.time1);
+
+    // A single object doesn't need an acceleration structure around it.
+    let objects = objects.with_object_ids();
+    let world = if objects.objects.len() > 1 {
+        HittableList::from(Arc::new(builder.bvh.build_list(&objects, time0, time1)))
+    } else {
+        objects
+    };
+
+    Ok(SceneInfo {
+        world,
+        lights,
+        background,
+        lookfrom: to_vec3(camera.lookfrom),
+        lookat: to_vec3(camera.lookat),
+        vfov: camera.vfov.unwrap_or(defaults.vfov),
+        aperture: camera.aperture.unwrap_or(defaults.aperture),
+        focus_dist: camera.focus_dist.unwrap_or(defaults.focus_dist),
+        time0,
+        time1,
+        image_width: camera.image_width.unwrap_or(defaults.image_width),
+        image_height: camera.image_height.unwrap_or(defaults.image_height),
+        samples_per_pixel: camera
+            .samples_per_pixel
+            .unwrap_or(defaults.samples_per_pixel),
+        max_depth: camera.max_depth.unwrap_or(defaults.max_depth),
+        sampler: camera.sampler.unwrap_or(defaults.sampler),
+    })
+}
+
+fn to_vec3(v: [f32; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn to_uv(uv: [f32; 2]) -> (f32, f32) {
+    (uv[0], uv[1])
+}
+
+/// Turns the deserialized descriptions into the actual scene objects, resolving texture and
+/// material names along the way.
+struct SceneBuilder<'a> {
+    path: &'a Path,
+    source: &'a str,
+    desc: &'a SceneDesc,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    textures_in_progress: HashSet<String>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    /// Numbers the materials in the order they first get used in.
+    material_ids: MaterialIds,
+    bvh: &'a mut BVHBuilder,
+    shapes: HashMap<String, SceneObject>,
+    shapes_in_progress: HashSet<String>,
+}
+
+/// A built object, along with the parts of it that emit light. Those get added to the scene's
+/// lights automatically.
+#[derive(Clone)]
+struct SceneObject {
+    hittable: Arc<dyn Hittable>,
+    emitters: Vec<Arc<dyn Hittable>>,
+}
+
+impl<'a> SceneBuilder<'a> {
+    fn line(&self, span: Range<usize>) -> usize {
+        self.source[..span.start].matches('\n').count() + 1
+    }
+
+    fn error(&self, span: Range<usize>, message: String) -> SceneFileError {
+        SceneFileError::Invalid {
+            path: self.path.to_path_buf(),
+            line: self.line(span),
+            message,
+        }
+    }
+
+    fn background(
+        &self,
+        background: &Spanned<BackgroundDesc>,
+    ) -> Result<Background, SceneFileError> {
+        let kind = match background.get_ref() {
+            BackgroundDesc::Color(color) => return Ok(Background::Color(to_vec3(*color))),
+            BackgroundDesc::Kind(kind) => kind,
+        };
+
+        match kind {
+            BackgroundKind::Environment {
+                path,
+                rotation,
+                intensity,
+            } => {
+                let image_path = self.resolve_path(path);
+                let map = EnvironmentMap::open(&image_path).map_err(|err| {
+                    self.error(
+                        background.span(),
+                        format!("could not load image '{}': {}", image_path.display(), err),
+                    )
+                })?;
+
+                Ok(Background::Environment(Arc::new(
+                    map.with_rotation(*rotation)
+                        .with_intensity(intensity.unwrap_or(1.0)),
+                )))
+            }
+            BackgroundKind::Sky {
+                sun_elevation,
+                sun_azimuth,
+                turbidity,
+                intensity,
+                sun_radius,
+            } => {
+                if !(0.0..=90.0).contains(sun_elevation) {
+                    return Err(self.error(
+                        background.span(),
+                        "sun_elevation needs to be between 0 and 90 degrees".to_string(),
+                    ));
+                }
+                if !(1.7..=10.0).contains(turbidity) {
+                    return Err(self.error(
+                        background.span(),
+                        "turbidity needs to be between 1.7 and 10".to_string(),
+                    ));
+                }
+
+                let mut sky = Sky::new(*sun_elevation, *sun_azimuth, *turbidity)
+                    .with_intensity(intensity.unwrap_or(1.0));
+                if let Some(sun_radius) = sun_radius {
+                    if *sun_radius <= 0.0 {
+                        return Err(self.error(
+                            background.span(),
+                            "sun_radius needs to be positive".to_string(),
+                        ));
+                    }
+                    sky = sky.with_sun_radius(*sun_radius);
+                }
+
+                Ok(Background::Sky(Arc::new(sky)))
+            }
+            BackgroundKind::Gradient { bottom, top } => Ok(Background::Gradient {
+                bottom: bottom.map_or(Color::from(1.0), to_vec3),
+                top: top.map_or(Color::new(0.5, 0.7, 1.0), to_vec3),
+            }),
+        }
+    }
+
+    fn light(&self, light: &Spanned<LightDesc>) -> Result<Arc<dyn Light>, SceneFileError> {
+        let span = light.span();
+        let light_color = |color: &Option<[f32; 3]>, intensity: f32| {
+            intensity * color.map_or(Color::from(1.0), to_vec3)
+        };
+        let light_direction = |direction: &[f32; 3]| {
+            let direction = to_vec3(*direction);
+            if direction.near_zero() {
+                return Err(self.error(span.clone(), "light direction can't be zero".to_string()));
+            }
+            Ok(direction)
+        };
+
+        Ok(match light.get_ref() {
+            LightDesc::Point {
+                position,
+                color,
+                intensity,
+            } => Arc::new(PointLight::new(
+                to_vec3(*position),
+                light_color(color, *intensity),
+            )),
+            LightDesc::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                cone_angle,
+                cone_delta,
+            } => {
+                let cone_angle = cone_angle.unwrap_or(30.0);
+                let cone_delta = cone_delta.unwrap_or(5.0);
+                if !(0.0..=cone_angle).contains(&cone_delta) || cone_angle > 180.0 {
+                    return Err(self.error(
+                        span,
+                        "spot lights need 0 <= cone_delta <= cone_angle <= 180".to_string(),
+                    ));
+                }
+
+                Arc::new(SpotLight::new(
+                    to_vec3(*position),
+                    light_direction(direction)?,
+                    light_color(color, *intensity),
+                    cone_angle,
+                    cone_delta,
+                ))
+            }
+            LightDesc::Directional {
+                direction,
+                color,
+                intensity,
+            } => Arc::new(DirectionalLight::new(
+                light_direction(direction)?,
+                light_color(color, *intensity),
+            )),
+        })
+    }
+
+    /// Paths in the scene file are relative to the scene file itself.
+    fn resolve_path(&self, path: &str) -> PathBuf {
+        self.path.parent().unwrap_or(Path::new("")).join(path)
+    }
+
+    /// Resolves a texture reference, `span` is the location of the entry that refers to it.
+    fn texture_ref(
+        &mut self,
+        texture: &TextureRef,
+        span: Range<usize>,
+    ) -> Result<Arc<dyn Texture>, SceneFileError> {
+        match texture {
+            TextureRef::Color(color) => Ok(Arc::new(SolidColor::new(to_vec3(*color)))),
+            TextureRef::Name(name) => self.texture(name, span),
+        }
+    }
+
+    fn texture(
+        &mut self,
+        name: &str,
+        span: Range<usize>,
+    ) -> Result<Arc<dyn Texture>, SceneFileError> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+
+        let desc = self.desc;
+        let entry = desc
+            .textures
+            .get(name)
+            .ok_or_else(|| self.error(span.clone(), format!("unknown texture '{}'", name)))?;
+
+        // Checker textures can refer to other textures, so watch out for cycles.
+        if !self.textures_in_progress.insert(name.to_string()) {
+            return Err(self.error(
+                span,
+                format!("texture '{}' (indirectly) refers to itself", name),
+            ));
+        }
+
+        let texture: Arc<dyn Texture> = match entry.get_ref() {
+            TextureDesc::Solid { color } => Arc::new(SolidColor::new(to_vec3(*color))),
+            TextureDesc::Checker { even, odd } => Arc::new(CheckerTexture::from_textures(
+                self.texture_ref(even, entry.span())?,
+                self.texture_ref(odd, entry.span())?,
+            )),
+            TextureDesc::Noise { scale } => Arc::new(NoiseTexture::new(*scale)),
+            TextureDesc::Image { path } => {
+                let image_path = self.resolve_path(path);
+                let texture = ImageTexture::open(&image_path).map_err(|err| {
+                    self.error(
+                        entry.span(),
+                        format!("could not load image '{}': {}", image_path.display(), err),
+                    )
+                })?;
+
+                Arc::new(texture)
+            }
+        };
+
+        self.textures_in_progress.remove(name);
+        self.textures.insert(name.to_string(), texture.clone());
+
+        Ok(texture)
+    }
+
+    fn material(
+        &mut self,
+        name: &str,
+        span: Range<usize>,
+    ) -> Result<Arc<dyn Material>, SceneFileError> {
+        if let Some(material) = self.materials.get(name) {
+            return Ok(material.clone());
+        }
+
+        let desc = self.desc;
+        let entry = desc
+            .materials
+            .get(name)
+            .ok_or_else(|| self.error(span, format!("unknown material '{}'", name)))?;
+
+        let material: Arc<dyn Material> = match entry.get_ref() {
+            MaterialDesc::Lambertian { albedo } => Arc::new(Lambertian::from_texture(
+                self.texture_ref(albedo, entry.span())?,
+            )),
+            MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal::new(to_vec3(*albedo), *fuzz)),
+            MaterialDesc::Dielectric { ir } => Arc::new(Dielectric::new(*ir)),
+            MaterialDesc::DiffuseLight { emit } => {
+                Arc::new(DiffuseLight::new(self.texture_ref(emit, entry.span())?))
+            }
+            MaterialDesc::Isotropic { albedo } => {
+                Arc::new(Isotropic::new(self.texture_ref(albedo, entry.span())?))
+            }
+        };
+        let material: Arc<dyn Material> = Arc::new(self.material_ids.assign(material));
+
+        self.materials.insert(name.to_string(), material.clone());
+
+        Ok(material)
+    }
+
+    fn build_object(
+        &mut self,
+        object: &Spanned<ObjectDesc>,
+    ) -> Result<SceneObject, SceneFileError> {
+        let span = object.span();
+
+        let hittable: Arc<dyn Hittable> = match object.get_ref() {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => Arc::new(Sphere::new(
+                to_vec3(*center),
+                *radius,
+                self.material(material, span)?,
+            )),
+            ObjectDesc::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => Arc::new(MovingSphere::new(
+                to_vec3(*center0),
+                to_vec3(*center1),
+                *time0,
+                *time1,
+                *radius,
+                self.material(material, span)?,
+            )),
+            ObjectDesc::XyRect {
+                x0,
+                x1,
+                y0,
+                y1,
+                k,
+                material,
+            } => Arc::new(XYRect::new(
+                *x0,
+                *x1,
+                *y0,
+                *y1,
+                *k,
+                self.material(material, span)?,
+            )),
+            ObjectDesc::XzRect {
+                x0,
+                x1,
+                z0,
+                z1,
+                k,
+                material,
+            } => Arc::new(XZRect::new(
+                *x0,
+                *x1,
+                *z0,
+                *z1,
+                *k,
+                self.material(material, span)?,
+            )),
+            ObjectDesc::YzRect {
+                y0,
+                y1,
+                z0,
+                z1,
+                k,
+                material,
+            } => Arc::new(YZRect::new(
+                *y0,
+                *y1,
+                *z0,
+                *z1,
+                *k,
+                self.material(material, span)?,
+            )),
+            ObjectDesc::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            } => {
+                let mut triangle = Triangle::new(
+                    to_vec3(vertices[0]),
+                    to_vec3(vertices[1]),
+                    to_vec3(vertices[2]),
+                    self.material(material, span)?,
+                );
+                if let Some(normals) = normals {
+                    triangle = triangle.with_normals(normals.map(to_vec3));
+                }
+                if let Some(uvs) = uvs {
+                    triangle = triangle.with_uvs(uvs.map(to_uv));
+                }
+
+                Arc::new(triangle)
+            }
+            ObjectDesc::Box { p0, p1, material } => Arc::new(Cuboid::new(
+                to_vec3(*p0),
+                to_vec3(*p1),
+                self.material(material, span)?,
+            )),
+            ObjectDesc::Mesh {
+                positions,
+                normals,
+                uvs,
+                indices,
+                material,
+            } => {
+                if indices.is_empty() {
+                    return Err(self.error(span, "mesh has no triangles".to_string()));
+                }
+                if let Some(&index) = indices.iter().flatten().find(|&&i| i >= positions.len()) {
+                    return Err(self.error(
+                        span,
+                        format!(
+                            "mesh index {} out of range, there are only {} positions",
+                            index,
+                            positions.len()
+                        ),
+                    ));
+                }
+                if normals.as_ref().is_some_and(|n| n.len() != positions.len()) {
+                    return Err(self.error(span, "mesh needs one normal per position".to_string()));
+                }
+                if uvs.as_ref().is_some_and(|uv| uv.len() != positions.len()) {
+                    return Err(self.error(span, "mesh needs one uv per position".to_string()));
+                }
+
+                Arc::new(TriangleMesh::new(
+                    positions.iter().copied().map(to_vec3).collect(),
+                    normals
+                        .as_ref()
+                        .map(|normals| normals.iter().copied().map(to_vec3).collect()),
+                    uvs.as_ref()
+                        .map(|uvs| uvs.iter().copied().map(to_uv).collect()),
+                    indices.clone(),
+                    self.material(material, span)?,
+                    self.bvh,
+                ))
+            }
+            ObjectDesc::Obj { path, material } => {
+                let material = match material {
+                    Some(material) => Some(self.material(material, span.clone())?),
+                    None => None,
+                };
+
+                let model = obj::load(
+                    self.resolve_path(path),
+                    material,
+                    &mut self.material_ids,
+                    self.bvh,
+                )
+                .map_err(|source| SceneFileError::Obj {
+                    path: self.path.to_path_buf(),
+                    line: self.line(span.clone()),
+                    source,
+                })?;
+
+                return Ok(SceneObject {
+                    hittable: Arc::new(model.objects),
+                    emitters: model.emitters,
+                });
+            }
+            ObjectDesc::ConstantMedium {
+                boundary,
+                density,
+                material,
+            } => {
+                if *density <= 0.0 {
+                    return Err(self.error(span, "density needs to be positive".to_string()));
+                }
+
+                Arc::new(ConstantMedium::new(
+                    self.shape(boundary, span.clone())?.hittable,
+                    *density,
+                    self.material(material, span)?,
+                ))
+            }
+            ObjectDesc::Volume {
+                boundary,
+                density,
+                scattering,
+                absorption,
+                emission,
+                material,
+            } => {
+                if *scattering < 0.0 || *absorption < 0.0 {
+                    return Err(self.error(
+                        span,
+                        "scattering and absorption can't be negative".to_string(),
+                    ));
+                }
+
+                let field: Arc<dyn DensityField> = match density {
+                    DensityDesc::Noise { scale, density } => {
+                        Arc::new(NoiseDensity::new(*scale, *density))
+                    }
+                    DensityDesc::Voxels {
+                        path,
+                        resolution,
+                        p0,
+                        p1,
+                    } => {
+                        let voxel_path = self.resolve_path(path);
+                        let grid =
+                            VoxelGrid::load(&voxel_path, *resolution, to_vec3(*p0), to_vec3(*p1))
+                                .map_err(|err| {
+                                self.error(
+                                    span.clone(),
+                                    format!(
+                                        "could not load voxels '{}': {}",
+                                        voxel_path.display(),
+                                        err
+                                    ),
+                                )
+                            })?;
+
+                        Arc::new(grid)
+                    }
+                };
+
+                Arc::new(
+                    HeterogeneousMedium::new(
+                        self.shape(boundary, span.clone())?.hittable,
+                        field,
+                        *scattering,
+                        self.material(material, span)?,
+                    )
+                    .with_absorption(*absorption)
+                    .with_emission(to_vec3(*emission)),
+                )
+            }
+            ObjectDesc::Instance { shape, transform } => {
+                let shape = self.shape(shape, span.clone())?;
+                let transform = self.transform(transform, span)?;
+                let instance = |object: Arc<dyn Hittable>| -> Arc<dyn Hittable> {
+                    Arc::new(Instance::new(object, transform))
+                };
+
+                return Ok(SceneObject {
+                    hittable: instance(shape.hittable),
+                    emitters: shape.emitters.into_iter().map(instance).collect(),
+                });
+            }
+        };
+
+        let span = object.span();
+        let material = match object.get_ref() {
+            ObjectDesc::Sphere { material, .. }
+            | ObjectDesc::XyRect { material, .. }
+            | ObjectDesc::XzRect { material, .. }
+            | ObjectDesc::YzRect { material, .. }
+            | ObjectDesc::Triangle { material, .. }
+            | ObjectDesc::Mesh { material, .. } => Some(material),
+            ObjectDesc::MovingSphere { material, .. } => {
+                if self.material(material, span.clone())?.is_emissive() {
+                    return Err(self.error(span, "moving spheres can't be lights".to_string()));
+                }
+                None
+            }
+            ObjectDesc::Box { material, .. } => {
+                if self.material(material, span.clone())?.is_emissive() {
+                    return Err(self.error(span, "boxes can't be lights".to_string()));
+                }
+                None
+            }
+            ObjectDesc::ConstantMedium { material, .. } | ObjectDesc::Volume { material, .. } => {
+                if self.material(material, span.clone())?.is_emissive() {
+                    return Err(self.error(
+                        span,
+                        "the material of a medium can't be a light".to_string(),
+                    ));
+                }
+                None
+            }
+            // These bring their own emitters along, see above.
+            ObjectDesc::Obj { .. } | ObjectDesc::Instance { .. } => None,
+        };
+
+        let mut emitters = Vec::new();
+        if let Some(material) = material {
+            if self.material(material, span)?.is_emissive() {
+                emitters.push(hittable.clone());
+            }
+        }
+
+        Ok(SceneObject { hittable, emitters })
+    }
+
+    /// Builds an entry of the `[shapes]` table, `span` is the location of the instance using it.
+    fn shape(&mut self, name: &str, span: Range<usize>) -> Result<SceneObject, SceneFileError> {
+        if let Some(shape) = self.shapes.get(name) {
+            return Ok(shape.clone());
+        }
+
+        let desc = self.desc;
+        let entry = desc
+            .shapes
+            .get(name)
+            .ok_or_else(|| self.error(span.clone(), format!("unknown shape '{}'", name)))?;
+
+        // Shapes can be instances of other shapes, so watch out for cycles.
+        if !self.shapes_in_progress.insert(name.to_string()) {
+            return Err(self.error(
+                span,
+                format!("shape '{}' (indirectly) refers to itself", name),
+            ));
+        }
+
+        let shape = self.build_object(entry)?;
+
+        self.shapes_in_progress.remove(name);
+        self.shapes.insert(name.to_string(), shape.clone());
+
+        Ok(shape)
+    }
+
+    /// Combines the steps of an instance's transform into a single matrix.
+    fn transform(
+        &self,
+        steps: &[TransformDesc],
+        span: Range<usize>,
+    ) -> Result<Mat4, SceneFileError> {
+        let mut transform = Mat4::identity();
+
+        for step in steps {
+            let matrix = match step {
+                TransformDesc::Translate { translate } => Mat4::translation(to_vec3(*translate)),
+                TransformDesc::Rotate { rotate, degrees } => {
+                    if to_vec3(*rotate).near_zero() {
+                        return Err(self.error(span, "rotation axis can't be zero".to_string()));
+                    }
+                    Mat4::rotation(to_vec3(*rotate), *degrees)
+                }
+                TransformDesc::Scale { scale } => Mat4::scale(to_vec3(*scale)),
+                TransformDesc::Matrix { matrix } => Mat4::from_rows(*matrix).ok_or_else(|| {
+                    self.error(
+                        span.clone(),
+                        "the bottom row of a matrix needs to be [0, 0, 0, 1]".to_string(),
+                    )
+                })?,
+            };
+
+            transform = matrix * transform;
+        }
+
+        if transform.inverse().is_none() {
+            return Err(self.error(span, "transform can't be inverted".to_string()));
+        }
+
+        Ok(transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    fn parse_str(source: &str) -> Result<SceneInfo, SceneFileError> {
+        parse(
+            Path::new("scenes/test.toml"),
+            source,
+            &mut BVHBuilder::default(),
+        )
+    }
+
+    /// The line an `Invalid` error points at.
+    fn error_line(result: Result<SceneInfo, SceneFileError>) -> usize {
+        match result {
+            Err(SceneFileError::Invalid { line, .. }) => line,
+            Err(err) => panic!("expected an invalid scene error, got '{}'", err),
+            Ok(_) => panic!("expected an invalid scene error, the scene loaded"),
+        }
+    }
+
+    #[test]
+    fn loads_camera_and_objects() {
+        let scene = parse_str(
+            r#"
+            background = [0.5, 0.6, 0.7]
+
+            [camera]
+            lookfrom = [0.0, 1.0, 5.0]
+            lookat = [0.0, 1.0, 0.0]
+            vfov = 30.0
+            samples_per_pixel = 8
+
+            [materials.ground]
+            type = "lambertian"
+            albedo = [0.5, 0.5, 0.5]
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, -1000.0, 0.0]
+            radius = 1000.0
+            material = "ground"
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 1.0, 0.0]
+            radius = 1.0
+            material = "ground"
+            "#,
+        )
+        .unwrap();
+
+        let defaults = SceneInfo::default();
+        let background = scene.background.value(&Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!([background.x, background.y, background.z], [0.5, 0.6, 0.7]);
+
+        assert_eq!(scene.lookfrom.z, 5.0);
+        assert_eq!(scene.vfov, 30.0);
+        assert_eq!(scene.samples_per_pixel, 8);
+        assert_eq!(scene.image_width, defaults.image_width);
+        assert_eq!(scene.max_depth, defaults.max_depth);
+
+        // Both spheres end up in a single BVH.
+        assert_eq!(scene.world.objects.len(), 1);
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn unknown_names_point_at_their_line() {
+        let line = error_line(parse_str(
+            r#"
+[camera]
+lookfrom = [0.0, 0.0, 1.0]
+lookat = [0.0, 0.0, 0.0]
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "missing"
+"#,
+        ));
+        assert_eq!(line, 6);
+
+        let line = error_line(parse_str(
+            r#"
+[camera]
+lookfrom = [0.0, 0.0, 1.0]
+lookat = [0.0, 0.0, 0.0]
+
+[materials.checkered]
+type = "lambertian"
+albedo = "checker"
+
+[textures.checker]
+type = "checker"
+even = [1.0, 1.0, 1.0]
+odd = "missing"
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "checkered"
+"#,
+        ));
+        assert_eq!(line, 10);
+    }
+
+    #[test]
+    fn texture_cycles_are_an_error() {
+        let result = parse_str(
+            r#"
+[camera]
+lookfrom = [0.0, 0.0, 1.0]
+lookat = [0.0, 0.0, 0.0]
+
+[textures.a]
+type = "checker"
+even = "b"
+odd = [0.0, 0.0, 0.0]
+
+[textures.b]
+type = "checker"
+even = "a"
+odd = [0.0, 0.0, 0.0]
+
+[materials.cyclic]
+type = "lambertian"
+albedo = "a"
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "cyclic"
+"#,
+        );
+        assert!(error_line(result) > 0);
+    }
+
+    #[test]
+    fn unknown_fields_are_a_parse_error() {
+        let result = parse_str(
+            r#"
+[camera]
+lookfrom = [0.0, 0.0, 1.0]
+lookat = [0.0, 0.0, 0.0]
+fov = 40.0
+"#,
+        );
+        assert!(matches!(result, Err(SceneFileError::Parse { .. })));
+    }
+}
@@ -1,27 +1,108 @@
-use rand::Rng;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
-    aarect::{XYRect, XZRect, YZRect},
-    bvh,
+    aarect::{Cuboid, XYRect, XZRect, YZRect},
+    background::Background,
+    bvh::BVHBuilder,
+    constant_medium::ConstantMedium,
+    density::NoiseDensity,
+    hittable::Hittable,
     hittable_list::HittableList,
+    instance::Instance,
+    lights::{AreaLight, DirectionalLight, Light, PointLight, SpotLight},
+    mat4::Mat4,
     materials::DiffuseLight,
-    materials::{Dielectric, Lambertian, Metal},
+    materials::{Dielectric, Isotropic, Lambertian, MaterialIds, Metal},
     objects::{MovingSphere, Sphere},
+    perlin::Perlin,
+    sampler::SamplerKind,
+    sky::{Sky, SunLight},
     texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor},
-    utils::random,
+    triangle::TriangleMesh,
+    utils::{random, random_range},
     vec3::{Color, Point3, Vec3},
+    volume::HeterogeneousMedium,
 };
 
-pub fn random_scene() -> HittableList {
+pub struct SceneInfo {
+    pub world: HittableList,
+    /// The lights that get sampled directly at diffuse hits. Emissive objects should be in here as
+    /// well as in `world`, otherwise they only light the scene through scattered rays.
+    pub lights: Vec<Arc<dyn Light>>,
+    pub background: Background,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vfov: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+    pub time0: f32,
+    pub time1: f32,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    pub sampler: SamplerKind,
+}
+
+impl Default for SceneInfo {
+    fn default() -> Self {
+        Self {
+            world: HittableList::new(),
+            lights: Vec::new(),
+            background: Background::Color(Color::from(0.0)),
+            lookfrom: Point3::new(10.0, 2.0, 3.0),
+            lookat: Point3::from(0.0),
+            vfov: 20.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            time0: 0.0,
+            time1: 1.0,
+            image_width: 400,
+            image_height: 225,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            sampler: SamplerKind::Sobol,
+        }
+    }
+}
+
+/// Builds a scene, putting whatever needs a BVH in one built with the `BVHBuilder`.
+type SceneBuilder = fn(&mut BVHBuilder) -> SceneInfo;
+
+/// All the built-in scenes, by the name they can be selected with on the command line.
+pub const SCENES: [(&str, SceneBuilder); 12] = [
+    ("random_scene", random_scene),
+    ("two_spheres", |_| two_spheres()),
+    ("two_perlin_spheres", |_| two_perlin_spheres()),
+    ("earth", |_| earth()),
+    ("simple_light", |_| simple_light()),
+    ("cornell_box", |_| cornell_box()),
+    ("cornell_smoke", |_| cornell_smoke()),
+    ("cornell_clouds", |_| cornell_clouds()),
+    ("final_scene", final_scene),
+    ("sunny_spheres", |_| sunny_spheres()),
+    ("delta_lights", |_| delta_lights()),
+    ("terrain", terrain),
+];
+
+pub fn from_name(name: &str, bvh: &mut BVHBuilder) -> Option<SceneInfo> {
+    SCENES
+        .iter()
+        .find(|(scene_name, _)| *scene_name == name)
+        .map(|(_, build)| build(bvh))
+}
+
+pub fn random_scene(bvh: &mut BVHBuilder) -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
     let mut world = HittableList::new();
 
     let checker = CheckerTexture::new(
         SolidColor::new(Color::new(0.2, 0.3, 0.1)),
         SolidColor::new(Color::from(0.9)),
     );
-    let ground_material = Lambertian::from_texture(Rc::new(checker));
-    world.add(Rc::new(Sphere::new(
+    let ground_material = ids.assign(Lambertian::from_texture(Arc::new(checker)));
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
         ground_material.clone(),
@@ -40,142 +121,178 @@ pub fn random_scene() -> HittableList {
                 if choose_mat < 0.8 {
                     // Diffuse
                     let albedo = Color::random() * Color::random();
-                    let material = Lambertian::new(albedo);
-                    let center2 =
-                        center + Vec3::new(0.0, rand::thread_rng().gen_range(0.0..0.5), 0.0);
-                    world.add(Rc::new(MovingSphere::new(
+                    let material = ids.assign(Lambertian::new(albedo));
+                    let center2 = center + Vec3::new(0.0, random_range(0.0, 0.5), 0.0);
+                    world.add(Arc::new(MovingSphere::new(
                         center, center2, 0.0, 1.0, 0.2, material,
                     )));
                 } else if choose_mat < 0.95 {
                     // Metal
                     let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz = rand::thread_rng().gen_range(0.0..0.5);
-                    world.add(Rc::new(Sphere::new(center, 0.2, Metal::new(albedo, fuzz))));
+                    let fuzz = random_range(0.0, 0.5);
+                    world.add(Arc::new(Sphere::new(
+                        center,
+                        0.2,
+                        ids.assign(Metal::new(albedo, fuzz)),
+                    )));
                 } else {
                     // Glass
-                    world.add(Rc::new(Sphere::new(center, 0.2, Dielectric::new(1.5))));
+                    world.add(Arc::new(Sphere::new(
+                        center,
+                        0.2,
+                        ids.assign(Dielectric::new(1.5)),
+                    )));
                 }
             }
         }
     }
 
-    world.add(Rc::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 1.0, 0.0),
         1.0,
-        Dielectric::new(1.5),
+        ids.assign(Dielectric::new(1.5)),
     )));
 
-    world.add(Rc::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
-        Lambertian::new(Color::new(0.4, 0.2, 0.1)),
+        ids.assign(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
     )));
 
-    world.add(Rc::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         Point3::new(4.0, 1.0, 0.0),
         1.0,
-        Metal::new(Color::new(0.7, 0.6, 0.5), 0.0),
+        ids.assign(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
     )));
 
-    // world
-    HittableList::from(Rc::new(bvh::BVHNode::from(&world, 0.0, 1.0)))
+    SceneInfo {
+        world: HittableList::from(Arc::new(bvh.build_list(&world.with_object_ids(), 0.0, 1.0))),
+        background: Background::Color(Color::new(0.7, 0.8, 1.0)),
+        lookfrom: Point3::new(13.0, 2.0, 3.0),
+        lookat: Point3::from(0.0),
+        aperture: 0.1,
+        ..Default::default()
+    }
 }
 
-pub fn two_spheres() -> HittableList {
+pub fn two_spheres() -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
     let mut world = HittableList::new();
 
-    let checker = Rc::new(CheckerTexture::new(
+    let checker = Arc::new(CheckerTexture::new(
         SolidColor::new(Color::new(0.2, 0.3, 0.1)),
         SolidColor::new(Color::from(0.9)),
     ));
 
-    world.add(Rc::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, -10.0, 0.0),
         10.0,
-        Lambertian::from_texture(checker.clone()),
+        ids.assign(Lambertian::from_texture(checker.clone())),
     )));
 
-    world.add(Rc::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 10.0, 0.0),
         10.0,
-        Lambertian::from_texture(checker.clone()),
+        ids.assign(Lambertian::from_texture(checker.clone())),
     )));
 
-    world
+    SceneInfo {
+        world: world.with_object_ids(),
+        background: Background::Color(Color::new(0.7, 0.8, 1.0)),
+        lookfrom: Point3::new(13.0, 2.0, 3.0),
+        lookat: Point3::from(0.0),
+        ..Default::default()
+    }
 }
 
-pub fn two_perlin_spheres() -> HittableList {
+pub fn two_perlin_spheres() -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
     let mut world = HittableList::new();
 
-    let perlin_texture = Rc::new(NoiseTexture::new(4.0));
+    let perlin_texture = Arc::new(NoiseTexture::new(4.0));
 
-    world.add(Rc::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Lambertian::from_texture(perlin_texture.clone()),
+        ids.assign(Lambertian::from_texture(perlin_texture.clone())),
     )));
-    world.add(Rc::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 2.0, 0.0),
         2.0,
-        Lambertian::from_texture(perlin_texture.clone()),
+        ids.assign(Lambertian::from_texture(perlin_texture.clone())),
     )));
 
-    world
+    SceneInfo {
+        world: world.with_object_ids(),
+        background: Background::Color(Color::new(0.7, 0.8, 1.0)),
+        lookfrom: Point3::new(13.0, 2.0, 3.0),
+        lookat: Point3::from(0.0),
+        ..Default::default()
+    }
 }
 
-pub fn earth() -> HittableList {
-    let earth_texture = Rc::new(ImageTexture::new("res/earthmap.jpg"));
-    let globe = Rc::new(Sphere::new(
+pub fn earth() -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
+    let earth_texture = Arc::new(ImageTexture::new("res/earthmap.jpg"));
+    let globe = Arc::new(Sphere::new(
         Point3::from(0.0),
         2.0,
-        Lambertian::from_texture(earth_texture),
+        ids.assign(Lambertian::from_texture(earth_texture)),
     ));
 
-    HittableList::from(globe)
+    SceneInfo {
+        world: HittableList::from(globe).with_object_ids(),
+        background: Background::Color(Color::new(0.7, 0.8, 1.0)),
+        lookfrom: Point3::new(13.0, 2.0, 1.0),
+        lookat: Point3::from(0.0),
+        ..Default::default()
+    }
 }
 
-pub fn simple_light() -> HittableList {
+pub fn simple_light() -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
     let mut world = HittableList::new();
 
-    let pertext = Rc::new(NoiseTexture::new(4.0));
-    world.add(Rc::new(Sphere::new(
+    let pertext = Arc::new(NoiseTexture::new(4.0));
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Lambertian::from_texture(pertext.clone()),
+        ids.assign(Lambertian::from_texture(pertext.clone())),
     )));
-    world.add(Rc::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 2.0, 0.0),
         2.0,
-        Lambertian::from_texture(pertext.clone()),
+        ids.assign(Lambertian::from_texture(pertext.clone())),
     )));
 
-    let difflight = DiffuseLight::from_color(Color::new(4.0, 4.0, 4.0));
-    world.add(Rc::new(XYRect::new(3.0, 5.0, 1.0, 3.0, -2.0, difflight)));
+    let difflight = ids.assign(DiffuseLight::from_color(Color::new(4.0, 4.0, 4.0)));
+    let light = Arc::new(XYRect::new(3.0, 5.0, 1.0, 3.0, -2.0, difflight));
+    world.add(light.clone());
 
-    world
+    SceneInfo {
+        world: world.with_object_ids(),
+        lights: vec![Arc::new(AreaLight::new(light))],
+        background: Background::Color(Color::new(0.0, 0.0, 0.0)),
+        lookfrom: Point3::new(26.0, 3.0, 6.0),
+        lookat: Point3::new(0.0, 2.0, 0.0),
+        ..Default::default()
+    }
 }
 
-pub fn cornell_box() -> HittableList {
-    let mut world = HittableList::new();
-
-    let red = Lambertian::new(Color::new(0.65, 0.05, 0.05));
-    let white = Lambertian::new(Color::new(0.73, 0.73, 0.73));
-    let green = Lambertian::new(Color::new(0.12, 0.45, 0.15));
-    let light = DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0));
+/// The walls and light of the Cornell box, without anything inside of it.
+fn cornell_walls(world: &mut HittableList, light: Arc<dyn Hittable>, ids: &mut MaterialIds) {
+    let red = ids.assign(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white = ids.assign(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = ids.assign(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
 
-    world.add(Rc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)));
-    world.add(Rc::new(YZRect::new(
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        0.0,
-        red.clone(),
-    )));
-    world.add(Rc::new(XZRect::new(
-        213.0, 343.0, 227.0, 332.0, 554.0, light,
-    )));
-    world.add(Rc::new(XZRect::new(
+    world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)));
+    world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)));
+    world.add(light);
+    world.add(Arc::new(XZRect::new(
         0.0,
         555.0,
         0.0,
@@ -183,7 +300,7 @@ pub fn cornell_box() -> HittableList {
         0.0,
         white.clone(),
     )));
-    world.add(Rc::new(XZRect::new(
+    world.add(Arc::new(XZRect::new(
         0.0,
         555.0,
         0.0,
@@ -191,14 +308,411 @@ pub fn cornell_box() -> HittableList {
         555.0,
         white.clone(),
     )));
-    world.add(Rc::new(XYRect::new(
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
+    world.add(Arc::new(XYRect::new(0.0, 555.0, 0.0, 555.0, 555.0, white)));
+}
+
+/// The two boxes that go in the Cornell box, rotated and moved into place.
+fn cornell_boxes(ids: &mut MaterialIds) -> (Arc<dyn Hittable>, Arc<dyn Hittable>) {
+    let white = ids.assign(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+
+    let box1 = Arc::new(Cuboid::new(
+        Point3::from(0.0),
+        Point3::new(165.0, 330.0, 165.0),
         white.clone(),
+    ));
+    let box1 = Arc::new(Instance::new(
+        box1,
+        Mat4::translation(Vec3::new(265.0, 0.0, 295.0))
+            * Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), 15.0),
+    ));
+
+    let box2 = Arc::new(Cuboid::new(
+        Point3::from(0.0),
+        Point3::new(165.0, 165.0, 165.0),
+        white,
+    ));
+    let box2 = Arc::new(Instance::new(
+        box2,
+        Mat4::translation(Vec3::new(130.0, 0.0, 65.0))
+            * Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), -18.0),
+    ));
+
+    (box1, box2)
+}
+
+fn cornell_scene_info(world: HittableList, light: Arc<dyn Hittable>) -> SceneInfo {
+    SceneInfo {
+        world: world.with_object_ids(),
+        lights: vec![Arc::new(AreaLight::new(light))],
+        background: Background::Color(Color::new(0.0, 0.0, 0.0)),
+        lookfrom: Point3::new(278.0, 278.0, -800.0),
+        lookat: Point3::new(278.0, 278.0, 0.0),
+        vfov: 40.0,
+        image_width: 600,
+        image_height: 600,
+        samples_per_pixel: 200,
+        ..Default::default()
+    }
+}
+
+pub fn cornell_box() -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
+    let mut world = HittableList::new();
+
+    let light: Arc<dyn Hittable> = Arc::new(XZRect::new(
+        213.0,
+        343.0,
+        227.0,
+        332.0,
+        554.0,
+        ids.assign(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0))),
+    ));
+    cornell_walls(&mut world, light.clone(), &mut ids);
+
+    let (box1, box2) = cornell_boxes(&mut ids);
+    world.add(box1);
+    world.add(box2);
+
+    cornell_scene_info(world, light)
+}
+
+pub fn cornell_smoke() -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
+    let mut world = HittableList::new();
+
+    let light: Arc<dyn Hittable> = Arc::new(XZRect::new(
+        113.0,
+        443.0,
+        127.0,
+        432.0,
+        554.0,
+        ids.assign(DiffuseLight::from_color(Color::new(7.0, 7.0, 7.0))),
+    ));
+    cornell_walls(&mut world, light.clone(), &mut ids);
+
+    let (box1, box2) = cornell_boxes(&mut ids);
+    world.add(Arc::new(ConstantMedium::new(
+        box1,
+        0.01,
+        ids.assign(Isotropic::from_color(Color::from(0.0))),
+    )));
+    world.add(Arc::new(ConstantMedium::new(
+        box2,
+        0.01,
+        ids.assign(Isotropic::from_color(Color::from(1.0))),
+    )));
+
+    cornell_scene_info(world, light)
+}
+
+/// A cloud and a glowing ball of fire, in the Cornell box.
+pub fn cornell_clouds() -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
+    let mut world = HittableList::new();
+
+    let light: Arc<dyn Hittable> = Arc::new(XZRect::new(
+        213.0,
+        343.0,
+        227.0,
+        332.0,
+        554.0,
+        ids.assign(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0))),
+    ));
+    cornell_walls(&mut world, light.clone(), &mut ids);
+
+    let cloud_boundary = Arc::new(Sphere::new(
+        Point3::new(370.0, 300.0, 300.0),
+        150.0,
+        ids.assign(Dielectric::new(1.0)),
+    ));
+    world.add(Arc::new(HeterogeneousMedium::new(
+        cloud_boundary,
+        Arc::new(NoiseDensity::new(0.015, 0.08)),
+        1.0,
+        ids.assign(Isotropic::from_color(Color::from(0.9))),
     )));
 
-    world
+    let fire_boundary = Arc::new(Sphere::new(
+        Point3::new(160.0, 120.0, 200.0),
+        100.0,
+        ids.assign(Dielectric::new(1.0)),
+    ));
+    world.add(Arc::new(
+        HeterogeneousMedium::new(
+            fire_boundary,
+            Arc::new(NoiseDensity::new(0.03, 0.05)),
+            0.2,
+            ids.assign(Isotropic::from_color(Color::from(0.5))),
+        )
+        .with_absorption(0.8)
+        .with_emission(Color::new(2.0, 0.7, 0.15)),
+    ));
+
+    cornell_scene_info(world, light)
+}
+
+/// The final scene of "Ray Tracing: The Next Week", with a bit of everything.
+pub fn final_scene(bvh: &mut BVHBuilder) -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
+    let mut boxes1 = HittableList::new();
+    let ground = ids.assign(Lambertian::new(Color::new(0.48, 0.83, 0.53)));
+
+    let boxes_per_side = 20;
+    for i in 0..boxes_per_side {
+        for j in 0..boxes_per_side {
+            let w = 100.0;
+            let x0 = -1000.0 + i as f32 * w;
+            let z0 = -1000.0 + j as f32 * w;
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = random_range(1.0, 101.0);
+            let z1 = z0 + w;
+
+            boxes1.add(Arc::new(Cuboid::new(
+                Point3::new(x0, y0, z0),
+                Point3::new(x1, y1, z1),
+                ground.clone(),
+            )));
+        }
+    }
+
+    let mut world = HittableList::new();
+    world.add(Arc::new(bvh.build_list(&boxes1, 0.0, 1.0)));
+
+    let light: Arc<dyn Hittable> = Arc::new(XZRect::new(
+        123.0,
+        423.0,
+        147.0,
+        412.0,
+        554.0,
+        ids.assign(DiffuseLight::from_color(Color::new(7.0, 7.0, 7.0))),
+    ));
+    world.add(light.clone());
+
+    let center1 = Point3::new(400.0, 400.0, 200.0);
+    let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
+    world.add(Arc::new(MovingSphere::new(
+        center1,
+        center2,
+        0.0,
+        1.0,
+        50.0,
+        ids.assign(Lambertian::new(Color::new(0.7, 0.3, 0.1))),
+    )));
+
+    world.add(Arc::new(Sphere::new(
+        Point3::new(260.0, 150.0, 45.0),
+        50.0,
+        ids.assign(Dielectric::new(1.5)),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 150.0, 145.0),
+        50.0,
+        ids.assign(Metal::new(Color::new(0.8, 0.8, 0.9), 1.0)),
+    )));
+
+    // A glass ball filled with blue smoke.
+    let boundary = Arc::new(Sphere::new(
+        Point3::new(360.0, 150.0, 145.0),
+        70.0,
+        ids.assign(Dielectric::new(1.5)),
+    ));
+    world.add(boundary.clone());
+    world.add(Arc::new(ConstantMedium::new(
+        boundary,
+        0.2,
+        ids.assign(Isotropic::from_color(Color::new(0.2, 0.4, 0.9))),
+    )));
+
+    // Thin mist over everything.
+    let boundary = Arc::new(Sphere::new(
+        Point3::from(0.0),
+        5000.0,
+        ids.assign(Dielectric::new(1.5)),
+    ));
+    world.add(Arc::new(ConstantMedium::new(
+        boundary,
+        0.0001,
+        ids.assign(Isotropic::from_color(Color::from(1.0))),
+    )));
+
+    let earth_texture = Arc::new(ImageTexture::new("res/earthmap.jpg"));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(400.0, 200.0, 400.0),
+        100.0,
+        ids.assign(Lambertian::from_texture(earth_texture)),
+    )));
+
+    let pertext = Arc::new(NoiseTexture::new(0.1));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(220.0, 280.0, 300.0),
+        80.0,
+        ids.assign(Lambertian::from_texture(pertext)),
+    )));
+
+    // A box full of little spheres.
+    let mut boxes2 = HittableList::new();
+    let white = ids.assign(Lambertian::new(Color::from(0.73)));
+    for _ in 0..1000 {
+        boxes2.add(Arc::new(Sphere::new(
+            Point3::random_range(0.0, 165.0),
+            10.0,
+            white.clone(),
+        )));
+    }
+    world.add(Arc::new(Instance::new(
+        Arc::new(bvh.build_list(&boxes2, 0.0, 1.0)),
+        Mat4::translation(Vec3::new(-100.0, 270.0, 395.0))
+            * Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), 15.0),
+    )));
+
+    SceneInfo {
+        world: world.with_object_ids(),
+        lights: vec![Arc::new(AreaLight::new(light))],
+        background: Background::Color(Color::new(0.0, 0.0, 0.0)),
+        lookfrom: Point3::new(478.0, 278.0, -600.0),
+        lookat: Point3::new(278.0, 278.0, 0.0),
+        vfov: 40.0,
+        image_width: 800,
+        image_height: 800,
+        samples_per_pixel: 1000,
+        ..Default::default()
+    }
+}
+
+/// A few spheres outside, in the afternoon sun.
+pub fn sunny_spheres() -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
+    let mut world = HittableList::new();
+
+    let checker = CheckerTexture::new(
+        SolidColor::new(Color::new(0.2, 0.3, 0.1)),
+        SolidColor::new(Color::from(0.9)),
+    );
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ids.assign(Lambertian::from_texture(Arc::new(checker))),
+    )));
+
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        ids.assign(Dielectric::new(1.5)),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(-2.2, 1.0, -0.5),
+        1.0,
+        ids.assign(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(2.2, 1.0, -0.5),
+        1.0,
+        ids.assign(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
+    )));
+
+    let sky = Arc::new(Sky::new(25.0, 120.0, 3.0));
+
+    SceneInfo {
+        world: world.with_object_ids(),
+        lights: vec![Arc::new(SunLight::new(sky.clone()))],
+        background: Background::Sky(sky),
+        lookfrom: Point3::new(0.0, 2.0, 9.0),
+        lookat: Point3::new(0.0, 1.0, 0.0),
+        vfov: 30.0,
+        ..Default::default()
+    }
+}
+
+/// The spheres from `sunny_spheres`, lit by a spot light, a point light and faint moonlight instead.
+pub fn delta_lights() -> SceneInfo {
+    let mut scene = sunny_spheres();
+
+    scene.lights = vec![
+        Arc::new(SpotLight::new(
+            Point3::new(-4.0, 6.0, 3.0),
+            Vec3::new(4.0, -6.0, -3.0),
+            Color::new(60.0, 55.0, 45.0),
+            25.0,
+            8.0,
+        )),
+        Arc::new(PointLight::new(
+            Point3::new(2.5, 2.5, 2.0),
+            Color::new(2.0, 4.0, 8.0),
+        )),
+        Arc::new(DirectionalLight::new(
+            Vec3::new(1.0, -1.0, -1.0),
+            Color::new(0.1, 0.1, 0.15),
+        )),
+    ];
+    scene.background = Background::Color(Color::new(0.01, 0.01, 0.02));
+
+    scene
+}
+
+/// Rolling hills made of a single mesh of 80 000 triangles, with a few spheres on top. Mostly there
+/// to see how the BVH copes with large meshes, see `--bvh-stats`.
+pub fn terrain(bvh: &mut BVHBuilder) -> SceneInfo {
+    let mut ids = MaterialIds::new();
+
+    const CELLS: usize = 200;
+    const SIZE: f32 = 24.0;
+
+    let noise = Perlin::new();
+    let height =
+        |x: f32, z: f32| 2.0 * noise.turb_scale(&Point3::new(0.12 * x, 0.0, 0.12 * z), 5) - 1.0;
+
+    let cell_size = SIZE / CELLS as f32;
+    let mut positions = Vec::with_capacity((CELLS + 1) * (CELLS + 1));
+    for row in 0..=CELLS {
+        for column in 0..=CELLS {
+            let x = column as f32 * cell_size - SIZE / 2.0;
+            let z = row as f32 * cell_size - SIZE / 2.0;
+            positions.push(Point3::new(x, height(x, z), z));
+        }
+    }
+
+    let mut indices = Vec::with_capacity(2 * CELLS * CELLS);
+    for row in 0..CELLS {
+        for column in 0..CELLS {
+            let corner = row * (CELLS + 1) + column;
+            indices.push([corner, corner + CELLS + 1, corner + 1]);
+            indices.push([corner + 1, corner + CELLS + 1, corner + CELLS + 2]);
+        }
+    }
+
+    let mut world = HittableList::new();
+    world.add(Arc::new(TriangleMesh::new(
+        positions,
+        None,
+        None,
+        indices,
+        ids.assign(Lambertian::new(Color::new(0.45, 0.4, 0.3))),
+        bvh,
+    )));
+
+    world.add(Arc::new(Sphere::new(
+        Point3::new(-1.5, height(-1.5, 0.0) + 1.0, 0.0),
+        1.0,
+        ids.assign(Metal::new(Color::new(0.7, 0.6, 0.5), 0.05)),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(1.5, height(1.5, 1.0) + 1.0, 1.0),
+        1.0,
+        ids.assign(Dielectric::new(1.5)),
+    )));
+
+    SceneInfo {
+        world: world.with_object_ids(),
+        background: Background::Color(Color::new(0.7, 0.8, 1.0)),
+        lookfrom: Point3::new(0.0, 5.0, 12.0),
+        lookat: Point3::new(0.0, 0.5, 0.0),
+        vfov: 40.0,
+        ..Default::default()
+    }
 }
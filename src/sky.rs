@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use crate::{
+    lights::{Light, LightSample},
+    onb::ONB,
+    pdf::Pdf,
+    utils::{random_2d, to_radians, INFINITY, PI},
+    vec3::{Color, Point3, Vec3},
+};
+
+/// Makes the sky come out around 1 with the default intensity. The model itself works in kcd/m².
+const DEFAULT_INTENSITY: f32 = 0.05;
+
+/// Luminance of the sun before the atmosphere gets to it, in kcd/m².
+const SUN_LUMINANCE: f32 = 2.0e6;
+
+/// Angular radius of the real sun, in degrees.
+const SUN_RADIUS: f32 = 0.265;
+
+/// The clear daytime sky from "A Practical Analytic Model for Daylight" by Preetham et al., along
+/// with the sun itself.
+pub struct Sky {
+    /// Unit vector pointing at the sun.
+    sun_direction: Vec3,
+    /// Coefficients A to E of the Perez function, for Y, x and y.
+    perez: [[f32; 5]; 3],
+    /// The Y, x and y at the zenith, divided by the Perez function there.
+    zenith: [f32; 3],
+    sun_radiance: Color,
+    /// The sun is a disc on the sky of this angular radius.
+    sun_cos_max: f32,
+    sun_solid_angle: f32,
+    intensity: f32,
+}
+
+impl Sky {
+    /// `elevation` is the angle of the sun above the horizon, between 0 and 90 degrees. `azimuth` is
+    /// the compass direction of the sun in degrees, with 0 towards -z and 90 towards +x.
+    /// `turbidity` is the haziness of the air, from about 2 for a very clear sky to 10.
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Self {
+        let (sin_elevation, cos_elevation) = f32::sin_cos(to_radians(elevation));
+        let (sin_azimuth, cos_azimuth) = f32::sin_cos(to_radians(azimuth));
+        let sun_direction = Vec3::new(
+            cos_elevation * sin_azimuth,
+            sin_elevation,
+            -cos_elevation * cos_azimuth,
+        );
+
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // Angle of the sun from the zenith.
+        let theta_s = to_radians(90.0 - elevation);
+        let (t2, s, s2, s3) = (t * t, theta_s, theta_s * theta_s, theta_s.powi(3));
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * f32::tan(chi) - 0.2155 * t + 2.4192;
+        let zenith_x = t2 * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let zenith_y = t2 * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        let zenith = [zenith_luminance, zenith_x, zenith_y];
+        let zenith = [0, 1, 2].map(|i| zenith[i] / perez_function(&perez[i], 1.0, theta_s));
+
+        Self {
+            sun_direction,
+            perez,
+            zenith,
+            sun_radiance: SUN_LUMINANCE * sun_transmittance(theta_s, turbidity),
+            sun_cos_max: f32::cos(to_radians(SUN_RADIUS)),
+            sun_solid_angle: cone_solid_angle(SUN_RADIUS),
+            intensity: DEFAULT_INTENSITY,
+        }
+    }
+
+    /// Scales the brightness of both the sky and the sun.
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = DEFAULT_INTENSITY * intensity;
+        self
+    }
+
+    /// Makes the sun a bigger (or smaller) disc of the given angular radius in degrees, which softens
+    /// its shadows. The sun still lights the scene just as much.
+    pub fn with_sun_radius(mut self, degrees: f32) -> Self {
+        let solid_angle = cone_solid_angle(degrees);
+
+        self.sun_radiance *= self.sun_solid_angle / solid_angle;
+        self.sun_cos_max = f32::cos(to_radians(degrees));
+        self.sun_solid_angle = solid_angle;
+        self
+    }
+
+    /// The light arriving from `direction`, including the sun if it lies in that direction.
+    pub fn value(&self, direction: &Vec3) -> Color {
+        let d = Vec3::unit_vector(*direction);
+
+        // The model only covers the sky above the horizon, below it the horizon just continues.
+        let cos_theta = f32::max(d.y, 0.001);
+        let cos_gamma = Vec3::dot(&d, &self.sun_direction);
+        let gamma = f32::acos(cos_gamma.clamp(-1.0, 1.0));
+
+        let [luminance, x, y] =
+            [0, 1, 2].map(|i| self.zenith[i] * perez_function(&self.perez[i], cos_theta, gamma));
+
+        let mut color = xyy_to_rgb(x, y, luminance);
+        if cos_gamma >= self.sun_cos_max {
+            color += self.sun_radiance;
+        }
+
+        self.intensity * color
+    }
+}
+
+/// Relative brightness of the sky at `theta` from the zenith and `gamma` from the sun.
+fn perez_function(coefficients: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coefficients;
+    let cos_gamma = f32::cos(gamma);
+
+    (1.0 + a * f32::exp(b / cos_theta))
+        * (1.0 + c * f32::exp(d * gamma) + e * cos_gamma * cos_gamma)
+}
+
+/// Converts a CIE xyY color to linear sRGB, clipping colors outside of the sRGB gamut.
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Color {
+    if y <= 0.0 {
+        return Color::from(0.0);
+    }
+
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+
+    let r = 3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z;
+    let g = -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z;
+    let b = 0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z;
+
+    Color::new(f32::max(r, 0.0), f32::max(g, 0.0), f32::max(b, 0.0))
+}
+
+/// How much of the sunlight in the red, green and blue parts of the spectrum makes it through the
+/// atmosphere, from scattering by air molecules (Rayleigh) and by haze (Ångström's formula).
+fn sun_transmittance(theta_s: f32, turbidity: f32) -> Color {
+    // Relative length of the path through the atmosphere, from Kasten's formula.
+    let zenith_degrees = theta_s * 180.0 / PI;
+    let air_mass = 1.0 / (f32::cos(theta_s) + 0.15 * f32::powf(93.885 - zenith_degrees, -1.253));
+
+    let beta = 0.04608 * turbidity - 0.04586;
+    let channel = |wavelength: f32| {
+        let rayleigh = 0.008735 * wavelength.powf(-4.08);
+        let haze = beta * wavelength.powf(-1.3);
+        f32::exp(-air_mass * (rayleigh + haze))
+    };
+
+    // Wavelengths in micrometers.
+    Color::new(channel(0.68), channel(0.55), channel(0.44))
+}
+
+/// Solid angle of a cone with an opening angle of `degrees` around its axis.
+fn cone_solid_angle(degrees: f32) -> f32 {
+    // 1 - cos(x) = 2 sin²(x / 2), which doesn't lose all precision for tiny angles.
+    let half_sin = f32::sin(to_radians(degrees) / 2.0);
+    4.0 * PI * half_sin * half_sin
+}
+
+/// The sun of a `Sky`, to sample directly. It's a light that's infinitely far away, so rays that
+/// escape the scene towards it still get to see it in the sky.
+pub struct SunLight {
+    sky: Arc<Sky>,
+}
+
+impl SunLight {
+    pub fn new(sky: Arc<Sky>) -> Self {
+        Self { sky }
+    }
+}
+
+impl Light for SunLight {
+    fn sample(&self, _p: &Point3, _time: f32) -> Option<LightSample> {
+        let sun_pdf = SunPdf::new(&self.sky);
+        let direction = Vec3::unit_vector(sun_pdf.generate());
+
+        Some(LightSample {
+            direction,
+            distance: INFINITY,
+            // The bit of sky behind the sun is part of this light as well.
+            radiance: self.sky.value(&direction),
+            pdf: 1.0 / self.sky.sun_solid_angle,
+        })
+    }
+
+    fn pdf(&self, _origin: &Point3) -> Box<dyn Pdf + '_> {
+        Box::new(SunPdf::new(&self.sky))
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+}
+
+/// Directions spread evenly over the disc of the sun.
+struct SunPdf<'a> {
+    sky: &'a Sky,
+    uvw: ONB,
+}
+
+impl<'a> SunPdf<'a> {
+    fn new(sky: &'a Sky) -> Self {
+        Self {
+            sky,
+            uvw: ONB::build_from_w(&sky.sun_direction),
+        }
+    }
+}
+
+impl Pdf for SunPdf<'_> {
+    fn value(&self, direction: &Vec3) -> f32 {
+        let cosine = Vec3::dot(&Vec3::unit_vector(*direction), &self.sky.sun_direction);
+        if cosine >= self.sky.sun_cos_max {
+            1.0 / self.sky.sun_solid_angle
+        } else {
+            0.0
+        }
+    }
+
+    fn generate(&self) -> Vec3 {
+        let (r1, r2) = random_2d();
+
+        let z = 1.0 - r1 * self.sky.sun_solid_angle / (2.0 * PI);
+        let phi = 2.0 * PI * r2;
+        let sin_theta = f32::sqrt(f32::max(1.0 - z * z, 0.0));
+
+        self.uvw.local(&Vec3::new(
+            f32::cos(phi) * sin_theta,
+            f32::sin(phi) * sin_theta,
+            z,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cone_solid_angles() {
+        assert!((cone_solid_angle(90.0) - 2.0 * PI).abs() < 1e-5);
+        assert!((cone_solid_angle(180.0) - 4.0 * PI).abs() < 1e-5);
+
+        // Small angles approach the area of a disc.
+        let radius = to_radians(SUN_RADIUS);
+        assert!((cone_solid_angle(SUN_RADIUS) / (PI * radius * radius) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sun_is_brightest_and_bigger_suns_are_as_bright_overall() {
+        let sky = Sky::new(30.0, 90.0, 3.0);
+        let sun = Vec3::new(f32::cos(to_radians(30.0)), f32::sin(to_radians(30.0)), 0.0);
+        assert!((sky.sun_direction - sun).length() < 1e-5);
+
+        let zenith = sky.value(&Vec3::new(0.0, 1.0, 0.0));
+        let away = sky.value(&Vec3::new(-1.0, 0.5, 0.0));
+        assert!(sky.value(&sun).luminance() > 1000.0 * zenith.luminance());
+        assert!(zenith.luminance() > 0.0 && away.luminance() > 0.0);
+
+        let big = Sky::new(30.0, 90.0, 3.0).with_sun_radius(5.0);
+        let power = |sky: &Sky| sky.sun_radiance.luminance() * sky.sun_solid_angle;
+        assert!((power(&big) / power(&sky) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sun_light_samples_the_disc_of_the_sun() {
+        let sky = Arc::new(Sky::new(45.0, 0.0, 3.0).with_sun_radius(5.0));
+        let light = SunLight::new(sky.clone());
+        let pdf = light.pdf(&Point3::from(0.0));
+
+        for _ in 0..1000 {
+            let sample = light.sample(&Point3::from(0.0), 0.0).unwrap();
+            assert!(Vec3::dot(&sample.direction, &sky.sun_direction) >= sky.sun_cos_max - 1e-5);
+            assert!((pdf.value(&sample.direction) - sample.pdf).abs() < 1e-3 * sample.pdf);
+        }
+        assert_eq!(pdf.value(&Vec3::new(0.0, 1.0, 0.0)), 0.0);
+        assert!((sky.sun_solid_angle * pdf.value(&sky.sun_direction) - 1.0).abs() < 1e-5);
+    }
+}
@@ -1,12 +1,12 @@
-use image::io::Reader as ImageReader;
-use std::rc::Rc;
+use image::{io::Reader as ImageReader, ImageResult};
+use std::{path::Path, sync::Arc};
 
 use crate::{
     perlin::Perlin,
     vec3::{Color, Point3},
 };
 
-pub trait Texture {
+pub trait Texture: Send + Sync {
     fn value(&self, uv: &(f32, f32), p: Point3) -> Color;
 }
 
@@ -21,25 +21,25 @@ impl SolidColor {
 }
 
 impl Texture for SolidColor {
-    fn value(&self, uv: &(f32, f32), p: Point3) -> Color {
+    fn value(&self, _uv: &(f32, f32), _p: Point3) -> Color {
         self.color
     }
 }
 
 pub struct CheckerTexture {
-    even: Rc<dyn Texture>,
-    odd: Rc<dyn Texture>,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
 }
 
 impl CheckerTexture {
     pub fn new(even: SolidColor, odd: SolidColor) -> Self {
         Self {
-            even: Rc::new(even),
-            odd: Rc::new(odd),
+            even: Arc::new(even),
+            odd: Arc::new(odd),
         }
     }
 
-    pub fn from_textures(even: Rc<dyn Texture>, odd: Rc<dyn Texture>) -> Self {
+    pub fn from_textures(even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
         Self { even, odd }
     }
 }
@@ -85,18 +85,23 @@ pub struct ImageTexture {
 
 impl ImageTexture {
     pub fn new(img_path: &str) -> Self {
-        let img = ImageReader::open(img_path).unwrap().decode().unwrap();
+        Self::open(img_path).unwrap()
+    }
 
-        Self {
-            data: img.as_bytes().to_vec(),
+    pub fn open<P: AsRef<Path>>(img_path: P) -> ImageResult<Self> {
+        let img = ImageReader::open(img_path)?.decode()?;
+
+        Ok(Self {
             width: img.width(),
             height: img.height(),
-        }
+            // The sampling code below expects tightly packed RGB bytes.
+            data: img.into_rgb8().into_raw(),
+        })
     }
 }
 
 impl Texture for ImageTexture {
-    fn value(&self, uv: &(f32, f32), p: Point3) -> Color {
+    fn value(&self, uv: &(f32, f32), _p: Point3) -> Color {
         let u = uv.0.clamp(0.0, 1.0);
         let v = 1.0 - uv.1.clamp(0.0, 1.0);
 
@@ -114,7 +119,7 @@ impl Texture for ImageTexture {
         let bytes_per_pixel = 3;
         let bytes_per_scanline = bytes_per_pixel * self.width;
         let pixel_idx = j * bytes_per_scanline + i * bytes_per_pixel;
-        let pixel_r = self.data[(pixel_idx + 0) as usize] as f32 * color_scale;
+        let pixel_r = self.data[pixel_idx as usize] as f32 * color_scale;
         let pixel_g = self.data[(pixel_idx + 1) as usize] as f32 * color_scale;
         let pixel_b = self.data[(pixel_idx + 2) as usize] as f32 * color_scale;
 
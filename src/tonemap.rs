@@ -0,0 +1,206 @@
+use clap::ValueEnum;
+
+use crate::{utils::clamp, vec3::Color};
+
+/// Radiance gets capped at this before tone mapping. The curves are all flat long before it, but
+/// infinite values would turn into NaNs in them.
+const MAX_RADIANCE: f32 = 1.0e6;
+
+/// How radiance above 1 gets squeezed into the range of an 8-bit image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ToneMapper {
+    /// Cuts off everything above 1.
+    Clamp,
+    /// `L / (1 + L)` on the luminance, which never quite reaches white.
+    Reinhard,
+    /// Reinhard that maps the white point to white.
+    ExtendedReinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+}
+
+/// Turns linear radiance into the colors of an 8-bit sRGB image.
+#[derive(Clone, Copy, Debug)]
+pub struct ToneMapping {
+    pub operator: ToneMapper,
+    /// Exposure compensation in stops, every stop doubles the brightness.
+    pub exposure: f32,
+    /// The luminance that ends up as pure white with `ToneMapper::ExtendedReinhard`.
+    pub white_point: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapper::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+        }
+    }
+}
+
+impl ToneMapping {
+    /// Exposes and tone maps `color`, which comes out as linear values between 0 and 1.
+    pub fn apply(&self, color: Color) -> Color {
+        let color = (f32::exp2(self.exposure) * color).map(|c| clamp(c, 0.0, MAX_RADIANCE));
+
+        let mapped = match self.operator {
+            ToneMapper::Clamp => color,
+            ToneMapper::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMapper::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                scale_luminance(color, |l| l * (1.0 + l / white_squared) / (1.0 + l))
+            }
+            ToneMapper::Aces => color.map(aces),
+            ToneMapper::Hable => {
+                // The curve is made for scenes that are a stop darker, and to map 11.2 to white.
+                const EXPOSURE_BIAS: f32 = 2.0;
+                const WHITE: f32 = 11.2;
+                color.map(|c| hable(EXPOSURE_BIAS * c) / hable(WHITE))
+            }
+        };
+
+        mapped.map(|c| clamp(c, 0.0, 1.0))
+    }
+
+    /// Tone maps `color` and encodes it with the sRGB transfer function, 8 bits per channel.
+    pub fn encode_srgb8(&self, color: Color) -> [u8; 3] {
+        let color = self.apply(color);
+        [color.x, color.y, color.z].map(|c| (255.0 * srgb_encode(c) + 0.5) as u8)
+    }
+}
+
+/// Scales the whole color by what `curve` does to its luminance, which keeps its hue intact.
+fn scale_luminance(color: Color, curve: impl Fn(f32) -> f32) -> Color {
+    let luminance = color.luminance();
+    if luminance <= 0.0 {
+        return Color::from(0.0);
+    }
+
+    (curve(luminance) / luminance) * color
+}
+
+fn aces(x: f32) -> f32 {
+    const A: f32 = 2.51;
+    const B: f32 = 0.03;
+    const C: f32 = 2.43;
+    const D: f32 = 0.59;
+    const E: f32 = 0.14;
+
+    (x * (A * x + B)) / (x * (C * x + D) + E)
+}
+
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15; // Shoulder strength
+    const B: f32 = 0.50; // Linear strength
+    const C: f32 = 0.10; // Linear angle
+    const D: f32 = 0.20; // Toe strength
+    const E: f32 = 0.02; // Toe numerator
+    const F: f32 = 0.30; // Toe denominator
+
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+/// The sRGB transfer function, from linear values to the ones stored in the image.
+fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [ToneMapper; 5] = [
+        ToneMapper::Clamp,
+        ToneMapper::Reinhard,
+        ToneMapper::ExtendedReinhard,
+        ToneMapper::Aces,
+        ToneMapper::Hable,
+    ];
+
+    fn mapping(operator: ToneMapper) -> ToneMapping {
+        ToneMapping {
+            operator,
+            ..Default::default()
+        }
+    }
+
+    fn gray(mapping: &ToneMapping, value: f32) -> f32 {
+        mapping.apply(Color::from(value)).x
+    }
+
+    #[test]
+    fn curves_go_from_black_to_white() {
+        for operator in OPERATORS {
+            let mapping = mapping(operator);
+
+            assert!(gray(&mapping, 0.0) < 1e-6, "{:?}", operator);
+            assert!(gray(&mapping, -1.0) < 1e-6, "{:?}", operator);
+
+            let mut previous = 0.0;
+            for value in [0.01, 0.1, 0.5, 1.0, 2.0, 10.0, 1000.0] {
+                let mapped = gray(&mapping, value);
+                assert!(mapped >= previous && mapped <= 1.0, "{:?}", operator);
+                previous = mapped;
+            }
+        }
+    }
+
+    #[test]
+    fn curves_at_one_and_infinity() {
+        let at_one = |operator| gray(&mapping(operator), 1.0);
+        let at_infinity = |operator| gray(&mapping(operator), f32::INFINITY);
+
+        assert_eq!(at_one(ToneMapper::Clamp), 1.0);
+        assert!((at_one(ToneMapper::Reinhard) - 0.5).abs() < 1e-5);
+        // (1 + 1/16) / 2 with the default white point of 4.
+        assert!((at_one(ToneMapper::ExtendedReinhard) - 0.53125).abs() < 1e-5);
+        assert!((at_one(ToneMapper::Aces) - 0.8038).abs() < 1e-3);
+        assert!(at_one(ToneMapper::Hable) > 0.0 && at_one(ToneMapper::Hable) < 1.0);
+
+        // Only plain Reinhard never quite gets there, but it's as close as an 8-bit image can tell.
+        for operator in OPERATORS {
+            assert!(at_infinity(operator) > 0.999, "{:?}", operator);
+        }
+        let white = mapping(ToneMapper::ExtendedReinhard);
+        assert!((gray(&white, white.white_point) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let brighter = ToneMapping {
+            exposure: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(gray(&brighter, 0.125), 0.5);
+    }
+
+    #[test]
+    fn hues_survive_the_luminance_curves() {
+        let mapped = mapping(ToneMapper::Reinhard).apply(Color::new(1.0, 0.5, 0.25));
+        assert!((mapped.x / mapped.y - 2.0).abs() < 1e-5);
+        assert!((mapped.y / mapped.z - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn srgb_encoding() {
+        let clamp = mapping(ToneMapper::Clamp);
+
+        assert_eq!(clamp.encode_srgb8(Color::from(0.0)), [0, 0, 0]);
+        assert_eq!(clamp.encode_srgb8(Color::from(1.0)), [255, 255, 255]);
+        assert_eq!(
+            clamp.encode_srgb8(Color::new(0.5, 0.2, 0.001)),
+            [188, 124, 3]
+        );
+        assert_eq!(
+            clamp.encode_srgb8(Color::from(f32::INFINITY)),
+            [255, 255, 255]
+        );
+    }
+}
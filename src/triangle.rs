@@ -0,0 +1,566 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    bvh::{BVHBuilder, TraversalStats, BVH},
+    hittable::{solid_angle_pdf, HitRecord, Hittable},
+    materials::Material,
+    ray::Ray,
+    utils::{max, min, random, random_2d, INFINITY},
+    vec3::{Point3, Vec3},
+};
+
+// Padding added to the bounding boxes, so that axis-aligned triangles don't end up with a flat box.
+const BOX_PADDING: f32 = 0.0001;
+
+/// Barycentric coordinates and distance of a ray-triangle intersection.
+struct TriangleIntersection {
+    t: f32,
+    u: f32,
+    v: f32,
+}
+
+/// Möller-Trumbore ray-triangle intersection.
+fn intersect(
+    ray: &Ray,
+    vertices: [Point3; 3],
+    t_min: f32,
+    t_max: f32,
+) -> Option<TriangleIntersection> {
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+
+    let pvec = Vec3::cross(&ray.direction, &edge2);
+    let det = Vec3::dot(&edge1, &pvec);
+
+    // The ray is parallel to the triangle.
+    if det.abs() < 1e-8 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let tvec = ray.origin - vertices[0];
+    let u = Vec3::dot(&tvec, &pvec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let qvec = Vec3::cross(&tvec, &edge1);
+    let v = Vec3::dot(&ray.direction, &qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = Vec3::dot(&edge2, &qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some(TriangleIntersection { t, u, v })
+}
+
+/// Builds the hit record for an intersection, interpolating the vertex normals and UVs if the
+/// triangle has them.
+fn hit_record<'a>(
+    ray: &Ray,
+    hit: &TriangleIntersection,
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [(f32, f32); 3],
+    mat: &'a dyn Material,
+) -> HitRecord<'a> {
+    let w = 1.0 - hit.u - hit.v;
+
+    let geometric_normal = Vec3::unit_vector(Vec3::cross(
+        &(vertices[1] - vertices[0]),
+        &(vertices[2] - vertices[0]),
+    ));
+    let mut shading_normal = match normals {
+        Some(n) => Vec3::unit_vector(w * n[0] + hit.u * n[1] + hit.v * n[2]),
+        None => geometric_normal,
+    };
+
+    // Keep the shading normal on the same side as the winding order says the triangle faces.
+    if Vec3::dot(&shading_normal, &geometric_normal) < 0.0 {
+        shading_normal = -shading_normal;
+    }
+
+    let front_face = Vec3::dot(&ray.direction, &geometric_normal) < 0.0;
+
+    HitRecord {
+        t: hit.t,
+        p: ray.at(hit.t),
+        uv: (
+            w * uvs[0].0 + hit.u * uvs[1].0 + hit.v * uvs[2].0,
+            w * uvs[0].1 + hit.u * uvs[1].1 + hit.v * uvs[2].1,
+        ),
+        normal: if front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        },
+        mat,
+        object_id: 0,
+        front_face,
+    }
+}
+
+fn bounding_box(vertices: [Point3; 3]) -> AABB {
+    let minimum = Point3::new(
+        min(min(vertices[0].x, vertices[1].x), vertices[2].x),
+        min(min(vertices[0].y, vertices[1].y), vertices[2].y),
+        min(min(vertices[0].z, vertices[1].z), vertices[2].z),
+    );
+    let maximum = Point3::new(
+        max(max(vertices[0].x, vertices[1].x), vertices[2].x),
+        max(max(vertices[0].y, vertices[1].y), vertices[2].y),
+        max(max(vertices[0].z, vertices[1].z), vertices[2].z),
+    );
+
+    AABB::new(
+        minimum - Vec3::from(BOX_PADDING),
+        maximum + Vec3::from(BOX_PADDING),
+    )
+}
+
+fn area(vertices: [Point3; 3]) -> f32 {
+    0.5 * Vec3::cross(&(vertices[1] - vertices[0]), &(vertices[2] - vertices[0])).length()
+}
+
+/// Density of `direction` from `origin` when picking points uniformly over a surface with the given
+/// `area` that the triangle is part of.
+fn pdf_value(vertices: [Point3; 3], area: f32, origin: &Point3, direction: &Vec3) -> f32 {
+    match intersect(
+        &Ray::new(*origin, *direction, 0.0),
+        vertices,
+        0.001,
+        INFINITY,
+    ) {
+        Some(hit) => {
+            let normal = Vec3::unit_vector(Vec3::cross(
+                &(vertices[1] - vertices[0]),
+                &(vertices[2] - vertices[0]),
+            ));
+            solid_angle_pdf(direction, hit.t, &normal, area)
+        }
+        None => 0.0,
+    }
+}
+
+/// Uniformly picks a point on the triangle.
+fn random_point(vertices: [Point3; 3]) -> Point3 {
+    let (r1, r2) = random_2d();
+    let sqrt_r1 = f32::sqrt(r1);
+
+    (1.0 - sqrt_r1) * vertices[0] + sqrt_r1 * (1.0 - r2) * vertices[1] + sqrt_r1 * r2 * vertices[2]
+}
+
+/// UVs for triangles that don't specify any.
+const DEFAULT_UVS: [(f32, f32); 3] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+
+/// A single, standalone triangle. The front face is the side from which the vertices are in
+/// counter-clockwise order.
+pub struct Triangle<M: Material> {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [(f32, f32); 3],
+    material: M,
+}
+
+impl<M: Material> Triangle<M> {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, material: M) -> Self {
+        Self {
+            vertices: [v0, v1, v2],
+            normals: None,
+            uvs: DEFAULT_UVS,
+            material,
+        }
+    }
+
+    /// Smooth shades the triangle by interpolating these vertex normals.
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [(f32, f32); 3]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+}
+
+impl<M: Material> Hittable for Triangle<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let hit = intersect(ray, self.vertices, t_min, t_max)?;
+
+        Some(hit_record(
+            ray,
+            &hit,
+            self.vertices,
+            self.normals,
+            self.uvs,
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(bounding_box(self.vertices))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        pdf_value(self.vertices, area(self.vertices), origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        random_point(self.vertices) - *origin
+    }
+}
+
+/// Vertex data shared by all the triangles of a mesh.
+struct MeshData<M: Material> {
+    positions: Vec<Point3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f32, f32)>>,
+    indices: Vec<[usize; 3]>,
+    material: M,
+    /// Total area of the triangles.
+    area: f32,
+}
+
+/// A triangle that points into the vertex data of a mesh.
+struct MeshTriangle<M: Material> {
+    mesh: Arc<MeshData<M>>,
+    index: usize,
+}
+
+impl<M: Material> MeshTriangle<M> {
+    fn vertices(&self) -> [Point3; 3] {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        [
+            self.mesh.positions[i0],
+            self.mesh.positions[i1],
+            self.mesh.positions[i2],
+        ]
+    }
+}
+
+impl<M: Material> Hittable for MeshTriangle<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let vertices = self.vertices();
+        let hit = intersect(ray, vertices, t_min, t_max)?;
+
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let normals = self
+            .mesh
+            .normals
+            .as_ref()
+            .map(|normals| [normals[i0], normals[i1], normals[i2]]);
+        let uvs = match &self.mesh.uvs {
+            Some(uvs) => [uvs[i0], uvs[i1], uvs[i2]],
+            None => DEFAULT_UVS,
+        };
+
+        Some(hit_record(
+            ray,
+            &hit,
+            vertices,
+            normals,
+            uvs,
+            &self.mesh.material,
+        ))
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        Some(bounding_box(self.vertices()))
+    }
+
+    /// The density for the whole mesh, which picks its triangles proportional to their area.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        pdf_value(self.vertices(), self.mesh.area, origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        random_point(self.vertices()) - *origin
+    }
+}
+
+/// An indexed triangle mesh. Vertices are shared between triangles, and optionally carry a normal
+/// and UV coordinates that get interpolated over the faces. The triangles are put in their own
+/// BVH, so the mesh is a single object to the rest of the scene.
+pub struct TriangleMesh {
+    bvh: BVH,
+    triangles: Vec<Arc<dyn Hittable>>,
+    /// Running total of the triangle areas, for picking triangles when the mesh is a light.
+    area_cdf: Vec<f32>,
+}
+
+impl TriangleMesh {
+    /// `normals` and `uvs` are per vertex, so they need to be as long as `positions`. The BVH of
+    /// the triangles is built with `bvh`.
+    ///
+    /// Panics if the mesh has no triangles, or if the indices or attributes are out of range.
+    pub fn new<M: Material + 'static>(
+        positions: Vec<Point3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f32, f32)>>,
+        indices: Vec<[usize; 3]>,
+        material: M,
+        bvh: &mut BVHBuilder,
+    ) -> Self {
+        assert!(
+            !indices.is_empty(),
+            "A triangle mesh needs at least one triangle!"
+        );
+        assert!(
+            indices.iter().flatten().all(|&i| i < positions.len()),
+            "Triangle mesh index out of range!"
+        );
+        assert!(
+            normals.as_ref().is_none_or(|n| n.len() == positions.len()),
+            "Triangle mesh needs exactly one normal per vertex!"
+        );
+        assert!(
+            uvs.as_ref().is_none_or(|uv| uv.len() == positions.len()),
+            "Triangle mesh needs exactly one UV per vertex!"
+        );
+
+        let area_cdf: Vec<f32> = indices
+            .iter()
+            .scan(0.0, |total, &[i0, i1, i2]| {
+                *total += area([positions[i0], positions[i1], positions[i2]]);
+                Some(*total)
+            })
+            .collect();
+
+        let triangle_count = indices.len();
+        let mesh = Arc::new(MeshData {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material,
+            area: *area_cdf.last().unwrap(),
+        });
+
+        let triangles: Vec<Arc<dyn Hittable>> = (0..triangle_count)
+            .map(|index| {
+                Arc::new(MeshTriangle {
+                    mesh: mesh.clone(),
+                    index,
+                }) as Arc<dyn Hittable>
+            })
+            .collect();
+
+        Self {
+            bvh: bvh.build(&triangles, 0.0, 1.0),
+            triangles,
+            area_cdf,
+        }
+    }
+
+    fn total_area(&self) -> f32 {
+        *self.area_cdf.last().unwrap()
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn hit_any(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.bvh.hit_any(ray, t_min, t_max)
+    }
+
+    fn hit_counting(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord<'_>> {
+        self.bvh.hit_counting(ray, t_min, t_max, stats)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.bvh.bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f32 {
+        // Only the closest triangle along the direction can be seen from `origin`.
+        match self
+            .bvh
+            .hit_object(&Ray::new(*origin, *direction, 0.0), 0.001, INFINITY)
+        {
+            Some((_, triangle)) => triangle.pdf_value(origin, direction),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let target = random() * self.total_area();
+        let index = self
+            .area_cdf
+            .partition_point(|&total| total <= target)
+            .min(self.triangles.len() - 1);
+
+        self.triangles[index].random(origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{materials::Lambertian, vec3::Color};
+
+    fn material() -> Lambertian {
+        Lambertian::new(Color::from(0.5))
+    }
+
+    fn down(x: f32, y: f32) -> Ray {
+        Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn triangle_hits_inside_and_misses_outside() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            material(),
+        );
+
+        let rec = triangle
+            .hit(&down(0.25, 0.25), 0.001, f32::INFINITY)
+            .unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-5);
+        assert_near(rec.p, Point3::new(0.25, 0.25, 0.0));
+        assert_near(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+        assert_eq!(rec.uv, (0.25, 0.25));
+
+        assert!(triangle
+            .hit(&down(0.75, 0.75), 0.001, f32::INFINITY)
+            .is_none());
+        assert!(triangle
+            .hit(&down(-0.1, 0.5), 0.001, f32::INFINITY)
+            .is_none());
+        assert!(triangle.hit(&down(0.25, 0.25), 0.001, 0.5).is_none());
+    }
+
+    #[test]
+    fn back_faces_flip_the_normal() {
+        // Clockwise as seen from the ray, so it hits the back.
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            material(),
+        );
+
+        let rec = triangle
+            .hit(&down(0.25, 0.25), 0.001, f32::INFINITY)
+            .unwrap();
+        assert!(!rec.front_face);
+        assert_near(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn normals_and_uvs_are_interpolated() {
+        let tilted = Vec3::unit_vector(Vec3::new(1.0, 0.0, 1.0));
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            material(),
+        )
+        .with_normals([up, tilted, up])
+        .with_uvs([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]);
+
+        let rec = triangle.hit(&down(0.5, 0.0), 0.001, f32::INFINITY).unwrap();
+        assert_near(rec.normal, Vec3::unit_vector(up + tilted));
+        assert_eq!(rec.uv, (0.5, 0.0));
+
+        let rec = triangle.hit(&down(0.0, 0.5), 0.001, f32::INFINITY).unwrap();
+        assert_near(rec.normal, up);
+        assert_eq!(rec.uv, (0.5, 0.5));
+    }
+
+    #[test]
+    fn meshes_share_vertices_between_triangles() {
+        // A unit quad, with UVs that follow the positions.
+        let positions = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ];
+        let uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let indices = vec![[0, 1, 2], [0, 2, 3]];
+        let mesh = TriangleMesh::new(
+            positions,
+            None,
+            Some(uvs),
+            indices,
+            material(),
+            &mut BVHBuilder::default(),
+        );
+
+        for (x, y) in [(0.2, 0.7), (0.7, 0.2), (0.5, 0.5)] {
+            let rec = mesh.hit(&down(x, y), 0.001, f32::INFINITY).unwrap();
+            assert!((rec.t - 1.0).abs() < 1e-5);
+            assert!((rec.uv.0 - x).abs() < 1e-5 && (rec.uv.1 - y).abs() < 1e-5);
+        }
+        assert!(mesh.hit(&down(1.5, 0.5), 0.001, f32::INFINITY).is_none());
+
+        let bounding_box = mesh.bounding_box(0.0, 1.0).unwrap();
+        assert!(bounding_box.minimum.x < 0.0 && bounding_box.maximum.y > 1.0);
+    }
+
+    #[test]
+    fn light_pdfs_use_the_geometric_normal() {
+        let origin = Point3::new(0.5, 0.5, 1.0);
+        let towards = |x: f32, y: f32| Point3::new(x, y, 0.0) - origin;
+        // Straight below `origin` the surfaces are hit at t = 1, so the density is the squared
+        // distance over the cosine and the area, which is |direction|³ / area.
+        let expected = |direction: Vec3, area: f32| direction.length().powi(3) / area;
+
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(0.0, 2.0, 0.0),
+            material(),
+        );
+        let direction = towards(0.3, 0.6);
+        let pdf = triangle.pdf_value(&origin, &direction);
+        assert!((pdf - expected(direction, 2.0)).abs() < 1e-4);
+
+        // A unit quad with the vertex normals tilted well away from the face, which shouldn't
+        // change anything.
+        let tilted = Vec3::unit_vector(Vec3::new(1.0, 1.0, 1.0));
+        let mesh = TriangleMesh::new(
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            Some(vec![tilted; 4]),
+            None,
+            vec![[0, 1, 2], [0, 2, 3]],
+            material(),
+            &mut BVHBuilder::default(),
+        );
+        for (x, y) in [(0.5, 0.5), (0.9, 0.2), (0.1, 0.8)] {
+            let direction = towards(x, y);
+            let pdf = mesh.pdf_value(&origin, &direction);
+            assert!((pdf - expected(direction, 1.0)).abs() < 1e-4);
+        }
+        assert_eq!(mesh.pdf_value(&origin, &towards(1.5, 0.5)), 0.0);
+    }
+}
@@ -1,14 +1,69 @@
-use rand::Rng;
+use std::cell::RefCell;
+
+use crate::sampler::{Sampler, SamplerKind};
 
 pub const INFINITY: f32 = f32::INFINITY;
-pub const PI: f32 = 3.1415926535897932385;
+pub const PI: f32 = std::f32::consts::PI;
 
 pub fn to_radians(degrees: f32) -> f32 {
     degrees * PI / 180.0
 }
 
+thread_local! {
+    /// Where every thread gets its random numbers from. The renderer gives every thread the sampler
+    /// of the render, and starts it at every sample it takes.
+    static SAMPLER: RefCell<Box<dyn Sampler>> =
+        RefCell::new(SamplerKind::Independent.create(rand::random(), 1));
+}
+
+/// The next dimension of the current sample, between 0 (inclusive) and 1 (exclusive).
 pub fn random() -> f32 {
-    rand::thread_rng().gen::<f32>()
+    SAMPLER.with(|sampler| sampler.borrow_mut().next_1d())
+}
+
+/// The next two dimensions of the current sample. Use this rather than calling `random` twice for
+/// anything that picks a point in 2D, like a spot on a light, which the sampler can then spread out
+/// evenly.
+pub fn random_2d() -> (f32, f32) {
+    SAMPLER.with(|sampler| sampler.borrow_mut().next_2d())
+}
+
+/// A random number between `min` (inclusive) and `max` (exclusive).
+pub fn random_range(min: f32, max: f32) -> f32 {
+    min + (max - min) * random()
+}
+
+/// A random index into something of length `len`.
+pub fn random_index(len: usize) -> usize {
+    usize::min((random() * len as f32) as usize, len - 1)
+}
+
+/// Restarts the random numbers of the current thread from `seed`, for anything random that isn't
+/// part of a sample, like the objects of a scene.
+pub fn seed_random(seed: u64) {
+    set_sampler(SamplerKind::Independent.create(seed, 1));
+}
+
+/// Makes `sampler` the source of the random numbers of the current thread.
+pub fn set_sampler(sampler: Box<dyn Sampler>) {
+    SAMPLER.with(|current| *current.borrow_mut() = sampler);
+}
+
+/// Starts sample `index` of the pixel at `pixel` with the sampler of the current thread.
+pub fn start_sample(pixel: (u32, u32), index: u32) {
+    SAMPLER.with(|sampler| sampler.borrow_mut().start_sample(pixel, index));
+}
+
+/// Derives a new seed from `seed` and `value`, e.g. a seed for every pixel from a seed for the
+/// whole image. Uses the SplitMix64 finalizer, so similar inputs give unrelated seeds.
+pub fn mix_seed(seed: u64, value: u64) -> u64 {
+    let mut z = seed
+        ^ value
+            .wrapping_add(0x9e3779b97f4a7c15)
+            .wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
 }
 
 pub fn clamp(x: f32, min: f32, max: f32) -> f32 {
@@ -1,7 +1,6 @@
-use rand::Rng;
-use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub};
 
-use crate::utils::random;
+use crate::utils::{random, random_2d, random_range, PI};
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Vec3 {
@@ -32,9 +31,9 @@ impl Vec3 {
 
     pub fn random_range(min: f32, max: f32) -> Self {
         Self {
-            x: rand::thread_rng().gen_range(min..max),
-            y: rand::thread_rng().gen_range(min..max),
-            z: rand::thread_rng().gen_range(min..max),
+            x: random_range(min, max),
+            y: random_range(min, max),
+            z: random_range(min, max),
         }
     }
 
@@ -48,27 +47,38 @@ impl Vec3 {
         }
     }
 
+    /// A direction picked uniformly over the whole sphere.
     pub fn random_unit_vector() -> Self {
-        Vec3::unit_vector(Vec3::random_in_unit_sphere())
+        let (r1, r2) = random_2d();
+        let z = 1.0 - 2.0 * r1;
+        let r = f32::sqrt(f32::max(1.0 - z * z, 0.0));
+        let phi = 2.0 * PI * r2;
+
+        Vec3::new(r * f32::cos(phi), r * f32::sin(phi), z)
     }
 
+    /// A point picked uniformly in the unit disk in the xy plane. Uses Shirley's concentric
+    /// mapping, which keeps points that are close together in the square close together in the
+    /// disk as well.
     pub fn random_in_unit_disk() -> Self {
-        loop {
-            let p = Vec3::new(
-                rand::thread_rng().gen_range(-1.0..1.0),
-                rand::thread_rng().gen_range(-1.0..1.0),
-                0.0,
-            );
-            if p.length_squared() >= 1.0 {
-                continue;
-            }
-            return p;
+        let (r1, r2) = random_2d();
+        let (a, b) = (2.0 * r1 - 1.0, 2.0 * r2 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::from(0.0);
         }
+
+        let (r, theta) = if f32::abs(a) > f32::abs(b) {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+
+        Vec3::new(r * f32::cos(theta), r * f32::sin(theta), 0.0)
     }
 
     pub fn random_in_hemisphere(normal: &Vec3) -> Self {
         let in_unit_sphere = Vec3::random_in_unit_sphere();
-        if Vec3::dot(&in_unit_sphere, &normal) > 0.0 {
+        if Vec3::dot(&in_unit_sphere, normal) > 0.0 {
             in_unit_sphere
         } else {
             -in_unit_sphere
@@ -88,6 +98,16 @@ impl Vec3 {
         f32::abs(self.x) < s && f32::abs(self.y) < s && f32::abs(self.z) < s
     }
 
+    /// Relative luminance, for a linear color with Rec. 709 primaries.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
+    /// Applies `f` to every component.
+    pub fn map(self, f: impl Fn(f32) -> f32) -> Self {
+        Self::new(f(self.x), f(self.y), f(self.z))
+    }
+
     pub fn dot(u: &Self, v: &Self) -> f32 {
         u.x * v.x + u.y * v.y + u.z * v.z
     }
@@ -117,6 +137,20 @@ impl Vec3 {
     }
 }
 
+// vec[axis]
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Axis out of range!"),
+        }
+    }
+}
+
 // -vec
 impl Neg for Vec3 {
     type Output = Self;
@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::AABB,
+    constant_medium::boundary_interval,
+    density::DensityField,
+    hittable::{HitRecord, Hittable},
+    materials::{Material, ScatterRecord},
+    ray::Ray,
+    utils::random,
+    vec3::{Color, Point3, Vec3},
+};
+
+/// A volume whose density changes throughout space, like clouds or billowing smoke, filling a
+/// closed (convex) boundary. The medium absorbs, scatters and emits light in proportion to its
+/// density.
+///
+/// Distances to collisions are picked with delta tracking and shadow rays are attenuated with ratio
+/// tracking, both of which stay unbiased for any density field.
+pub struct HeterogeneousMedium<M: Material> {
+    boundary: Arc<dyn Hittable>,
+    field: Arc<dyn DensityField>,
+    /// Scattering and absorption per unit of density.
+    sigma_s: f32,
+    sigma_a: f32,
+    phase_function: M,
+    absorber: Absorber,
+}
+
+impl<M: Material> HeterogeneousMedium<M> {
+    /// A medium that only scatters light, by `sigma_s` per unit of density. `phase_function` decides
+    /// in which directions, see `Isotropic`.
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        field: Arc<dyn DensityField>,
+        sigma_s: f32,
+        phase_function: M,
+    ) -> Self {
+        Self {
+            boundary,
+            field,
+            sigma_s,
+            sigma_a: 0.0,
+            phase_function,
+            absorber: Absorber {
+                emission: Color::from(0.0),
+            },
+        }
+    }
+
+    /// Makes the medium absorb `sigma_a` of the light per unit of density as well.
+    pub fn with_absorption(mut self, sigma_a: f32) -> Self {
+        self.sigma_a = sigma_a;
+        self
+    }
+
+    /// Makes the absorbing part of the medium glow with `emission`, like fire. Does nothing without
+    /// absorption.
+    pub fn with_emission(mut self, emission: Color) -> Self {
+        self.absorber.emission = emission;
+        self
+    }
+
+    fn sigma_t(&self) -> f32 {
+        self.sigma_s + self.sigma_a
+    }
+
+    /// The extinction of the densest possible spot, which bounds the extinction everywhere.
+    fn majorant(&self) -> f32 {
+        self.field.max_density() * self.sigma_t()
+    }
+
+    /// Distance (in units of `t`) to the next tentative collision, where the extinction would be
+    /// the majorant everywhere.
+    fn step(&self, majorant: f32, ray_length: f32) -> f32 {
+        -f32::ln(1.0 - random()) / (majorant * ray_length)
+    }
+}
+
+impl<M: Material> Hittable for HeterogeneousMedium<M> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return None;
+        }
+
+        let (t_enter, t_exit) = boundary_interval(&*self.boundary, ray, t_min, t_max)?;
+        let ray_length = ray.direction.length();
+
+        // Delta tracking: step through the medium as if it was as dense as the majorant everywhere,
+        // and throw away the collisions with the made up "null" particles that fill it up.
+        let mut t = t_enter;
+        loop {
+            t += self.step(majorant, ray_length);
+            if t >= t_exit {
+                return None;
+            }
+
+            let density = self.field.density(&ray.at(t));
+            let xi = random() * majorant;
+
+            let mat: &dyn Material = if xi < density * self.sigma_s {
+                &self.phase_function
+            } else if xi < density * self.sigma_t() {
+                &self.absorber
+            } else {
+                continue;
+            };
+
+            return Some(HitRecord {
+                t,
+                p: ray.at(t),
+                uv: (0.0, 0.0),
+                // A volume has no surface, so these are arbitrary.
+                normal: Vec3::new(1.0, 0.0, 0.0),
+                front_face: true,
+                mat,
+                object_id: 0,
+            });
+        }
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return 1.0;
+        }
+
+        let (t_enter, t_exit) = match boundary_interval(&*self.boundary, ray, t_min, t_max) {
+            Some(interval) => interval,
+            None => return 1.0,
+        };
+        let ray_length = ray.direction.length();
+
+        // Ratio tracking: the same steps as delta tracking, but instead of picking what kind of
+        // particle got hit, keep the fraction of light that the null particles let through.
+        let mut transmittance = 1.0;
+        let mut t = t_enter;
+        loop {
+            t += self.step(majorant, ray_length);
+            if t >= t_exit {
+                return transmittance;
+            }
+
+            let density = self.field.density(&ray.at(t));
+            transmittance *= 1.0 - density * self.sigma_t() / majorant;
+        }
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+/// What a path ends on when it gets absorbed by a medium, which is where the medium's emission
+/// comes from. It isn't emissive as far as the lights are concerned, since volumes can't be
+/// sampled as lights.
+struct Absorber {
+    emission: Color,
+}
+
+impl Material for Absorber {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _uv: &(f32, f32), _p: &Point3) -> Color {
+        self.emission
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aarect::Cuboid, density::VoxelGrid, materials::Isotropic};
+
+    const RAYS: usize = 20_000;
+
+    /// A 2 unit cube, with the density going from 0 to 1 along the x axis. Rays along the x axis
+    /// through it pass an optical depth of `sigma_t`.
+    fn gradient(sigma_s: f32, sigma_a: f32) -> HeterogeneousMedium<Isotropic> {
+        let (p0, p1) = (Point3::from(0.0), Point3::from(2.0));
+        let boundary = Arc::new(Cuboid::new(p0, p1, Isotropic::from_color(Color::from(1.0))));
+        let field = Arc::new(VoxelGrid::new([2, 1, 1], vec![0.0, 1.0], p0, p1));
+
+        HeterogeneousMedium::new(
+            boundary,
+            field,
+            sigma_s,
+            Isotropic::from_color(Color::from(1.0)),
+        )
+        .with_absorption(sigma_a)
+        .with_emission(Color::from(1.0))
+    }
+
+    fn through() -> Ray {
+        Ray::new(Point3::new(-1.0, 1.0, 1.0), Vec3::new(1.0, 0.0, 0.0), 0.0)
+    }
+
+    #[test]
+    fn delta_tracking_collides_by_the_optical_depth() {
+        let medium = gradient(0.5, 0.5);
+
+        let mut escaped = 0;
+        let mut absorbed = 0;
+        for _ in 0..RAYS {
+            match medium.hit(&through(), 0.001, f32::INFINITY) {
+                Some(rec) => {
+                    assert!((0.0..=2.0).contains(&rec.p.x));
+                    if rec.mat.emitted(&rec.uv, &rec.p).x > 0.0 {
+                        absorbed += 1;
+                    }
+                }
+                None => escaped += 1,
+            }
+        }
+
+        let escaped = escaped as f32 / RAYS as f32;
+        assert!((escaped - f32::exp(-1.0)).abs() < 0.015);
+        // Half of the collisions absorb the ray.
+        let absorbed = absorbed as f32 / (RAYS as f32 * (1.0 - escaped));
+        assert!((absorbed - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn ratio_tracking_averages_to_the_transmittance() {
+        for (sigma_s, sigma_a) in [(1.0, 0.0), (0.5, 1.5)] {
+            let medium = gradient(sigma_s, sigma_a);
+
+            let total: f32 = (0..RAYS)
+                .map(|_| medium.transmittance(&through(), 0.001, f32::INFINITY))
+                .sum();
+            let expected = f32::exp(-(sigma_s + sigma_a));
+            assert!((total / RAYS as f32 - expected).abs() < 0.01);
+        }
+
+        // Stopping halfway only passes the thin part of the medium, with an optical depth of 1/8.
+        let medium = gradient(1.0, 0.0);
+        let total: f32 = (0..RAYS)
+            .map(|_| medium.transmittance(&through(), 0.001, 2.0))
+            .sum();
+        assert!((total / RAYS as f32 - f32::exp(-0.125)).abs() < 0.01);
+    }
+}